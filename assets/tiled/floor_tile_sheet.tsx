@@ -1,4 +1,70 @@
 <?xml version="1.0" encoding="UTF-8"?>
 <tileset version="1.10" tiledversion="1.10.2" name="floor_tile_sheet" tilewidth="32" tileheight="32" tilecount="64" columns="8">
  <image source="../background/floor_tile_sheet.png" width="256" height="256"/>
+ <tile id="0">
+  <properties>
+   <property name="acceleration" type="float" value="0.9"/>
+   <property name="footsteps" value="dirt"/>
+   <property name="friction" type="float" value="1"/>
+   <property name="impact" value="dust"/>
+   <property name="material" value="dirt"/>
+   <property name="noise" type="float" value="70"/>
+   <property name="speed" type="float" value="0.9"/>
+  </properties>
+ </tile>
+ <tile id="1">
+  <properties>
+   <property name="acceleration" type="float" value="1"/>
+   <property name="footsteps" value="wood"/>
+   <property name="friction" type="float" value="0.8"/>
+   <property name="impact" value="splinters"/>
+   <property name="material" value="wood"/>
+   <property name="noise" type="float" value="110"/>
+   <property name="speed" type="float" value="1"/>
+  </properties>
+ </tile>
+ <tile id="2">
+  <properties>
+   <property name="acceleration" type="float" value="1"/>
+   <property name="footsteps" value="grass"/>
+   <property name="friction" type="float" value="1.2"/>
+   <property name="impact" value="leaves"/>
+   <property name="material" value="grass"/>
+   <property name="noise" type="float" value="50"/>
+   <property name="speed" type="float" value="0.95"/>
+  </properties>
+ </tile>
+ <tile id="5">
+  <properties>
+   <property name="acceleration" type="float" value="0.9"/>
+   <property name="footsteps" value="dirt"/>
+   <property name="friction" type="float" value="1"/>
+   <property name="impact" value="dust"/>
+   <property name="material" value="dirt"/>
+   <property name="noise" type="float" value="70"/>
+   <property name="speed" type="float" value="0.9"/>
+  </properties>
+ </tile>
+ <tile id="6">
+  <properties>
+   <property name="acceleration" type="float" value="0.9"/>
+   <property name="footsteps" value="dirt"/>
+   <property name="friction" type="float" value="1"/>
+   <property name="impact" value="dust"/>
+   <property name="material" value="dirt"/>
+   <property name="noise" type="float" value="70"/>
+   <property name="speed" type="float" value="0.9"/>
+  </properties>
+ </tile>
+ <tile id="7">
+  <properties>
+   <property name="acceleration" type="float" value="0.9"/>
+   <property name="footsteps" value="dirt"/>
+   <property name="friction" type="float" value="1"/>
+   <property name="impact" value="dust"/>
+   <property name="material" value="dirt"/>
+   <property name="noise" type="float" value="70"/>
+   <property name="speed" type="float" value="0.9"/>
+  </properties>
+ </tile>
 </tileset>
@@ -11,6 +11,8 @@ uniform float lightsRadius[MAX_LIGHTS];
 uniform float lightsRotation[MAX_LIGHTS]; // Cone angle for cone lights
 uniform int lightsType[MAX_LIGHTS];
 uniform float lightsAngle[MAX_LIGHTS];
+// Red where the player can see, green where they have seen before
+uniform sampler2D fogMask;
 
 const int RADIAL_LIGHT = 0;
 const int AMBIENT_LIGHT = 1;
@@ -73,6 +75,16 @@ void main() {
             color_gradient += cur_gradient * lightsColor[i].rgb * lightAlpha * cone_factor;
         }
     }
+    vec4 lit_color = color * vec4(color_gradient, 1.0);
+
+    // Places seen before but out of sight are shown darker and washed out, places never seen
+    // are hidden
+    vec4 fog = texture(fogMask, uv);
+    if (fog.r < 0.5) {
+        float gray = dot(lit_color.rgb, vec3(0.299, 0.587, 0.114));
+        lit_color.rgb = mix(vec3(gray), lit_color.rgb, 0.25) * 0.5 * fog.g;
+    }
+
     // Output the final color with the original alpha
-    gl_FragColor = color * vec4(color_gradient, 1.0);
+    gl_FragColor = lit_color;
 }
@@ -0,0 +1,474 @@
+use crate::{TileRotation, Wall, WallVarient};
+use std::collections::HashSet;
+
+// A direction between tiles, or the side of a tile
+type Side = (i32, i32);
+const UP: Side = (0, -1);
+const DOWN: Side = (0, 1);
+const LEFT: Side = (-1, 0);
+const RIGHT: Side = (1, 0);
+const SIDES: [Side; 4] = [UP, DOWN, LEFT, RIGHT];
+
+// How far away other walls are looked at when it isn't clear which side of a wall is outside
+const SIDE_SEARCH_RADIUS: i32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WallStyle {
+    Plain,
+    White,
+}
+
+impl WallStyle {
+    pub fn from_varient(varient: WallVarient) -> WallStyle {
+        match varient {
+            WallVarient::Staight | WallVarient::Elbow | WallVarient::TinyElbow => WallStyle::Plain,
+            _ => WallStyle::White,
+        }
+    }
+}
+
+// Shape of a wall, described by the sides of the tile its wall strips are on. Walls sit on
+// the outside edge of the tiles they are on, so a rooms walls are on the rooms border tiles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WallShape {
+    Straight(Side),
+    // Outside corner, strips on a vertical and a horizontal side
+    Elbow(Side, Side),
+    // Inside corner, a small block in the corner between a vertical and a horizontal side
+    TinyElbow(Side, Side),
+    Pillar,
+}
+
+impl WallShape {
+    pub fn from_wall(wall: &Wall) -> WallShape {
+        let corner_sides = match wall.rotation {
+            TileRotation::Two => (UP, LEFT),
+            TileRotation::Three => (UP, RIGHT),
+            TileRotation::One => (DOWN, RIGHT),
+            TileRotation::None => (DOWN, LEFT),
+        };
+        match wall.varient {
+            WallVarient::Staight | WallVarient::WhiteStraight => {
+                WallShape::Straight(match wall.rotation {
+                    TileRotation::Two => UP,
+                    TileRotation::One => DOWN,
+                    TileRotation::None => LEFT,
+                    TileRotation::Three => RIGHT,
+                })
+            }
+            WallVarient::Elbow | WallVarient::WhiteElbow => {
+                WallShape::Elbow(corner_sides.0, corner_sides.1)
+            }
+            WallVarient::TinyElbow | WallVarient::WhiteTinyElbow => {
+                WallShape::TinyElbow(corner_sides.0, corner_sides.1)
+            }
+            WallVarient::WhitePillar => WallShape::Pillar,
+        }
+    }
+
+    // Sides of the tile the wall is on
+    fn get_sides(&self) -> Vec<Side> {
+        match self {
+            WallShape::Straight(side) => vec![*side],
+            WallShape::Elbow(side, other_side) | WallShape::TinyElbow(side, other_side) => {
+                vec![*side, *other_side]
+            }
+            WallShape::Pillar => vec![],
+        }
+    }
+
+    pub fn to_wall(self, style: WallStyle) -> Wall {
+        let white = style == WallStyle::White;
+        let (varient, rotation) = match self {
+            WallShape::Straight(side) => (
+                if white {
+                    WallVarient::WhiteStraight
+                } else {
+                    WallVarient::Staight
+                },
+                match side {
+                    UP => TileRotation::Two,
+                    DOWN => TileRotation::One,
+                    LEFT => TileRotation::None,
+                    _ => TileRotation::Three,
+                },
+            ),
+            WallShape::Elbow(side, other_side) => (
+                if white {
+                    WallVarient::WhiteElbow
+                } else {
+                    WallVarient::Elbow
+                },
+                get_corner_rotation(side, other_side),
+            ),
+            WallShape::TinyElbow(side, other_side) => (
+                if white {
+                    WallVarient::WhiteTinyElbow
+                } else {
+                    WallVarient::TinyElbow
+                },
+                get_corner_rotation(side, other_side),
+            ),
+            // There is only a white pillar
+            WallShape::Pillar => (WallVarient::WhitePillar, TileRotation::None),
+        };
+        Wall { varient, rotation }
+    }
+}
+
+// Works out the shape of every wall in a mask of which tiles have walls, indexed [y][x].
+//
+// Connected walls are followed as chains and every wall in a chain gets its strips on the
+// same side of the chain. The side is picked so that the chain has more outside corners than
+// inside corners, which puts the strips on the outside of rooms even when doors break them
+// up. Chains without corners use the walls around them instead. Walls where 3 or 4 walls meet
+// can't be drawn with the tile sheet and become straights.
+pub fn get_wall_shapes(mask: &[Vec<bool>]) -> Vec<Vec<Option<WallShape>>> {
+    get_wall_shapes_keeping_sides(mask, &[])
+}
+
+// Same as get_wall_shapes, but chains with walls that already have a shape keep their walls
+// on the same side. Used when changing walls on an existing map so that only the shapes
+// around the change are updated.
+pub fn get_wall_shapes_keeping_sides(
+    mask: &[Vec<bool>],
+    existing: &[Vec<Option<WallShape>>],
+) -> Vec<Vec<Option<WallShape>>> {
+    let height = mask.len() as i32;
+    let width = mask.first().map_or(0, |line| line.len()) as i32;
+    let is_wall = |x: i32, y: i32| {
+        x >= 0 && y >= 0 && x < width && y < height && mask[y as usize][x as usize]
+    };
+    let get_neighbours = |x: i32, y: i32| {
+        SIDES
+            .into_iter()
+            .filter(|(dir_x, dir_y)| is_wall(x + dir_x, y + dir_y))
+            .collect::<Vec<Side>>()
+    };
+    // Junctions end chains
+    let in_chain = |x: i32, y: i32| is_wall(x, y) && get_neighbours(x, y).len() <= 2;
+    let get_chain_links = |(x, y): (i32, i32)| {
+        get_neighbours(x, y)
+            .into_iter()
+            .filter(|(dir_x, dir_y)| in_chain(x + dir_x, y + dir_y))
+            .collect::<Vec<Side>>()
+    };
+    // Follows a chain from a tile, not going back to where it came from. Stops at the end of
+    // the chain or when getting back to the start of a loop.
+    let walk = |start: (i32, i32)| {
+        let mut tiles = vec![start];
+        let mut previous = None;
+        let mut current = start;
+        loop {
+            let next = get_chain_links(current)
+                .into_iter()
+                .map(|(dir_x, dir_y)| (current.0 + dir_x, current.1 + dir_y))
+                .find(|next| Some(*next) != previous);
+            match next {
+                Some(next) if next != start => {
+                    previous = Some(current);
+                    current = next;
+                    tiles.push(next);
+                }
+                _ => return (tiles, next == Some(start)),
+            }
+        }
+    };
+
+    let mut shapes = vec![vec![None; width as usize]; height as usize];
+    let mut visited = HashSet::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !in_chain(x, y) || visited.contains(&(x, y)) {
+                continue;
+            }
+            // Find one end of the chain, then walk it from there
+            let (tiles, is_loop) = walk((x, y));
+            let (tiles, is_loop) = if is_loop {
+                (tiles, is_loop)
+            } else {
+                walk(*tiles.last().unwrap())
+            };
+            visited.extend(tiles.iter().copied());
+
+            let directions = get_chain_directions(&tiles, is_loop, get_neighbours);
+            let existing_sides = tiles
+                .iter()
+                .map(|(x, y)| {
+                    existing
+                        .get(*y as usize)
+                        .and_then(|line| line.get(*x as usize))
+                        .and_then(|shape| shape.map(|shape| shape.get_sides()))
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<Vec<Side>>>();
+            let outside_is_left = pick_outside_side(&tiles, &directions, &existing_sides, is_wall);
+            for ((x, y), (dir_in, dir_out)) in tiles.into_iter().zip(directions) {
+                let get_normal = |(dir_x, dir_y): Side| {
+                    if outside_is_left {
+                        (dir_y, -dir_x)
+                    } else {
+                        (-dir_y, dir_x)
+                    }
+                };
+                shapes[y as usize][x as usize] = Some(match (dir_in, dir_out) {
+                    (None, None) => WallShape::Pillar,
+                    (Some(dir), None) | (None, Some(dir)) => WallShape::Straight(get_normal(dir)),
+                    (Some(dir_in), Some(dir_out)) if dir_in == dir_out => {
+                        WallShape::Straight(get_normal(dir_in))
+                    }
+                    (Some(dir_in), Some(dir_out)) => {
+                        let sides = (get_normal(dir_in), get_normal(dir_out));
+                        // Turning away from the outside goes around an outside corner
+                        if is_right_turn(dir_in, dir_out) == outside_is_left {
+                            WallShape::Elbow(sides.0, sides.1)
+                        } else {
+                            WallShape::TinyElbow(sides.0, sides.1)
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    // Junctions become straights along the wall going through them, facing away from the
+    // wall branching off
+    for y in 0..height {
+        for x in 0..width {
+            let neighbours = get_neighbours(x, y);
+            if !is_wall(x, y) || neighbours.len() <= 2 {
+                continue;
+            }
+            let side = if neighbours.contains(&LEFT) && neighbours.contains(&RIGHT) {
+                if neighbours.contains(&UP) && !neighbours.contains(&DOWN) {
+                    DOWN
+                } else {
+                    UP
+                }
+            } else if neighbours.contains(&LEFT) {
+                RIGHT
+            } else {
+                LEFT
+            };
+            shapes[y as usize][x as usize] = Some(WallShape::Straight(side));
+        }
+    }
+    shapes
+}
+
+// Direction walked into and out of each tile of a chain. Walls next to a junction still
+// connect to it.
+fn get_chain_directions(
+    tiles: &[(i32, i32)],
+    is_loop: bool,
+    get_neighbours: impl Fn(i32, i32) -> Vec<Side>,
+) -> Vec<(Option<Side>, Option<Side>)> {
+    let get_dir = |from: (i32, i32), to: (i32, i32)| (to.0 - from.0, to.1 - from.1);
+    tiles
+        .iter()
+        .enumerate()
+        .map(|(i, tile)| {
+            let previous = if i > 0 {
+                Some(tiles[i - 1])
+            } else if is_loop {
+                tiles.last().copied()
+            } else {
+                None
+            };
+            let next = if i + 1 < tiles.len() {
+                Some(tiles[i + 1])
+            } else if is_loop {
+                tiles.first().copied()
+            } else {
+                None
+            };
+            let mut dir_in = previous.map(|previous| get_dir(previous, *tile));
+            let mut dir_out = next.map(|next| get_dir(*tile, next));
+            // Connect to any wall that isn't part of the chain
+            for (dir_x, dir_y) in get_neighbours(tile.0, tile.1) {
+                if Some((-dir_x, -dir_y)) != dir_in && Some((dir_x, dir_y)) != dir_out {
+                    if dir_in.is_none() {
+                        dir_in = Some((-dir_x, -dir_y));
+                    } else if dir_out.is_none() {
+                        dir_out = Some((dir_x, dir_y));
+                    }
+                }
+            }
+            (dir_in, dir_out)
+        })
+        .collect()
+}
+
+// Returns true if the outside of a chain is on the left of the direction it was walked in
+fn pick_outside_side(
+    tiles: &[(i32, i32)],
+    directions: &[(Option<Side>, Option<Side>)],
+    existing_sides: &[Vec<Side>],
+    is_wall: impl Fn(i32, i32) -> bool,
+) -> bool {
+    // Walls that are already placed with the outside on the left minus on the right
+    let existing_score = directions
+        .iter()
+        .zip(existing_sides)
+        .map(|(directions, sides)| match directions.1.or(directions.0) {
+            Some((dir_x, dir_y)) if sides.contains(&(dir_y, -dir_x)) => 1,
+            Some((dir_x, dir_y)) if sides.contains(&(-dir_y, dir_x)) => -1,
+            _ => 0,
+        })
+        .sum::<i32>();
+    if existing_score != 0 {
+        return existing_score > 0;
+    }
+
+    // Outside corners minus inside corners with the outside on the left
+    let corner_score = directions
+        .iter()
+        .map(|directions| match directions {
+            (Some(dir_in), Some(dir_out)) if dir_in != dir_out => {
+                if is_right_turn(*dir_in, *dir_out) {
+                    1
+                } else {
+                    -1
+                }
+            }
+            _ => 0,
+        })
+        .sum::<i32>();
+    if corner_score != 0 {
+        return corner_score > 0;
+    }
+
+    // The inside is the side with more walls close by
+    let chain = tiles.iter().copied().collect::<HashSet<(i32, i32)>>();
+    let mut left_score = 0.0;
+    for ((x, y), directions) in tiles.iter().zip(directions) {
+        let Some((dir_x, dir_y)) = directions.1.or(directions.0) else {
+            continue;
+        };
+        let left = (dir_y, -dir_x);
+        for other_y in y - SIDE_SEARCH_RADIUS..=y + SIDE_SEARCH_RADIUS {
+            for other_x in x - SIDE_SEARCH_RADIUS..=x + SIDE_SEARCH_RADIUS {
+                if !is_wall(other_x, other_y) || chain.contains(&(other_x, other_y)) {
+                    continue;
+                }
+                let offset = (other_x - x, other_y - y);
+                let distance_squared = (offset.0.pow(2) + offset.1.pow(2)) as f32;
+                left_score += (offset.0 * left.0 + offset.1 * left.1) as f32 / distance_squared;
+            }
+        }
+    }
+    if left_score != 0.0 {
+        return left_score < 0.0;
+    }
+
+    // Nothing to go by, face up or left
+    let first = directions[0].1.or(directions[0].0).unwrap_or(RIGHT);
+    matches!((first.1, -first.0), UP | LEFT)
+}
+
+// Screen y goes down, so a positive cross product is a clockwise turn
+fn is_right_turn(dir_in: Side, dir_out: Side) -> bool {
+    dir_in.0 * dir_out.1 - dir_in.1 * dir_out.0 > 0
+}
+
+fn get_corner_rotation(side: Side, other_side: Side) -> TileRotation {
+    let (vertical, horizontal) = if side.0 == 0 {
+        (side, other_side)
+    } else {
+        (other_side, side)
+    };
+    match (vertical, horizontal) {
+        (UP, LEFT) => TileRotation::Two,
+        (UP, RIGHT) => TileRotation::Three,
+        (DOWN, RIGHT) => TileRotation::One,
+        _ => TileRotation::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mask from rows of text, # is a wall
+    fn mask(rows: &[&str]) -> Vec<Vec<bool>> {
+        rows.iter()
+            .map(|row| row.chars().map(|tile| tile == '#').collect())
+            .collect()
+    }
+
+    // Varient and rotation flags of the wall picked for a tile, in a style
+    fn get_wall(rows: &[&str], (x, y): (usize, usize), style: WallStyle) -> (WallVarient, u32) {
+        let wall = get_wall_shapes(&mask(rows))[y][x]
+            .expect("Tile should have a wall")
+            .to_wall(style);
+        (wall.varient, wall.rotation.to_raw_flags())
+    }
+
+    // Checks the wall picked for a tile in both styles
+    fn assert_walls(
+        rows: &[&str],
+        tile: (usize, usize),
+        (plain, white): (WallVarient, WallVarient),
+        rotation: TileRotation,
+    ) {
+        let rotation = rotation.to_raw_flags();
+        assert_eq!(get_wall(rows, tile, WallStyle::Plain), (plain, rotation));
+        assert_eq!(get_wall(rows, tile, WallStyle::White), (white, rotation));
+    }
+
+    const STRAIGHT: (WallVarient, WallVarient) = (WallVarient::Staight, WallVarient::WhiteStraight);
+    const ELBOW: (WallVarient, WallVarient) = (WallVarient::Elbow, WallVarient::WhiteElbow);
+    const TINY_ELBOW: (WallVarient, WallVarient) =
+        (WallVarient::TinyElbow, WallVarient::WhiteTinyElbow);
+
+    #[test]
+    fn straight_run_faces_up() {
+        let rows = ["......", ".####.", "......"];
+        for x in 1..5 {
+            assert_walls(&rows, (x, 1), STRAIGHT, TileRotation::Two);
+        }
+    }
+
+    #[test]
+    fn room_has_elbows_on_its_corners() {
+        let rows = ["......", ".####.", ".#..#.", ".#..#.", ".####.", "......"];
+        assert_walls(&rows, (1, 1), ELBOW, TileRotation::Two);
+        assert_walls(&rows, (4, 1), ELBOW, TileRotation::Three);
+        assert_walls(&rows, (4, 4), ELBOW, TileRotation::One);
+        assert_walls(&rows, (1, 4), ELBOW, TileRotation::None);
+        // Sides have their strips on the outside of the room
+        assert_walls(&rows, (2, 1), STRAIGHT, TileRotation::Two);
+        assert_walls(&rows, (4, 2), STRAIGHT, TileRotation::Three);
+        assert_walls(&rows, (2, 4), STRAIGHT, TileRotation::One);
+        assert_walls(&rows, (1, 2), STRAIGHT, TileRotation::None);
+    }
+
+    #[test]
+    fn inside_corner_of_room_is_tiny_elbow() {
+        // L shaped room, the corner at (3, 2) points into it
+        let rows = ["####...", "#..#...", "#..####", "#.....#", "#######"];
+        assert_walls(&rows, (3, 2), TINY_ELBOW, TileRotation::Three);
+        assert_walls(&rows, (6, 2), ELBOW, TileRotation::Three);
+    }
+
+    #[test]
+    fn junctions_face_away_from_branch() {
+        let t_down = ["###", ".#."];
+        assert_walls(&t_down, (1, 0), STRAIGHT, TileRotation::Two);
+        let t_up = [".#.", "###"];
+        assert_walls(&t_up, (1, 1), STRAIGHT, TileRotation::One);
+        let t_right = ["#.", "##", "#."];
+        assert_walls(&t_right, (0, 1), STRAIGHT, TileRotation::None);
+        let cross = [".#.", "###", ".#."];
+        assert_walls(&cross, (1, 1), STRAIGHT, TileRotation::Two);
+    }
+
+    #[test]
+    fn lone_wall_is_pillar() {
+        let rows = ["...", ".#.", "..."];
+        assert_eq!(get_wall_shapes(&mask(&rows))[1][1], Some(WallShape::Pillar));
+        // There is only a white pillar, so plain walls use it too
+        let pillar = (WallVarient::WhitePillar, WallVarient::WhitePillar);
+        assert_walls(&rows, (1, 1), pillar, TileRotation::None);
+    }
+}
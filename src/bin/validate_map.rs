@@ -0,0 +1,27 @@
+use lighting::map_validator::validate_map;
+
+// Checks map files for problems without starting the game, exits with 1 if any map has problems
+//     cargo run --bin validate_map -- assets/maps/map0.tmx assets/maps/interior0.tmx
+fn main() {
+    let paths = std::env::args().skip(1).collect::<Vec<String>>();
+    if paths.is_empty() {
+        eprintln!("Usage: validate_map <map.tmx>...");
+        std::process::exit(2);
+    }
+
+    let mut problem_count = 0;
+    for path in paths.iter() {
+        let problems = validate_map(path);
+        for problem in problems.iter() {
+            println!("{path}: {problem}");
+        }
+        if problems.is_empty() {
+            println!("{path}: ok");
+        }
+        problem_count += problems.len();
+    }
+    if problem_count > 0 {
+        println!("Found {problem_count} problems");
+        std::process::exit(1);
+    }
+}
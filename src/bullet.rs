@@ -1,26 +1,88 @@
-use rand::Rng;
 use raylib::prelude::*;
 
-use crate::{Collider, Line, WorldMap, TILE_SIZE};
+use crate::{auto_tile::WallStyle, Collider, Line, WorldMap};
+
+// Deepest wall a bullet can go through, in world units
+const MAX_PENETRATION_DEPTH: f32 = 16.0;
+// Distance stepped through a wall while looking for its other side
+const PENETRATION_STEP: f32 = 1.0;
+
+// What a bullet is, used to work out how much it has left after hitting something
+#[derive(Clone, Copy, Debug)]
+pub struct Caliber {
+    // In kg, speed is in world units a second so energy is only meaningful between bullets
+    pub mass: f32,
+    pub muzzle_speed: f32,
+    // How well the bullet goes through walls, higher is better
+    pub penetration: f32,
+}
+
+impl Caliber {
+    pub const RIFLE: Caliber = Caliber {
+        mass: 0.004,
+        muzzle_speed: 1000.0,
+        penetration: 1.0,
+    };
+    pub const PISTOL: Caliber = Caliber {
+        mass: 0.008,
+        muzzle_speed: 800.0,
+        penetration: 0.4,
+    };
+    pub const SHRAPNEL: Caliber = Caliber {
+        mass: 0.002,
+        muzzle_speed: 500.0,
+        penetration: 0.2,
+    };
+}
+
+// How a wall reacts to bullets hitting it
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceBallistics {
+    // Bullets hitting closer to the surface than this, in degrees, ricochet off it
+    pub max_ricochet_angle: f32,
+    // Part of the speed kept when ricocheting at the shallowest angle
+    pub ricochet_speed_kept: f32,
+    // Energy it takes to go through one world unit of the wall
+    pub penetration_cost: f32,
+}
+
+impl SurfaceBallistics {
+    pub fn from_material(material: WallStyle) -> SurfaceBallistics {
+        match material {
+            WallStyle::Plain => SurfaceBallistics {
+                max_ricochet_angle: 20.0,
+                ricochet_speed_kept: 0.5,
+                penetration_cost: 120.0,
+            },
+            WallStyle::White => SurfaceBallistics {
+                max_ricochet_angle: 30.0,
+                ricochet_speed_kept: 0.7,
+                penetration_cost: 300.0,
+            },
+        }
+    }
+}
 
 pub struct Bullet {
     pub pos_history: [Vector2; 3],
     pub pos: Vector2,
     pub vel: Vector2,
+    pub caliber: Caliber,
     pub collided: Option<Vector2>,
     pub dbg_line_hit: Option<Line>,
     pub drag: f32,
 }
 
 impl Bullet {
-    pub fn new(pos: Vector2, vel: Vector2) -> Bullet {
+    pub fn new(pos: Vector2, vel: Vector2, caliber: Caliber) -> Bullet {
         Bullet {
             pos,
             vel,
+            caliber,
             pos_history: [pos; 3],
             dbg_line_hit: None,
             collided: None,
-            drag: 12.0,
+            drag: 720.0,
         }
     }
 
@@ -30,89 +92,163 @@ impl Bullet {
         self.pos_history[0] = self.pos;
     }
 
-    pub fn update(&mut self, rl: &RaylibHandle, world_map: &WorldMap) {
+    pub fn update(&mut self, world_map: &WorldMap, dt: f32) {
         self.collided = None;
         self.dbg_line_hit = None;
         self.update_history();
 
-        self.vel -= self.vel.normalized() * self.drag * rl.get_frame_time() * 60.0;
+        self.vel -= self.vel.normalized() * self.drag * dt;
         if self.vel.length() <= 20.0 {
             self.vel = Vector2::zero();
         }
-        self.handle_collisions(rl, world_map);
+        self.handle_collisions(world_map, dt);
         if self.collided.is_none() {
-            self.pos += self.vel * rl.get_frame_time();
+            self.pos += self.vel * dt;
         }
     }
 
-    pub fn handle_collisions(&mut self, rl: &RaylibHandle, world_map: &WorldMap) {
-        let frame_time = rl.get_frame_time();
-        let min_velocity_lost = 0.3;
+    pub fn get_energy(&self) -> f32 {
+        0.5 * self.caliber.mass * self.vel.length_sqr()
+    }
 
-        // Calculate bullet lines
-        let bullet_y_line = Line {
-            start: self.pos,
-            end: self.pos + self.vel * Vector2::new(0.0, 1.0) * frame_time,
-        };
-        let bullet_line = Line {
-            start: self.pos,
-            end: self.pos + self.vel * frame_time,
+    pub fn handle_collisions(&mut self, world_map: &WorldMap, dt: f32) {
+        let Some(hit) = world_map.raycast(self.pos, self.pos + self.vel * dt) else {
+            return;
         };
+        self.collided = Some(hit.normal);
+        self.dbg_line_hit = Some(Line {
+            start: hit.point,
+            end: hit.point + hit.normal * 8.0,
+        });
 
-        // Get all normals from every collision
-        let mut normals = Vec::new();
-        for (y, line) in world_map.walls.iter().enumerate() {
-            for (x, wall) in line.iter().enumerate() {
-                if let Some(wall) = wall {
-                    for rect in wall
-                        .get_collider()
-                        .with_pos(Vector2::new(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE))
-                        .rects
-                    {
-                        let lines = Line::from_rect(&rect);
-                        for line in lines {
-                            // Check for collision
-                            if let Some(intersection) = line.intersection(&bullet_line) {
-                                let velocity_lost =
-                                    rand::thread_rng().gen_range(0.0..=min_velocity_lost);
-                                let normal = if line.intersection(&bullet_y_line).is_some() {
-                                    Vector2::new(velocity_lost, -velocity_lost)
-                                } else {
-                                    Vector2::new(-velocity_lost, velocity_lost)
-                                };
-                                normals.push((intersection, normal, line));
-                            }
-                        }
-                    }
-                }
-            }
+        let surface = SurfaceBallistics::from_material(hit.material);
+        let dir = self.vel.normalized();
+        // Angle between the bullet and the surface, 0 when grazing it and 90 when head on
+        let angle = (-dir.dot(hit.normal)).clamp(0.0, 1.0).asin().to_degrees();
+        if angle <= surface.max_ricochet_angle {
+            // Bounce off the surface, losing more speed the steeper the hit
+            let speed_kept =
+                surface.ricochet_speed_kept * (1.0 - 0.5 * angle / surface.max_ricochet_angle);
+            self.vel = (self.vel - hit.normal * 2.0 * self.vel.dot(hit.normal)) * speed_kept;
+            // Moved just off the surface so the next raycast doesn't start inside the wall
+            self.pos = hit.point + hit.normal;
+            return;
         }
 
-        // Find closest normal
-        if let Some((closest_index, _)) =
-            normals
-                .iter()
-                .enumerate()
-                .min_by(|(_, normal1), (_, normal2)| {
-                    normal1.0.length().partial_cmp(&normal2.0.length()).unwrap()
-                })
-        {
-            let (intersection, normal, line) = &normals[closest_index];
-            self.collided = Some(*normal);
-            self.vel *= *normal;
-            self.pos = *intersection + self.vel.normalized() * -(f32::EPSILON - 1.0);
-            self.dbg_line_hit = Some(line.clone());
+        // Steep hits go through the wall if it is thin enough and the bullet has the energy,
+        // otherwise they stop in it
+        if let Some(exit) = get_wall_exit(world_map, hit.point, dir) {
+            let thickness = hit.point.distance_to(exit);
+            let energy_left =
+                self.get_energy() - thickness * surface.penetration_cost / self.caliber.penetration;
+            if energy_left > 0.0 {
+                self.vel = dir * (2.0 * energy_left / self.caliber.mass).sqrt();
+                self.pos = exit + dir;
+                self.dbg_line_hit = Some(Line {
+                    start: hit.point,
+                    end: exit,
+                });
+                return;
+            }
         }
+        self.vel = Vector2::zero();
+        self.pos = hit.point + hit.normal;
+    }
+
+    // Where the bullet is drawn, between where it was last tick and where it is now
+    pub fn get_render_pos(&self, alpha: f32) -> Vector2 {
+        self.pos_history[0].lerp(self.pos, alpha)
     }
 
     pub fn get_collider(&self) -> Collider {
-        Collider {
-            rects: vec![Rectangle {
-                x: self.pos.x,
-                y: self.pos.y,
-                width: 1.0,
-                height: 1.0,
-            }],
+        Collider::from_rects(vec![Rectangle {
+            x: self.pos.x,
+            y: self.pos.y,
+            width: 1.0,
+            height: 1.0,
+        }])
+    }
+}
+
+// Where a line going into a wall at a point comes out of it on the other side, None if the wall
+// is deeper than bullets can go through
+fn get_wall_exit(world_map: &WorldMap, entry: Vector2, dir: Vector2) -> Option<Vector2> {
+    // Rays starting inside a wall hit it straight away
+    let is_inside = |pos: Vector2| {
+        world_map
+            .raycast(pos, pos + dir)
+            .is_some_and(|hit| hit.distance == 0.0)
+    };
+    let mut depth = PENETRATION_STEP;
+    while is_inside(entry + dir * depth) {
+        depth += PENETRATION_STEP;
+        if depth > MAX_PENETRATION_DEPTH {
+            return None;
         }
     }
+    // Found a point past the wall, the exact side it came out of is found by going back in
+    world_map
+        .raycast(entry + dir * depth, entry)
+        .map(|hit| hit.point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{world_map::test_map, TileRotation, WallVarient};
+
+    // Plain wall strip from x 64 to 74, 10 units thick
+    fn map_with_wall() -> WorldMap {
+        test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::Staight, TileRotation::None)],
+        )
+    }
+
+    #[test]
+    fn shallow_hit_ricochets_off_wall() {
+        let map = map_with_wall();
+        let vel = Vector2::new(10.0, 1000.0);
+        let mut bullet = Bullet::new(Vector2::new(63.9, 70.0), vel, Caliber::RIFLE);
+        bullet.handle_collisions(&map, 1.0 / 60.0);
+
+        // Reflected about the wall's normal, (-1, 0)
+        let reflected = Vector2::new(-vel.x, vel.y).normalized();
+        assert!(bullet.vel.normalized().distance_to(reflected) < 0.001);
+        // Grazing hits keep close to all of the speed the surface lets a ricochet keep
+        let kept = bullet.vel.length() / vel.length();
+        let ricochet_speed_kept =
+            SurfaceBallistics::from_material(WallStyle::Plain).ricochet_speed_kept;
+        assert!(kept <= ricochet_speed_kept);
+        assert!(kept > ricochet_speed_kept * 0.98);
+        assert!(bullet.pos.x < 64.0);
+    }
+
+    #[test]
+    fn rifle_goes_through_thin_wall() {
+        let map = map_with_wall();
+        let mut bullet = Bullet::new(
+            Vector2::new(50.0, 80.0),
+            Vector2::new(Caliber::RIFLE.muzzle_speed, 0.0),
+            Caliber::RIFLE,
+        );
+        bullet.handle_collisions(&map, 1.0 / 60.0);
+        assert!(bullet.pos.x > 74.0);
+        assert!(bullet.vel.x > 0.0 && bullet.vel.x < Caliber::RIFLE.muzzle_speed);
+        assert_eq!(bullet.vel.y, 0.0);
+    }
+
+    #[test]
+    fn pistol_stops_in_thin_wall() {
+        let map = map_with_wall();
+        let mut bullet = Bullet::new(
+            Vector2::new(55.0, 80.0),
+            Vector2::new(Caliber::PISTOL.muzzle_speed, 0.0),
+            Caliber::PISTOL,
+        );
+        bullet.handle_collisions(&map, 1.0 / 60.0);
+        assert_eq!(bullet.vel, Vector2::zero());
+        assert!(bullet.pos.x < 64.0);
+    }
 }
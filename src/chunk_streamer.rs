@@ -0,0 +1,173 @@
+use crate::{
+    world_map::{
+        read_map_header, skip_lines, try_parse_ground, try_parse_wall, MapChunk, CHUNK_SIZE,
+    },
+    Ground, Wall, WallVarient,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+// Position of a chunk and the chunk, or what is wrong with its tiles
+pub type LoadedChunk = ((u32, u32), Result<MapChunk, String>);
+
+// Loads chunks of a map file on a background thread
+pub struct ChunkStreamer {
+    requests: Sender<(u32, u32)>,
+    loaded: Receiver<LoadedChunk>,
+    pending: HashSet<(u32, u32)>,
+}
+
+impl ChunkStreamer {
+    pub fn new(path: &str, map_width: u32, map_height: u32) -> ChunkStreamer {
+        let (request_sender, request_receiver) = channel();
+        let (chunk_sender, chunk_receiver) = channel();
+        let path = path.to_string();
+
+        thread::spawn(move || {
+            let mut reader = BufReader::new(std::fs::File::open(&path).unwrap());
+            let line_offsets = index_layer_lines(&mut reader, map_height);
+
+            // Stops once the map is dropped and there is nothing left to load
+            for chunk_pos in request_receiver.iter() {
+                let chunk = read_chunk(&mut reader, &line_offsets, chunk_pos, map_width);
+                if chunk_sender.send((chunk_pos, chunk)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        ChunkStreamer {
+            requests: request_sender,
+            loaded: chunk_receiver,
+            pending: HashSet::new(),
+        }
+    }
+
+    // Queues a chunk to be loaded, unless it already is queued
+    pub fn request(&mut self, chunk_pos: (u32, u32)) {
+        if self.pending.insert(chunk_pos) {
+            self.requests
+                .send(chunk_pos)
+                .expect("Map streaming thread stopped");
+        }
+    }
+
+    // Returns every chunk that finished loading since the last call
+    pub fn take_loaded(&mut self) -> Vec<LoadedChunk> {
+        let loaded = self.loaded.try_iter().collect::<Vec<_>>();
+        for (chunk_pos, _) in loaded.iter() {
+            self.pending.remove(chunk_pos);
+        }
+        loaded
+    }
+
+    // Blocks until the next requested chunk has loaded
+    pub fn wait_for_chunk(&mut self) -> LoadedChunk {
+        let (chunk_pos, chunk) = self.loaded.recv().expect("Map streaming thread stopped");
+        self.pending.remove(&chunk_pos);
+        (chunk_pos, chunk)
+    }
+}
+
+// Byte offsets to the start of every line of the floor and wall layers
+struct LayerLineOffsets {
+    ground: Vec<u64>,
+    walls: Vec<u64>,
+}
+
+// Reads through the map file once, remembering where each line of layer data starts so
+// chunks can later be read without parsing the whole map
+fn index_layer_lines(reader: &mut BufReader<std::fs::File>, map_height: u32) -> LayerLineOffsets {
+    reader.rewind().unwrap();
+    read_map_header(reader);
+    let read_offsets = |reader: &mut BufReader<std::fs::File>| {
+        (0..map_height)
+            .map(|_| {
+                let offset = reader.stream_position().unwrap();
+                skip_lines(reader, 1);
+                offset
+            })
+            .collect::<Vec<u64>>()
+    };
+    let ground = read_offsets(reader);
+    // Skipping the 4 lines between the layers
+    skip_lines(reader, 4);
+    let walls = read_offsets(reader);
+    LayerLineOffsets { ground, walls }
+}
+
+// Reads the tiles of a chunk, failing on the first tile that can't be parsed
+fn read_chunk(
+    reader: &mut BufReader<std::fs::File>,
+    line_offsets: &LayerLineOffsets,
+    chunk_pos: (u32, u32),
+    map_width: u32,
+) -> Result<MapChunk, String> {
+    let x_start = (chunk_pos.0 * CHUNK_SIZE) as usize;
+    let chunk_width = CHUNK_SIZE.min(map_width - chunk_pos.0 * CHUNK_SIZE) as usize;
+    let lines = (chunk_pos.1 * CHUNK_SIZE) as usize..((chunk_pos.1 + 1) * CHUNK_SIZE) as usize;
+
+    // Reads the part of a line of layer data inside the chunk
+    let mut read_line = |offset: u64| {
+        reader.seek(SeekFrom::Start(offset)).unwrap();
+        let mut buffer = String::new();
+        reader.read_line(&mut buffer).unwrap();
+        buffer
+            .trim()
+            .split(',')
+            .filter(|s| s != &"")
+            .skip(x_start)
+            .take(chunk_width)
+            .map(|s| s.parse::<u32>().map_err(|_| format!("{s} isn't a tile id")))
+            .collect::<Result<Vec<u32>, String>>()
+    };
+    // Where a tile of the chunk is on the map, for errors
+    let y_start = lines.start;
+    let at = |x: usize, y: usize| format!("at ({}, {})", x_start + x, y_start + y);
+
+    let ground = line_offsets.ground[lines.start..lines.end.min(line_offsets.ground.len())]
+        .iter()
+        .enumerate()
+        .map(|(y, offset)| {
+            read_line(*offset)?
+                .into_iter()
+                .enumerate()
+                .map(|(x, ground)| {
+                    try_parse_ground(ground).ok_or(format!("ground {ground} {}", at(x, y)))
+                })
+                .collect::<Result<Vec<Ground>, String>>()
+        })
+        .collect::<Result<Vec<Vec<Ground>>, String>>()?;
+    let raw_walls = line_offsets.walls[lines.start..lines.end.min(line_offsets.walls.len())]
+        .iter()
+        .map(|offset| read_line(*offset))
+        .collect::<Result<Vec<Vec<u32>>, String>>()?;
+    let walls = raw_walls
+        .iter()
+        .enumerate()
+        .map(|(y, line)| {
+            line.iter()
+                .enumerate()
+                .map(|(x, wall)| try_parse_wall(*wall).map_err(|err| format!("{err} {}", at(x, y))))
+                .collect::<Result<Vec<Option<Wall>>, String>>()
+        })
+        .collect::<Result<Vec<Vec<Option<Wall>>>, String>>()?;
+    // Keep walls that can't be parsed so they can be saved again
+    let mut unknown_walls = HashMap::new();
+    for (y, line) in raw_walls.iter().enumerate() {
+        for (x, wall) in line.iter().enumerate() {
+            if *wall != 0 && WallVarient::from_raw_u32(*wall).is_none() {
+                unknown_walls.insert((x as u32, y as u32), *wall);
+            }
+        }
+    }
+    Ok(MapChunk {
+        ground,
+        walls,
+        unknown_walls,
+    })
+}
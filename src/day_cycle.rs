@@ -1,4 +1,4 @@
-use crate::{Light, LightEngine, LightHandle};
+use crate::{input::Actions, Light, LightEngine, LightHandle};
 use raylib::prelude::*;
 
 pub const FULL_CYCLE_LENGTH: f32 = 60.0;
@@ -13,9 +13,56 @@ pub const DAY_COLOR: Vector4 = Vector4::new(1.0, 1.0, 1.0, 1.0);
 pub const SUNRISE_COLOR: Vector4 = Vector4::new(0.50, 0.60, 0.80, 1.00);
 pub const SUNSET_COLOR: Vector4 = Vector4::new(0.86, 0.52, 0.4, 1.00);
 pub const NIGHT_COLOR: Vector4 = Vector4::new(0.0, 0.03, 0.07, 1.0);
+pub const FULL_MOON_COLOR: Vector4 = Vector4::new(0.16, 0.21, 0.34, 1.0);
+pub const MOON_CYCLE_DAYS: u32 = 8;
+pub const MOONRISE: f32 = SUNSET + SUNSET_LENGTH;
+pub const MOONSET: f32 = 1.0 - SUNRISE_LENGTH;
+pub const DEFAULT_MOON_SHADOW_ALPHA: f32 = 35.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MoonPhase {
+    New,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    Full,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+
+impl MoonPhase {
+    // Maps a normalized phase (0.0 new moon, 0.5 full moon) to the closest named phase
+    pub fn from_phase(phase: f32) -> MoonPhase {
+        match ((phase * 8.0).round() as u32) % 8 {
+            0 => MoonPhase::New,
+            1 => MoonPhase::WaxingCrescent,
+            2 => MoonPhase::FirstQuarter,
+            3 => MoonPhase::WaxingGibbous,
+            4 => MoonPhase::Full,
+            5 => MoonPhase::WaningGibbous,
+            6 => MoonPhase::LastQuarter,
+            _ => MoonPhase::WaningCrescent,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MoonPhase::New => "New Moon",
+            MoonPhase::WaxingCrescent => "Waxing Crescent",
+            MoonPhase::FirstQuarter => "First Quarter",
+            MoonPhase::WaxingGibbous => "Waxing Gibbous",
+            MoonPhase::Full => "Full Moon",
+            MoonPhase::WaningGibbous => "Waning Gibbous",
+            MoonPhase::LastQuarter => "Last Quarter",
+            MoonPhase::WaningCrescent => "Waning Crescent",
+        }
+    }
+}
 
 pub struct DayCycle {
     pub time: f32,
+    pub day: u32,
     ambient_light_handle: LightHandle,
 }
 
@@ -23,27 +70,28 @@ impl DayCycle {
     pub fn new(light_engine: &mut LightEngine) -> DayCycle {
         DayCycle {
             time: SUNRISE * FULL_CYCLE_LENGTH,
+            day: 0,
             ambient_light_handle: light_engine.spawn_light(Light::default_ambient()).unwrap(),
         }
     }
-    pub fn update(&mut self, rl: &mut RaylibHandle, light_engine: &mut LightEngine) {
-        self.time += rl.get_frame_time();
+    pub fn update(&mut self, light_engine: &mut LightEngine, dt: f32) {
+        self.time += dt;
         if self.time > FULL_CYCLE_LENGTH {
             self.time -= FULL_CYCLE_LENGTH;
+            self.day += 1;
         };
-        if rl.is_key_pressed(KeyboardKey::KEY_SEVEN) {
-            self.time = FULL_CYCLE_LENGTH * 1.0 - SUNRISE_LENGTH;
-        }
-        if rl.is_key_pressed(KeyboardKey::KEY_EIGHT) {
-            self.time = FULL_CYCLE_LENGTH * NOON;
-        }
-        if rl.is_key_pressed(KeyboardKey::KEY_NINE) {
-            self.time = FULL_CYCLE_LENGTH * SUNSET - SUNRISE_LENGTH;
+        light_engine.update_light(self.ambient_light_handle(), self.get_ambient_light());
+    }
+
+    // Keys for skipping to times of day, handled once a frame
+    pub fn handle_actions(&mut self, actions: &Actions) {
+        if let Some(time) = actions.skip_to_time {
+            self.time = time;
         }
-        if rl.is_key_pressed(KeyboardKey::KEY_ZERO) {
-            self.time = FULL_CYCLE_LENGTH * MIDNIGHT;
+        // Skip ahead to the next moon phase
+        if actions.next_moon_phase {
+            self.day += 1;
         }
-        light_engine.update_light(self.ambient_light_handle(), self.get_ambient_light());
     }
     pub fn ambient_light_handle(&self) -> &LightHandle {
         &self.ambient_light_handle
@@ -63,16 +111,18 @@ impl DayCycle {
                 ((normilized_time - (1.0 - SUNRISE_LENGTH)) / SUNRISE_LENGTH * default_shadow_color)
                     as u8
             }
+            // Night time, shadows are cast by the moon
+            else if let Some(moon_progress) = self.get_moon_progress() {
+                // Fade moon shadows in and out as the moon rises and sets
+                (DEFAULT_MOON_SHADOW_ALPHA
+                    * self.get_moon_illumination()
+                    * (moon_progress * PI as f32).sin()) as u8
+            }
             // Sun setting
             else if normilized_time > SUNSET {
                 (default_shadow_color
                     - (((normilized_time) - SUNSET) / SUNSET_LENGTH * default_shadow_color))
                     as u8
-            
-            }
-            // Full night time
-            else if (SUNSET + SUNSET_LENGTH..1.0 - SUNRISE_LENGTH).contains(&normilized_time) {
-                0
             }
             // Full Day time
             else {
@@ -80,8 +130,39 @@ impl DayCycle {
             },
         )
     }
+
+    // How far the moon is through its phase cycle, 0.0 being a new moon and 0.5 a full moon
+    pub fn get_moon_phase(&self) -> f32 {
+        (self.day % MOON_CYCLE_DAYS) as f32 / MOON_CYCLE_DAYS as f32
+    }
+
+    // Fraction of the moon that is lit, from 0.0 at new moon to 1.0 at full moon
+    pub fn get_moon_illumination(&self) -> f32 {
+        (1.0 - (self.get_moon_phase() * 2.0 * PI as f32).cos()) / 2.0
+    }
+
+    // How far along the moon is across the night sky, None if the moon is not up
+    pub fn get_moon_progress(&self) -> Option<f32> {
+        let normilized_time = self.get_normilized_time();
+        if (MOONRISE..MOONSET).contains(&normilized_time) {
+            Some((normilized_time - MOONRISE) / (MOONSET - MOONRISE))
+        } else {
+            None
+        }
+    }
+
+    // Ambient color of the night, brighter and bluer the fuller the moon is
+    pub fn get_night_color(&self) -> Vector4 {
+        let diff_color = sub_vector4(FULL_MOON_COLOR, NIGHT_COLOR);
+        add_vector4(
+            NIGHT_COLOR,
+            mul_f_vector4(diff_color, self.get_moon_illumination()),
+        )
+    }
+
     pub fn get_ambient_light(&self) -> Light {
         let normilized_time = self.get_normilized_time();
+        let night_color = self.get_night_color();
         let v_normilized_time =
             Vector4::new(normilized_time, normilized_time, normilized_time, 1.0);
 
@@ -89,10 +170,10 @@ impl DayCycle {
             // Sun rising from ntime 1.0 - sunrise length to 1.0
             if ((1.0 - SUNRISE_LENGTH)..=1.0).contains(&normilized_time) {
                 // Differance in color from night to halfway to full sunrise
-                let diff_color = sub_vector4(mul_f_vector4(SUNRISE_COLOR, 0.5), NIGHT_COLOR);
+                let diff_color = sub_vector4(mul_f_vector4(SUNRISE_COLOR, 0.5), night_color);
                 // how far along to halfway to sunrise length
                 let step = (normilized_time - (1.0 - SUNRISE_LENGTH)) / SUNRISE_LENGTH;
-                add_vector4(NIGHT_COLOR, mul_f_vector4(diff_color, step))
+                add_vector4(night_color, mul_f_vector4(diff_color, step))
             }
             // Sun rising from ntime 0.0 to sunrise length
             else if (SUNRISE..=SUNRISE_LENGTH).contains(&normilized_time) {
@@ -120,14 +201,14 @@ impl DayCycle {
             // Sun turing to night after sunset
             else if (SUNSET..=SUNSET + SUNSET_LENGTH).contains(&normilized_time) {
                 // Differance in color from sunset to night
-                let diff_color = sub_vector4(SUNSET_COLOR, NIGHT_COLOR);
+                let diff_color = sub_vector4(SUNSET_COLOR, night_color);
                 // How far along the change phase
                 let step = (normilized_time - SUNSET) / (SUNSET_LENGTH);
                 sub_vector4(SUNSET_COLOR, mul_f_vector4(diff_color, step))
             } else if (SUNRISE_LENGTH..(0.5 - SUNRISE_LENGTH)).contains(&normilized_time) {
                 DAY_COLOR
             } else {
-                NIGHT_COLOR
+                night_color
             }
         };
         Light::Ambient { color: final_color }
@@ -137,11 +218,13 @@ impl DayCycle {
         let hour = ((self.time / FULL_CYCLE_LENGTH + 0.25) * 24.0) as i32;
         let minute = (self.time / FULL_CYCLE_LENGTH * 24.0 * 60.0 % 60.0) as i32;
         format!(
-            "Game Time: {}:{}{} {}",
+            "Game Time: Day {} {}:{}{} {} ({})",
+            self.day + 1,
             if hour % 12 == 0 { 12 } else { hour % 12 },
             if minute < 10 { "0" } else { "" },
             minute,
-            if hour % 24 < 12 { "AM" } else { "PM" }
+            if hour % 24 < 12 { "AM" } else { "PM" },
+            MoonPhase::from_phase(self.get_moon_phase()).name()
         )
     }
 }
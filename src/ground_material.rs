@@ -0,0 +1,204 @@
+use crate::{get_xml_attribute, map_objects::unescape_xml, GroundVarient};
+use raylib::prelude::*;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+};
+
+// What is left where a bullet hits the ground
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImpactEffect {
+    Dust,
+    Splinters,
+    Leaves,
+}
+
+impl ImpactEffect {
+    fn from_name(name: &str) -> Option<ImpactEffect> {
+        match name {
+            "dust" => Some(ImpactEffect::Dust),
+            "splinters" => Some(ImpactEffect::Splinters),
+            "leaves" => Some(ImpactEffect::Leaves),
+            _ => None,
+        }
+    }
+
+    pub fn get_color(&self) -> Color {
+        match self {
+            ImpactEffect::Dust => Color::new(150, 120, 90, 255),
+            ImpactEffect::Splinters => Color::new(190, 140, 80, 255),
+            ImpactEffect::Leaves => Color::new(90, 150, 60, 255),
+        }
+    }
+}
+
+// How a kind of ground plays. Multipliers are relative to walking on the default ground.
+#[derive(Clone, Debug)]
+pub struct GroundMaterial {
+    pub name: String,
+    pub speed: f32,
+    pub acceleration: f32,
+    // How quickly the player slows down when not moving
+    pub friction: f32,
+    // Name of the set of footstep sounds to play
+    pub footsteps: String,
+    // How far footsteps on this ground can be heard, in world units
+    pub noise: f32,
+    pub impact: ImpactEffect,
+}
+
+impl Default for GroundMaterial {
+    fn default() -> Self {
+        GroundMaterial {
+            name: "default".to_string(),
+            speed: 1.0,
+            acceleration: 1.0,
+            friction: 1.0,
+            footsteps: "default".to_string(),
+            noise: 80.0,
+            impact: ImpactEffect::Dust,
+        }
+    }
+}
+
+// The material of every floor tile, set as custom tile properties of the floor tileset in Tiled
+pub struct GroundMaterials {
+    materials: HashMap<u32, GroundMaterial>,
+    default: GroundMaterial,
+}
+
+impl GroundMaterials {
+    pub fn load(path: &str) -> Result<GroundMaterials, String> {
+        let file = std::fs::File::open(path).map_err(|err| format!("{path}: {err}"))?;
+        Self::read(BufReader::new(file))
+    }
+
+    // Reads the tile properties of a tileset. Tiles without a material use the default one.
+    pub fn read(reader: impl BufRead) -> Result<GroundMaterials, String> {
+        let mut materials = HashMap::new();
+        let mut current_tile: Option<(u32, GroundMaterial)> = None;
+        for line in reader.lines() {
+            let line = line.map_err(|err| err.to_string())?;
+            let element = line.trim();
+            if element.starts_with("<tile ") {
+                let id = get_xml_attribute(element, "id")
+                    .and_then(|id| id.parse::<u32>().ok())
+                    .ok_or(format!("Tile without an id: {element}"))?;
+                current_tile = Some((id, GroundMaterial::default()));
+            } else if element.starts_with("</tile>") {
+                materials.extend(current_tile.take());
+            } else if let Some((id, material)) = current_tile.as_mut() {
+                if !element.starts_with("<property ") {
+                    continue;
+                }
+                let (Some(name), Some(value)) = (
+                    get_xml_attribute(element, "name"),
+                    get_xml_attribute(element, "value").map(unescape_xml),
+                ) else {
+                    continue;
+                };
+                let parse_f32 = |value: &str| {
+                    value
+                        .parse::<f32>()
+                        .map_err(|_| format!("{name} of tile {id} isn't a number: {value}"))
+                };
+                match name {
+                    "material" => material.name = value,
+                    "speed" => material.speed = parse_f32(&value)?,
+                    "acceleration" => material.acceleration = parse_f32(&value)?,
+                    "friction" => material.friction = parse_f32(&value)?,
+                    "footsteps" => material.footsteps = value,
+                    "noise" => material.noise = parse_f32(&value)?,
+                    "impact" => {
+                        material.impact = ImpactEffect::from_name(&value)
+                            .ok_or(format!("Unknown impact effect of tile {id}: {value}"))?
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(GroundMaterials {
+            materials,
+            default: GroundMaterial::default(),
+        })
+    }
+
+    pub fn get(&self, varient: GroundVarient) -> &GroundMaterial {
+        self.materials
+            .get(&(varient as u32))
+            .unwrap_or(&self.default)
+    }
+
+    pub fn get_default(&self) -> &GroundMaterial {
+        &self.default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tileset with the properties given for the wood tile
+    fn read_with_wood_properties(properties: &str) -> Result<GroundMaterials, String> {
+        let tsx = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="floor" tilewidth="32" tileheight="32" tilecount="8" columns="8">
+ <image source="floor.png" width="256" height="32"/>
+ <tile id="1">
+  <properties>
+{properties}
+  </properties>
+ </tile>
+</tileset>
+"#
+        );
+        GroundMaterials::read(tsx.as_bytes())
+    }
+
+    #[test]
+    fn tile_properties_override_defaults() {
+        let materials = read_with_wood_properties(
+            r#"   <property name="material" value="wood"/>
+   <property name="speed" type="float" value="1.2"/>
+   <property name="footsteps" value="wood"/>
+   <property name="impact" value="splinters"/>"#,
+        )
+        .unwrap();
+        let wood = materials.get(GroundVarient::Wood);
+        assert_eq!(wood.name, "wood");
+        assert_eq!(wood.speed, 1.2);
+        assert_eq!(wood.footsteps, "wood");
+        assert_eq!(wood.impact, ImpactEffect::Splinters);
+        // Properties that weren't set keep their default
+        assert_eq!(wood.friction, 1.0);
+        assert_eq!(wood.noise, GroundMaterial::default().noise);
+    }
+
+    #[test]
+    fn tiles_without_properties_use_default() {
+        let materials =
+            read_with_wood_properties(r#"   <property name="speed" value="0.5"/>"#).unwrap();
+        let grass = materials.get(GroundVarient::Grass);
+        assert_eq!(grass.name, "default");
+        assert_eq!(grass.speed, 1.0);
+        assert_eq!(grass.impact, ImpactEffect::Dust);
+    }
+
+    #[test]
+    fn speed_has_to_be_a_number() {
+        let result = read_with_wood_properties(r#"   <property name="speed" value="fast"/>"#);
+        assert_eq!(
+            result.err().as_deref(),
+            Some("speed of tile 1 isn't a number: fast")
+        );
+    }
+
+    #[test]
+    fn impact_has_to_be_known() {
+        let result = read_with_wood_properties(r#"   <property name="impact" value="sparks"/>"#);
+        assert_eq!(
+            result.err().as_deref(),
+            Some("Unknown impact effect of tile 1: sparks")
+        );
+    }
+}
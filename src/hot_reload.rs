@@ -0,0 +1,146 @@
+use crate::{
+    ground_material::GroundMaterials, world::World, LightEngine, Player, Renderer,
+    FLOOR_TILESET_PATH, FLOOR_TILE_SHEET_PATH, LIGHTING_SHADER_PATH, OBJECT_TILESET_PATH,
+    OBJECT_TILE_SHEET_PATH, WALL_TILESET_PATH, WALL_TILE_SHEET_PATH,
+};
+use raylib::prelude::*;
+use std::{collections::HashMap, time::SystemTime};
+
+// Seconds between checking the watched files for changes
+const POLL_INTERVAL: f64 = 0.5;
+
+// Watches the maps, shaders and textures the game uses and reloads them when they change on
+// disk. Files are polled for their modification time, so editors that save by replacing the
+// file are picked up too.
+pub struct AssetWatcher {
+    modified_times: HashMap<String, Option<SystemTime>>,
+    last_poll: f64,
+    // Last error for each file that failed to reload, cleared once it reloads fine
+    errors: HashMap<String, String>,
+}
+
+impl Default for AssetWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AssetWatcher {
+    pub fn new() -> AssetWatcher {
+        let mut watcher = AssetWatcher {
+            modified_times: HashMap::new(),
+            last_poll: 0.0,
+            errors: HashMap::new(),
+        };
+        watcher.watch(LIGHTING_SHADER_PATH);
+        watcher.watch(FLOOR_TILE_SHEET_PATH);
+        watcher.watch(WALL_TILE_SHEET_PATH);
+        watcher.watch(OBJECT_TILE_SHEET_PATH);
+        watcher.watch(FLOOR_TILESET_PATH);
+        watcher.watch(WALL_TILESET_PATH);
+        watcher.watch(OBJECT_TILESET_PATH);
+        for path in Player::get_texture_paths() {
+            watcher.watch(&path);
+        }
+        watcher
+    }
+
+    // Starts watching a file, if it isn't already
+    pub fn watch(&mut self, path: &str) {
+        if !self.modified_times.contains_key(path) {
+            self.modified_times
+                .insert(path.to_string(), get_modified_time(path));
+        }
+    }
+
+    // Remembers the modification time of a file the game just wrote itself, so it isn't
+    // reloaded
+    pub fn mark_saved(&mut self, path: &str) {
+        self.modified_times
+            .insert(path.to_string(), get_modified_time(path));
+    }
+
+    // Reloads every watched file that changed since the last poll
+    pub fn reload_changed(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        renderer: &mut Renderer,
+        light_engine: &mut LightEngine,
+        world: &mut World,
+        player: &mut Player,
+    ) {
+        // The map can change through transitions, so whichever one is loaded gets watched
+        self.watch(&world.map_path);
+        if rl.get_time() - self.last_poll < POLL_INTERVAL {
+            return;
+        }
+        self.last_poll = rl.get_time();
+
+        for path in self.take_changed_files() {
+            let result = if path.ends_with(".fs") {
+                renderer
+                    .reload_shader(rl, thread)
+                    .map(|()| light_engine.update_shader_locations(&renderer.shader))
+            } else if path.ends_with(".png") {
+                renderer
+                    .reload_texture(rl, thread, &path)
+                    .and_then(|()| player.reload_texture(rl, thread, &path))
+            } else if path.ends_with(".tsx") {
+                // The floor tileset also holds the ground materials
+                renderer.reload_tileset(&path).and_then(|()| {
+                    if path != FLOOR_TILESET_PATH {
+                        return Ok(());
+                    }
+                    GroundMaterials::load(&path).map(|materials| world.ground_materials = materials)
+                })
+            } else if path == world.map_path {
+                world.reload_map(light_engine)
+            } else {
+                // A map that isn't loaded anymore, it gets read again when it is next entered
+                Ok(())
+            };
+            match result {
+                Ok(()) => {
+                    self.errors.remove(&path);
+                }
+                Err(err) => {
+                    self.errors.insert(path, err);
+                }
+            }
+        }
+    }
+
+    // Messages for every file that failed to reload
+    pub fn get_errors(&self) -> Vec<String> {
+        let mut errors = self
+            .errors
+            .iter()
+            .map(|(path, err)| format!("Unable to reload {path}: {err}"))
+            .collect::<Vec<String>>();
+        errors.sort();
+        errors
+    }
+
+    fn take_changed_files(&mut self) -> Vec<String> {
+        let mut changed = vec![];
+        for (path, modified_time) in self.modified_times.iter_mut() {
+            let new_time = get_modified_time(path);
+            if new_time != *modified_time {
+                *modified_time = new_time;
+                // Files are often deleted and written again when saved, only reload once it
+                // is back
+                if new_time.is_some() {
+                    changed.push(path.clone());
+                }
+            }
+        }
+        changed
+    }
+}
+
+fn get_modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
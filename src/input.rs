@@ -0,0 +1,182 @@
+use crate::{
+    day_cycle::{FULL_CYCLE_LENGTH, MIDNIGHT, NOON, SUNRISE_LENGTH, SUNSET},
+    ImprovedCamera,
+};
+use raylib::prelude::*;
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GunSlot {
+    Rifle,
+    Pistol,
+}
+
+// What whoever is controlling the player wants to happen in a tick. Held actions last as long as
+// they are held, presses are only seen by one tick.
+#[derive(Clone, Debug, Default)]
+pub struct Actions {
+    // Direction to walk in, at most 1 long
+    pub movement: Vector2,
+    // Point in the world the player is aiming at
+    pub aim_pos: Vector2,
+    pub sprint: bool,
+    pub aim_down_sights: bool,
+    // Trigger held, for automatic guns
+    pub fire: bool,
+    // Trigger pulled, for semi automatic guns
+    pub fire_pressed: bool,
+    pub reload: bool,
+    pub toggle_flashlight: bool,
+    pub switch_gun: Option<GunSlot>,
+
+    // Debug actions
+    // Seconds into the day cycle to skip to
+    pub skip_to_time: Option<f32>,
+    pub next_moon_phase: bool,
+    // Color of a light to place where the player is aiming
+    pub spawn_light: Option<Color>,
+}
+
+impl Actions {
+    // Forgets the presses, once a tick has seen them
+    pub fn clear_presses(&mut self) {
+        self.fire_pressed = false;
+        self.reload = false;
+        self.toggle_flashlight = false;
+        self.switch_gun = None;
+        self.skip_to_time = None;
+        self.next_moon_phase = false;
+        self.spawn_light = None;
+    }
+}
+
+// Anything that can control the player, like the keyboard, a gamepad, the network, AI or a
+// script
+pub trait ActionSource {
+    // Actions for the next tick
+    fn next_tick(&mut self) -> Actions;
+}
+
+// Controls the player with the keyboard and mouse. Read once a frame, and a frame can run no
+// ticks or a few of them, so presses are kept until a tick takes them.
+#[derive(Default)]
+pub struct KeyboardInput {
+    actions: Actions,
+}
+
+impl KeyboardInput {
+    pub fn new() -> KeyboardInput {
+        Self::default()
+    }
+
+    pub fn update(&mut self, rl: &RaylibHandle, camera: &Camera2D) {
+        let actions = &mut self.actions;
+
+        let mut movement = Vector2::zero();
+        if rl.is_key_down(KeyboardKey::KEY_W) {
+            movement.y -= 1.0;
+        }
+        if rl.is_key_down(KeyboardKey::KEY_S) {
+            movement.y += 1.0;
+        }
+        if rl.is_key_down(KeyboardKey::KEY_A) {
+            movement.x -= 1.0;
+        }
+        if rl.is_key_down(KeyboardKey::KEY_D) {
+            movement.x += 1.0;
+        }
+        actions.movement = if movement == Vector2::zero() {
+            movement
+        } else {
+            movement.normalized()
+        };
+        actions.aim_pos = camera.to_world(rl.get_mouse_position());
+        actions.sprint = rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT);
+        actions.aim_down_sights = rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_RIGHT)
+            || rl.is_key_down(KeyboardKey::KEY_SPACE);
+        actions.fire = rl.is_key_down(KeyboardKey::KEY_E);
+
+        actions.fire_pressed |= rl.is_key_pressed(KeyboardKey::KEY_E);
+        actions.reload |= rl.is_key_pressed(KeyboardKey::KEY_R);
+        actions.toggle_flashlight |= rl.is_key_pressed(KeyboardKey::KEY_F);
+        if rl.is_key_down(KeyboardKey::KEY_FIVE) {
+            actions.switch_gun = Some(GunSlot::Rifle);
+        }
+        if rl.is_key_down(KeyboardKey::KEY_SIX) {
+            actions.switch_gun = Some(GunSlot::Pistol);
+        }
+
+        for (key, time) in [
+            (
+                KeyboardKey::KEY_SEVEN,
+                FULL_CYCLE_LENGTH * 1.0 - SUNRISE_LENGTH,
+            ),
+            (KeyboardKey::KEY_EIGHT, FULL_CYCLE_LENGTH * NOON),
+            (
+                KeyboardKey::KEY_NINE,
+                FULL_CYCLE_LENGTH * SUNSET - SUNRISE_LENGTH,
+            ),
+            (KeyboardKey::KEY_ZERO, FULL_CYCLE_LENGTH * MIDNIGHT),
+        ] {
+            if rl.is_key_pressed(key) {
+                actions.skip_to_time = Some(time);
+            }
+        }
+        // Skip ahead to the next moon phase
+        actions.next_moon_phase |= rl.is_key_pressed(KeyboardKey::KEY_N);
+        for (key, color) in [
+            (KeyboardKey::KEY_ONE, Color::WHITE),
+            (KeyboardKey::KEY_TWO, Color::RED),
+            (KeyboardKey::KEY_THREE, Color::BLUE),
+            (KeyboardKey::KEY_FOUR, Color::YELLOW),
+        ] {
+            if rl.is_key_pressed(key) {
+                actions.spawn_light = Some(color);
+            }
+        }
+    }
+}
+
+impl ActionSource for KeyboardInput {
+    fn next_tick(&mut self) -> Actions {
+        let actions = self.actions.clone();
+        self.actions.clear_presses();
+        actions
+    }
+}
+
+// Plays back a list of actions, each held for a number of ticks, then does nothing once it runs
+// out. Presses in a step only happen on its first tick.
+#[derive(Default)]
+pub struct ScriptedInput {
+    steps: VecDeque<(Actions, u32)>,
+}
+
+impl ScriptedInput {
+    pub fn new(steps: Vec<(Actions, u32)>) -> ScriptedInput {
+        ScriptedInput {
+            steps: steps.into(),
+        }
+    }
+
+    // Whether every step has been played
+    pub fn is_finished(&self) -> bool {
+        self.steps.iter().all(|(_, ticks)| *ticks == 0)
+    }
+}
+
+impl ActionSource for ScriptedInput {
+    fn next_tick(&mut self) -> Actions {
+        while let Some((actions, ticks)) = self.steps.front_mut() {
+            if *ticks == 0 {
+                self.steps.pop_front();
+                continue;
+            }
+            *ticks -= 1;
+            let tick_actions = actions.clone();
+            actions.clear_presses();
+            return tick_actions;
+        }
+        Actions::default()
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{bullet::Bullet, world::World, ImprovedCamera, LightHandle};
+use crate::{
+    bullet::{Bullet, Caliber},
+    world::World,
+    ImprovedCamera, LightHandle, TILE_SIZE,
+};
 use rand::Rng;
 use raylib::prelude::*;
 
@@ -82,6 +86,13 @@ impl GunItem {
         }
     }
 
+    pub fn get_caliber(&self) -> Caliber {
+        match self {
+            GunItem::AR15 { .. } => Caliber::RIFLE,
+            GunItem::Pistol { .. } => Caliber::PISTOL,
+        }
+    }
+
     pub fn get_render_barrel_length(&self) -> f32 {
         match self {
             GunItem::AR15 { .. } => 14.5,
@@ -98,24 +109,47 @@ pub struct FlashLight {
 pub fn explode(rl: &RaylibHandle, world: &mut World, camera: &Camera2D) {
     let num_shrapnel = 25;
     let num_random_shrapnel = 25;
-    let shrapnel_speed = 500.0;
+    let shrapnel_speed = Caliber::SHRAPNEL.muzzle_speed;
     let shrapnel_speed_margin = 0.3;
     let mut rng = rand::thread_rng();
     let mouse_world_pos = camera.to_world(rl.get_mouse_position());
+
+    // Blow up walls close to the explosion
+    let blast_radius = 24.0;
+    let blast_tiles = world.map.get_tile_range(
+        &Rectangle::new(
+            mouse_world_pos.x - blast_radius,
+            mouse_world_pos.y - blast_radius,
+            blast_radius * 2.0,
+            blast_radius * 2.0,
+        ),
+        0,
+    );
+    for (x, y) in blast_tiles.iter() {
+        let tile_center = Vector2::new((x as f32 + 0.5) * TILE_SIZE, (y as f32 + 0.5) * TILE_SIZE);
+        if tile_center.distance_to(mouse_world_pos) <= blast_radius {
+            world.map.destroy_wall(x, y);
+        }
+    }
+
     for i in 0..num_shrapnel {
         let angle = 2.0 * PI as f32 * (i as f32 / num_shrapnel as f32);
         let vel = Vector2::new(angle.cos(), angle.sin()) * shrapnel_speed;
         let random_vel = rng.gen_range(1.0 - shrapnel_speed_margin..1.0 + shrapnel_speed_margin);
-        world
-            .bullets
-            .push(Bullet::new(mouse_world_pos, vel * random_vel));
+        world.bullets.push(Bullet::new(
+            mouse_world_pos,
+            vel * random_vel,
+            Caliber::SHRAPNEL,
+        ));
     }
     for _ in 0..num_random_shrapnel {
         let angle = rng.gen_range(0.0..2.0 * PI as f32);
         let random_vel = rng.gen_range(1.0 - shrapnel_speed_margin..1.0 + shrapnel_speed_margin);
         let vel = Vector2::new(angle.cos(), angle.sin()) * shrapnel_speed;
-        world
-            .bullets
-            .push(Bullet::new(mouse_world_pos, vel * random_vel));
+        world.bullets.push(Bullet::new(
+            mouse_world_pos,
+            vel * random_vel,
+            Caliber::SHRAPNEL,
+        ));
     }
 }
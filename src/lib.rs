@@ -0,0 +1,34 @@
+pub use lighting::*;
+pub use player::*;
+pub use tile::*;
+pub use ui_renderer::*;
+pub use world_map::*;
+pub use world_renderer::*;
+
+pub mod auto_tile;
+pub mod bullet;
+pub mod chunk_streamer;
+pub mod day_cycle;
+pub mod ground_material;
+pub mod hot_reload;
+pub mod input;
+pub mod items;
+pub mod lighting;
+pub mod map_editor;
+pub mod map_generator;
+pub mod map_objects;
+pub mod map_validator;
+pub mod minimap;
+pub mod navigation;
+pub mod player;
+pub mod simulation;
+pub mod tile;
+pub mod tile_animation;
+pub mod tile_cache;
+pub mod timestep;
+pub mod triggers;
+pub mod ui_renderer;
+pub mod visibility;
+pub mod world;
+pub mod world_map;
+pub mod world_renderer;
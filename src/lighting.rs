@@ -2,7 +2,7 @@
 use raylib::prelude::*;
 use std::collections::HashMap;
 
-use crate::ImprovedCamera;
+use crate::input::Actions;
 
 pub const AMBIENT_LIGHT_NIGHT: Light = Light::Ambient {
     color: Vector4::new(0.7, 0.7, 1.0, 0.25),
@@ -146,6 +146,21 @@ struct ShaderUniforms {
     angle: i32,
 }
 
+impl ShaderUniforms {
+    fn from_shader(shader: &Shader) -> ShaderUniforms {
+        ShaderUniforms {
+            position: shader.get_shader_location("lightsPosition"),
+            color: shader.get_shader_location("lightsColor"),
+            amount: shader.get_shader_location("lightsAmount"),
+            radius: shader.get_shader_location("lightsRadius"),
+            light_type: shader.get_shader_location("lightsType"),
+            rotation: shader.get_shader_location("lightsRotation"),
+            angle: shader.get_shader_location("lightsAngle"),
+            screen_size: shader.get_shader_location("screenSize"),
+        }
+    }
+}
+
 pub struct LightEngine {
     lights: HashMap<u32, Light>,
     light_id: u32,
@@ -160,20 +175,39 @@ impl LightEngine {
         LightEngine {
             lights: HashMap::new(),
             light_id: 0,
+            shader_uniforms: ShaderUniforms::from_shader(shader),
+        }
+    }
+
+    // Keeps track of lights without a shader to send them to, for running the world without a
+    // window
+    pub fn headless() -> LightEngine {
+        LightEngine {
+            lights: HashMap::new(),
+            light_id: 0,
+            // Raylib's location for uniforms that don't exist, setting them does nothing
             shader_uniforms: ShaderUniforms {
-                position: shader.get_shader_location("lightsPosition"),
-                color: shader.get_shader_location("lightsColor"),
-                amount: shader.get_shader_location("lightsAmount"),
-                radius: shader.get_shader_location("lightsRadius"),
-                light_type: shader.get_shader_location("lightsType"),
-                rotation: shader.get_shader_location("lightsRotation"),
-                angle: shader.get_shader_location("lightsAngle"),
-                screen_size: shader.get_shader_location("screenSize"),
+                position: -1,
+                color: -1,
+                amount: -1,
+                radius: -1,
+                light_type: -1,
+                screen_size: -1,
+                rotation: -1,
+                angle: -1,
             },
         }
     }
+
+    // Looks up the uniform locations again, needed whenever the shader is reloaded
+    pub fn update_shader_locations(&mut self, shader: &Shader) {
+        self.shader_uniforms = ShaderUniforms::from_shader(shader);
+    }
+
+    // Fails once the shader's light limit is reached
+    #[allow(clippy::result_unit_err)]
     pub fn spawn_light(&mut self, light: Light) -> Result<LightHandle, ()> {
-        if self.light_id < 400 {
+        if self.lights.len() < 400 {
             self.lights.insert(self.light_id, light);
             self.light_id += 1;
             Ok(LightHandle(self.light_id - 1))
@@ -182,9 +216,16 @@ impl LightEngine {
         }
     }
 
+    pub fn despawn_light(&mut self, light_handle: LightHandle) {
+        self.lights.remove(&light_handle.0);
+    }
+
     pub fn update_light(&mut self, light_handle: &LightHandle, updated_light: Light) {
         self.lights.insert(light_handle.0, updated_light);
     }
+    pub fn get_light(&self, light_handle: &LightHandle) -> &Light {
+        self.lights.get(&light_handle.0).unwrap()
+    }
     pub fn get_mut_light(&mut self, light_handle: &LightHandle) -> &mut Light {
         self.lights.get_mut(&light_handle.0).unwrap()
     }
@@ -192,6 +233,9 @@ impl LightEngine {
     pub fn spawned_lights(&self) -> usize {
         self.lights.len()
     }
+    pub fn lights(&self) -> impl Iterator<Item = &Light> {
+        self.lights.values()
+    }
 
     // Updating the shader with new uniform values
     pub fn update_shader_values(
@@ -252,38 +296,13 @@ impl LightEngine {
         shader.set_shader_value(self.shader_uniforms.screen_size, screen_size);
     }
 
-    pub fn handle_spawning_light(&mut self, rl: &mut RaylibHandle, camera: &Camera2D) {
-        let pos = camera.to_world(rl.get_mouse_position());
-        let light_radius = Light::default_radial().radius();
-        if rl.is_key_pressed(KeyboardKey::KEY_ONE) {
-            self.spawn_light(Light::Radial {
-                pos,
-                color: Color::WHITE.into(),
-                radius: light_radius,
-            })
-            .unwrap();
-        }
-        if rl.is_key_pressed(KeyboardKey::KEY_TWO) {
-            self.spawn_light(Light::Radial {
-                pos,
-                color: Color::RED.into(),
-                radius: light_radius,
-            })
-            .unwrap();
-        }
-        if rl.is_key_pressed(KeyboardKey::KEY_THREE) {
-            self.spawn_light(Light::Radial {
-                pos,
-                color: Color::BLUE.into(),
-                radius: light_radius,
-            })
-            .unwrap();
-        }
-        if rl.is_key_pressed(KeyboardKey::KEY_FOUR) {
+    // Places a light where the player is aiming, when they ask for one
+    pub fn handle_spawning_light(&mut self, actions: &Actions) {
+        if let Some(color) = actions.spawn_light {
             self.spawn_light(Light::Radial {
-                pos,
-                color: Color::YELLOW.into(),
-                radius: light_radius,
+                pos: actions.aim_pos,
+                color: color.into(),
+                radius: Light::default_radial().radius(),
             })
             .unwrap();
         }
@@ -1,24 +1,18 @@
-use items::explode;
-use lighting::*;
-use player::*;
+use lighting::{
+    day_cycle,
+    hot_reload::AssetWatcher,
+    input::{ActionSource, KeyboardInput},
+    items::explode,
+    map_editor::MapEditor,
+    map_generator::MapGenerator,
+    minimap::Minimap,
+    simulation,
+    timestep::FixedTimestep,
+    triggers::{TriggerEventKind, PLAYER_ENTITY},
+    world::World,
+    DebugInfo, ImprovedCamera, LightEngine, Player, Renderer, UIRenderer,
+};
 use raylib::prelude::*;
-use tile::*;
-use ui_renderer::UIRenderer;
-use ui_renderer::*;
-use world::World;
-use world_map::*;
-use world_renderer::*;
-
-mod bullet;
-mod day_cycle;
-mod items;
-mod lighting;
-mod player;
-mod tile;
-mod ui_renderer;
-mod world;
-mod world_map;
-mod world_renderer;
 
 fn main() {
     let (mut rl, thread) = raylib::init()
@@ -33,36 +27,120 @@ fn main() {
     let mut camera = Camera2D::default();
     let mut player = Player::new(&mut rl, &thread, &mut light_engine);
     let mut debug_info = DebugInfo::new();
-    let mut world = World::new(&mut light_engine);
+    let mut editor = MapEditor::new();
+    let mut asset_watcher = AssetWatcher::new();
+    let mut minimap = Minimap::new(&mut rl, &thread);
+    let mut timestep = FixedTimestep::new();
+    let mut keyboard = KeyboardInput::new();
+    // Map can be picked by passing its path as the first argument, or generated by passing
+    // --generate with an optional seed
+    let args = std::env::args().collect::<Vec<String>>();
+    let map_path = match args.get(1).map(|arg| arg.as_str()) {
+        Some("--generate") => {
+            let seed = args
+                .get(2)
+                .map(|seed| seed.parse::<u64>().expect("Seed has to be a number"))
+                .unwrap_or_else(rand::random);
+            let map_path = format!("assets/maps/generated_{seed}.tmx");
+            MapGenerator::new(seed, 96, 96)
+                .generate()
+                .save_to_tmx(&map_path)
+                .expect("Unable to save generated map");
+            println!("Generated map with seed {seed} at {map_path}");
+            map_path
+        }
+        Some(path) => path.to_string(),
+        None => "assets/maps/map0.tmx".to_string(),
+    };
+    let mut world = World::new(&mut light_engine, &map_path);
 
     camera.zoom = 3.5;
-    player.pos = Vector2::new(64.0, 64.0);
+    player.pos = world
+        .map
+        .get_player_spawn()
+        .unwrap_or(Vector2::new(64.0, 64.0));
+    player.prev_pos = player.pos;
     camera.track(
         player.pos,
         Vector2::new(rl.get_screen_width() as f32, rl.get_screen_height() as f32),
     );
 
+    // Kept until the chunks load fine again, the world isn't always streamed every frame
+    let mut streaming_error = None;
+    // Shown in the debug info, events only last a frame
+    let mut last_trigger_event = String::new();
     while !rl.window_should_close() {
         /* ---- Update ---- */
         let screen_size = Vector2::new(rl.get_screen_width() as f32, rl.get_screen_height() as f32);
 
-        // Only update if player inst freezing time
-        if !rl.is_key_down(KeyboardKey::KEY_T) {
-            player.update(&mut rl, &mut light_engine, &camera, &mut world);
-            world.update_bullets(&rl);
+        if rl.is_key_pressed(KeyboardKey::KEY_F2) {
+            editor.toggle(player.pos);
+        }
+
+        if !editor.enabled {
+            minimap.update(&mut rl, &player);
+        }
+
+        // The world is paused while editing the map or looking at the full-screen map
+        if editor.enabled {
+            streaming_error = world.map.update_streaming(editor.view_pos).err();
+            editor.update(
+                &mut rl,
+                &mut camera,
+                &mut world,
+                &mut asset_watcher,
+                screen_size,
+            );
+            renderer.update_target(&mut rl, &thread, screen_size);
+        } else if minimap.full_screen {
+            renderer.update_target(&mut rl, &thread, screen_size);
+        } else if !rl.is_key_down(KeyboardKey::KEY_T) {
+            // Only update if player inst freezing time
+            streaming_error = world.map.update_streaming(player.pos).err();
+            keyboard.update(&rl, &camera);
+            // The world runs at a fixed tick rate, however many ticks fit into this frame
+            for _ in 0..timestep.advance(rl.get_frame_time()) {
+                let actions = keyboard.next_tick();
+                if simulation::tick(&actions, &mut light_engine, &mut world, &mut player) {
+                    camera.track(player.pos, screen_size);
+                }
+            }
+            renderer.interpolation = timestep.get_alpha();
+            world.update_visibility(&player);
 
             if rl.is_key_pressed(KeyboardKey::KEY_G) {
                 explode(&rl, &mut world, &camera);
             }
 
             camera.handle_player_controls(&mut rl);
-            camera.pan_to(&rl, player.pos, screen_size);
+            camera.pan_to(
+                &rl,
+                player.get_render_pos(renderer.interpolation),
+                screen_size,
+            );
 
-            world.day_cycle.update(&mut rl, &mut light_engine);
             debug_info.update(&mut rl);
             debug_info.add(format!("FPS: {}", rl.get_fps()));
             debug_info.add(format!("Frame time: {}", rl.get_frame_time()));
             debug_info.add(world.day_cycle.get_debug_info());
+            debug_info.add(format!(
+                "Ground: {}",
+                world.get_ground_material(player.pos).name
+            ));
+            // Events from every tick of the frame, not just the last
+            for event in world.triggers.drain_events() {
+                if event.kind != TriggerEventKind::Stay {
+                    last_trigger_event = format!("{:?} {}", event.kind, event.name);
+                }
+            }
+            debug_info.add(format!(
+                "Triggers: {}",
+                world
+                    .triggers
+                    .get_triggers_containing(PLAYER_ENTITY)
+                    .join(", ")
+            ));
+            debug_info.add(format!("Last trigger event: {last_trigger_event}"));
             debug_info.add(format!(
                 "Norm Time: {}",
                 world.day_cycle.time / day_cycle::FULL_CYCLE_LENGTH
@@ -71,10 +149,25 @@ fn main() {
                 "Spawned lights {}/400",
                 light_engine.spawned_lights()
             ));
-            light_engine.handle_spawning_light(&mut rl, &camera);
 
             renderer.update_target(&mut rl, &thread, screen_size);
         }
+        asset_watcher.reload_changed(
+            &mut rl,
+            &thread,
+            &mut renderer,
+            &mut light_engine,
+            &mut world,
+            &mut player,
+        );
+        debug_info.errors = asset_watcher.get_errors();
+        debug_info.errors.extend(streaming_error.clone());
+        let changed_tiles = world.map.take_changed_tiles();
+        world.navigation.update(&world.map, &changed_tiles);
+        renderer.update_tile_cache(&mut rl, &thread, &world.map, &changed_tiles);
+        minimap.sync(&mut rl, &thread, &world.map, &changed_tiles);
+        renderer.fog_enabled = !editor.enabled;
+
         /* ----- Draw ----- */
         let mut d = rl.begin_drawing(&thread);
         light_engine.update_shader_values(&mut renderer.shader, &camera, screen_size);
@@ -84,5 +177,10 @@ fn main() {
 
         // Drawing UI
         UIRenderer::render_ui(&mut d, &debug_info);
+        if editor.enabled {
+            editor.draw(&mut d, &camera);
+        } else {
+            minimap.draw(&mut d, &world, &player, &camera);
+        }
     }
 }
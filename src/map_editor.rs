@@ -0,0 +1,484 @@
+use crate::{
+    auto_tile::WallStyle, hot_reload::AssetWatcher, parse_ground, parse_wall, world::World, Ground,
+    GroundVarient, ImprovedCamera, TileRange, TileRotation, Wall, WallVarient, WorldMap, TILE_SIZE,
+};
+use raylib::prelude::*;
+
+const GROUND_VARIENTS: [GroundVarient; 6] = [
+    GroundVarient::Dirt,
+    GroundVarient::Wood,
+    GroundVarient::Grass,
+    GroundVarient::DirtQuarterEdge,
+    GroundVarient::DirtThreeQuarterEdge,
+    GroundVarient::DirtHalfEdge,
+];
+const WALL_VARIENTS: [WallVarient; 7] = [
+    WallVarient::Staight,
+    WallVarient::Elbow,
+    WallVarient::WhiteStraight,
+    WallVarient::WhiteElbow,
+    WallVarient::TinyElbow,
+    WallVarient::WhiteTinyElbow,
+    WallVarient::WhitePillar,
+];
+// How fast the view moves around the map, in world units per second
+const EDITOR_PAN_SPEED: f32 = 400.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EditorLayer {
+    Ground,
+    Walls,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EditorTool {
+    Paint,
+    Rectangle,
+    Eyedropper,
+}
+
+// A change to a single tile, with the raw tile ids of its ground and wall before and after
+struct TileEdit {
+    x: u32,
+    y: u32,
+    before: (u32, u32),
+    after: (u32, u32),
+}
+
+// Paints ground and walls onto the loaded map with the mouse
+pub struct MapEditor {
+    pub enabled: bool,
+    // Point the camera follows while editing
+    pub view_pos: Vector2,
+    layer: EditorLayer,
+    tool: EditorTool,
+    ground_varient: usize,
+    wall_varient: usize,
+    // Quarter turns clockwise
+    rotation: u32,
+    // Walls get picked from the walls around them instead of the selected varient
+    auto_tile: bool,
+    // Edits of the stroke the mouse is currently held down for
+    stroke: Vec<TileEdit>,
+    undo_stack: Vec<Vec<TileEdit>>,
+    redo_stack: Vec<Vec<TileEdit>>,
+    // Tile a rectangle was started on, and whether it erases
+    rect_start: Option<((u32, u32), bool)>,
+    hovered_tile: Option<(u32, u32)>,
+    status: String,
+}
+
+impl Default for MapEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapEditor {
+    pub fn new() -> MapEditor {
+        MapEditor {
+            enabled: false,
+            view_pos: Vector2::zero(),
+            layer: EditorLayer::Ground,
+            tool: EditorTool::Paint,
+            ground_varient: 0,
+            wall_varient: 0,
+            rotation: 0,
+            auto_tile: true,
+            stroke: vec![],
+            undo_stack: vec![],
+            redo_stack: vec![],
+            rect_start: None,
+            hovered_tile: None,
+            status: String::new(),
+        }
+    }
+
+    // Turns the editor on or off, starting the view where the player is
+    pub fn toggle(&mut self, player_pos: Vector2) {
+        self.enabled = !self.enabled;
+        self.view_pos = player_pos;
+        self.stroke.clear();
+        self.rect_start = None;
+    }
+
+    pub fn update(
+        &mut self,
+        rl: &mut RaylibHandle,
+        camera: &mut Camera2D,
+        world: &mut World,
+        asset_watcher: &mut AssetWatcher,
+        screen_size: Vector2,
+    ) {
+        let ctrl = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        let shift = rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT);
+
+        if ctrl {
+            if rl.is_key_pressed(KeyboardKey::KEY_Z) && !shift {
+                self.undo(&mut world.map);
+            } else if rl.is_key_pressed(KeyboardKey::KEY_Y) || rl.is_key_pressed(KeyboardKey::KEY_Z)
+            {
+                self.redo(&mut world.map);
+            }
+            if rl.is_key_pressed(KeyboardKey::KEY_S) {
+                self.status = match world.map.save_to_source() {
+                    Ok(()) => {
+                        // The map in the game is already what was saved, no need to reload it
+                        asset_watcher.mark_saved(&world.map_path);
+                        format!("Saved to {}", world.map_path)
+                    }
+                    Err(err) => format!("Unable to save map: {err}"),
+                };
+            }
+        } else {
+            self.handle_tool_keys(rl);
+            // Moving the view around
+            let mut direction = Vector2::zero();
+            if rl.is_key_down(KeyboardKey::KEY_W) {
+                direction.y -= 1.0;
+            }
+            if rl.is_key_down(KeyboardKey::KEY_S) {
+                direction.y += 1.0;
+            }
+            if rl.is_key_down(KeyboardKey::KEY_A) {
+                direction.x -= 1.0;
+            }
+            if rl.is_key_down(KeyboardKey::KEY_D) {
+                direction.x += 1.0;
+            }
+            let speed = if shift { 3.0 } else { 1.0 } * EDITOR_PAN_SPEED / camera.zoom.max(1.0);
+            self.view_pos += direction.normalized() * speed * rl.get_frame_time();
+        }
+        camera.handle_player_controls(rl);
+        camera.track(self.view_pos, screen_size);
+
+        let mouse_pos = camera.to_world(rl.get_mouse_position());
+        self.hovered_tile = if mouse_pos.x >= 0.0
+            && mouse_pos.y >= 0.0
+            && mouse_pos.x < world.map.width as f32 * TILE_SIZE
+            && mouse_pos.y < world.map.height as f32 * TILE_SIZE
+        {
+            Some((
+                (mouse_pos.x / TILE_SIZE) as u32,
+                (mouse_pos.y / TILE_SIZE) as u32,
+            ))
+        } else {
+            None
+        };
+        self.handle_mouse(rl, &mut world.map);
+    }
+
+    fn handle_tool_keys(&mut self, rl: &mut RaylibHandle) {
+        if rl.is_key_pressed(KeyboardKey::KEY_TAB) {
+            self.layer = match self.layer {
+                EditorLayer::Ground => EditorLayer::Walls,
+                EditorLayer::Walls => EditorLayer::Ground,
+            };
+        }
+        let varient_count = match self.layer {
+            EditorLayer::Ground => GROUND_VARIENTS.len(),
+            EditorLayer::Walls => WALL_VARIENTS.len(),
+        };
+        let varient = match self.layer {
+            EditorLayer::Ground => &mut self.ground_varient,
+            EditorLayer::Walls => &mut self.wall_varient,
+        };
+        if rl.is_key_pressed(KeyboardKey::KEY_Q) {
+            *varient = (*varient + varient_count - 1) % varient_count;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_E) {
+            *varient = (*varient + 1) % varient_count;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_R) {
+            self.rotation = (self.rotation + 1) % 4;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_C) {
+            self.auto_tile = !self.auto_tile;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_ONE) {
+            self.tool = EditorTool::Paint;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_TWO) {
+            self.tool = EditorTool::Rectangle;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_THREE) {
+            self.tool = EditorTool::Eyedropper;
+        }
+    }
+
+    // Left mouse paints, right mouse erases walls
+    fn handle_mouse(&mut self, rl: &RaylibHandle, map: &mut WorldMap) {
+        let left = MouseButton::MOUSE_BUTTON_LEFT;
+        let right = MouseButton::MOUSE_BUTTON_RIGHT;
+        match self.tool {
+            EditorTool::Paint => {
+                if let Some((x, y)) = self.hovered_tile {
+                    if rl.is_mouse_button_down(left) {
+                        self.edit_tile(map, x, y, false);
+                    } else if rl.is_mouse_button_down(right) {
+                        self.edit_tile(map, x, y, true);
+                    }
+                }
+            }
+            EditorTool::Rectangle => {
+                if let Some(tile) = self.hovered_tile {
+                    if rl.is_mouse_button_pressed(left) {
+                        self.rect_start = Some((tile, false));
+                    } else if rl.is_mouse_button_pressed(right) {
+                        self.rect_start = Some((tile, true));
+                    }
+                }
+                if let Some((start, erase)) = self.rect_start {
+                    let released = if erase {
+                        rl.is_mouse_button_released(right)
+                    } else {
+                        rl.is_mouse_button_released(left)
+                    };
+                    if released {
+                        if let Some(end) = self.hovered_tile {
+                            self.fill_rect(map, start, end, erase);
+                        }
+                        self.rect_start = None;
+                    }
+                }
+            }
+            EditorTool::Eyedropper => {
+                if let Some((x, y)) = self.hovered_tile {
+                    if rl.is_mouse_button_pressed(left) {
+                        self.pick_tile(map, x, y);
+                    }
+                }
+            }
+        }
+
+        // A stroke ends once no button is held
+        if !rl.is_mouse_button_down(left)
+            && !rl.is_mouse_button_down(right)
+            && !self.stroke.is_empty()
+        {
+            self.undo_stack.push(std::mem::take(&mut self.stroke));
+            self.redo_stack.clear();
+        }
+    }
+
+    // Fills a rectangle of tiles. With auto tiling, walls only go around the edge so that a
+    // rectangle makes a room
+    fn fill_rect(&mut self, map: &mut WorldMap, start: (u32, u32), end: (u32, u32), erase: bool) {
+        let range = get_rect_range(start, end);
+        let outline_only = self.layer == EditorLayer::Walls && self.auto_tile && !erase;
+        for (x, y) in range.iter() {
+            let on_edge = x == range.x.start
+                || x + 1 == range.x.end
+                || y == range.y.start
+                || y + 1 == range.y.end;
+            if on_edge || !outline_only {
+                self.edit_tile(map, x, y, erase);
+            }
+        }
+    }
+
+    // Selects the ground or wall of a tile and switches back to painting
+    fn pick_tile(&mut self, map: &WorldMap, x: u32, y: u32) {
+        match self.layer {
+            EditorLayer::Ground => {
+                let Some(ground) = map.get_ground(x, y) else {
+                    return;
+                };
+                self.ground_varient = GROUND_VARIENTS
+                    .iter()
+                    .position(|varient| *varient as u32 == ground.varient as u32)
+                    .unwrap_or(0);
+                self.rotation = get_rotation_steps(&ground.rotation);
+            }
+            EditorLayer::Walls => {
+                let Some(wall) = map.get_wall(x, y) else {
+                    return;
+                };
+                self.wall_varient = WALL_VARIENTS
+                    .iter()
+                    .position(|varient| *varient == wall.varient)
+                    .unwrap_or(0);
+                self.rotation = get_rotation_steps(&wall.rotation);
+                // Picking a wall means wanting that exact wall
+                self.auto_tile = false;
+            }
+        }
+        self.tool = EditorTool::Paint;
+    }
+
+    // Paints or erases a single tile, recording everything that changed around it
+    fn edit_tile(&mut self, map: &mut WorldMap, x: u32, y: u32, erase: bool) {
+        let Some(ground) = map.get_ground(x, y) else {
+            return;
+        };
+        let brush = self.get_brush();
+        // Skip tiles that already look like the brush, so holding the mouse doesn't redo work
+        let unchanged = match (self.layer, erase) {
+            (EditorLayer::Ground, false) => ground.to_raw_u32() == brush.0,
+            (EditorLayer::Ground, true) => true,
+            (EditorLayer::Walls, false) if self.auto_tile => map.get_wall(x, y).is_some(),
+            (EditorLayer::Walls, false) => map.get_raw_wall(x, y) == brush.1,
+            (EditorLayer::Walls, true) => map.get_raw_wall(x, y) == 0,
+        };
+        if unchanged {
+            return;
+        }
+
+        // Auto tiling can change the walls next to the tile too
+        let area = TileRange {
+            x: x.saturating_sub(1)..(x + 2).min(map.width),
+            y: y.saturating_sub(1)..(y + 2).min(map.height),
+        };
+        let before = get_tile_states(map, &area);
+        match (self.layer, erase) {
+            (EditorLayer::Ground, _) => map.set_ground(x, y, parse_ground(brush.0)),
+            (EditorLayer::Walls, false) if self.auto_tile => map.build_wall(
+                x,
+                y,
+                WallStyle::from_varient(WALL_VARIENTS[self.wall_varient]),
+            ),
+            (EditorLayer::Walls, false) => map.set_wall(x, y, parse_wall(brush.1)),
+            (EditorLayer::Walls, true) if self.auto_tile => map.destroy_wall(x, y),
+            (EditorLayer::Walls, true) => map.set_wall(x, y, None),
+        }
+        let after = get_tile_states(map, &area);
+        for (((x, y), before), (_, after)) in before.into_iter().zip(after) {
+            if before != after {
+                self.stroke.push(TileEdit {
+                    x,
+                    y,
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+
+    fn undo(&mut self, map: &mut WorldMap) {
+        if let Some(edits) = self.undo_stack.pop() {
+            for edit in edits.iter().rev() {
+                set_tile_state(map, edit.x, edit.y, edit.before);
+            }
+            self.redo_stack.push(edits);
+        }
+    }
+
+    fn redo(&mut self, map: &mut WorldMap) {
+        if let Some(edits) = self.redo_stack.pop() {
+            for edit in edits.iter() {
+                set_tile_state(map, edit.x, edit.y, edit.after);
+            }
+            self.undo_stack.push(edits);
+        }
+    }
+
+    // Raw ids of the ground and wall the editor paints with
+    fn get_brush(&self) -> (u32, u32) {
+        let ground = Ground {
+            varient: GROUND_VARIENTS[self.ground_varient],
+            rotation: get_rotation(self.rotation),
+        };
+        let wall = Wall {
+            varient: WALL_VARIENTS[self.wall_varient],
+            rotation: get_rotation(self.rotation),
+        };
+        (ground.to_raw_u32(), wall.to_raw_u32())
+    }
+
+    // Draws the hovered tile, the rectangle being dragged and what the editor is set to
+    pub fn draw(&self, d: &mut RaylibDrawHandle, camera: &Camera2D) {
+        let tile_rect = |range: &TileRange| {
+            camera.to_screen_rect(&Rectangle::new(
+                range.x.start as f32 * TILE_SIZE,
+                range.y.start as f32 * TILE_SIZE,
+                range.x.len() as f32 * TILE_SIZE,
+                range.y.len() as f32 * TILE_SIZE,
+            ))
+        };
+        if let Some(hovered) = self.hovered_tile {
+            let range = match self.rect_start {
+                Some((start, _)) => get_rect_range(start, hovered),
+                None => get_rect_range(hovered, hovered),
+            };
+            d.draw_rectangle_lines_ex(tile_rect(&range), 2.0, Color::YELLOW);
+        }
+
+        let font_size = 30;
+        let varient = match self.layer {
+            EditorLayer::Ground => format!("{:?}", GROUND_VARIENTS[self.ground_varient]),
+            EditorLayer::Walls if self.auto_tile => {
+                format!(
+                    "{:?} (auto tiled)",
+                    WallStyle::from_varient(WALL_VARIENTS[self.wall_varient])
+                )
+            }
+            EditorLayer::Walls => format!("{:?}", WALL_VARIENTS[self.wall_varient]),
+        };
+        let lines = [
+            format!(
+                "EDITOR  {:?} | {:?} | {} | {} deg",
+                self.layer,
+                self.tool,
+                varient,
+                self.rotation * 90
+            ),
+            "TAB layer, Q/E varient, R rotate, C auto tile, 1/2/3 paint/rect/pick".to_string(),
+            "Ctrl+Z undo, Ctrl+Y redo, Ctrl+S save, F2 exit".to_string(),
+            self.status.clone(),
+        ];
+        let bottom = d.get_screen_height();
+        for (i, line) in lines.iter().enumerate() {
+            d.draw_text(
+                line,
+                font_size / 5,
+                bottom - (lines.len() - i) as i32 * font_size,
+                font_size,
+                Color::WHITE,
+            );
+        }
+    }
+}
+
+// Raw ids of the ground and wall of every loaded tile in a range
+fn get_tile_states(map: &WorldMap, range: &TileRange) -> Vec<((u32, u32), (u32, u32))> {
+    map.iter_tiles(range)
+        .map(|tile| {
+            (
+                (tile.x, tile.y),
+                (tile.ground.to_raw_u32(), map.get_raw_wall(tile.x, tile.y)),
+            )
+        })
+        .collect()
+}
+
+fn set_tile_state(map: &mut WorldMap, x: u32, y: u32, (ground, wall): (u32, u32)) {
+    map.set_ground(x, y, parse_ground(ground));
+    map.set_wall(x, y, parse_wall(wall));
+}
+
+// Tiles between two corners of a rectangle, in any order
+fn get_rect_range(start: (u32, u32), end: (u32, u32)) -> TileRange {
+    TileRange {
+        x: start.0.min(end.0)..start.0.max(end.0) + 1,
+        y: start.1.min(end.1)..start.1.max(end.1) + 1,
+    }
+}
+
+fn get_rotation(steps: u32) -> TileRotation {
+    match steps % 4 {
+        0 => TileRotation::None,
+        1 => TileRotation::Two,
+        2 => TileRotation::Three,
+        _ => TileRotation::One,
+    }
+}
+
+fn get_rotation_steps(rotation: &TileRotation) -> u32 {
+    match rotation {
+        TileRotation::None => 0,
+        TileRotation::Two => 1,
+        TileRotation::Three => 2,
+        TileRotation::One => 3,
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{
+    auto_tile::{get_wall_shapes, WallStyle},
+    map_objects::MapObject,
+    world::PLAYER_SPAWN_CLASS,
+    Ground, GroundVarient, TileRotation, Wall, WorldMap, OBJECT_FIRST_GID, TILE_SIZE,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raylib::prelude::*;
+use std::{collections::HashMap, ops::Range};
+
+// Tiles kept free of rooms around the edge of the map
+const MAP_MARGIN: i32 = 4;
+// Minimum amount of tiles between two rooms
+const ROOM_GAP: i32 = 3;
+// Width of the dirt paths between rooms
+const PATH_WIDTH: i32 = 2;
+// Props that can be placed in rooms, from the object tile sheet
+const PROP_GIDS: Range<u32> = OBJECT_FIRST_GID..OBJECT_FIRST_GID + 3;
+const ROOM_LIGHT_COLOR: &str = "#ffffd8a8";
+
+// Generates outdoor maps with buildings connected by dirt paths. The same seed and settings
+// always give the same map.
+pub struct MapGenerator {
+    pub seed: u64,
+    pub width: u32,
+    pub height: u32,
+    // Amount of times to try placing a room, rooms that don't fit are skipped
+    pub room_attempts: u32,
+    // Size of rooms in tiles, including their walls
+    pub room_size: Range<u32>,
+    pub props_per_room: Range<u32>,
+}
+
+// A building, rectangular or L shaped when a corner is cut out. Positions are in tiles.
+struct Room {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    // Corner cut out of the room (x, y, width, height)
+    notch: Option<(i32, i32, i32, i32)>,
+    style: WallStyle,
+    doors: Vec<(i32, i32)>,
+}
+
+impl Room {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        let in_rect = |(rect_x, rect_y, width, height): (i32, i32, i32, i32)| {
+            x >= rect_x && x < rect_x + width && y >= rect_y && y < rect_y + height
+        };
+        in_rect((self.x, self.y, self.width, self.height)) && !self.notch.is_some_and(in_rect)
+    }
+
+    fn overlaps(&self, other: &Room, gap: i32) -> bool {
+        self.x - gap < other.x + other.width
+            && other.x - gap < self.x + self.width
+            && self.y - gap < other.y + other.height
+            && other.y - gap < self.y + self.height
+    }
+
+    fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    fn tiles(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (self.y..self.y + self.height)
+            .flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+            .filter(|(x, y)| self.contains(*x, *y))
+    }
+
+    // Directions from a tile that lead out of the room
+    fn get_outside_sides(&self, x: i32, y: i32) -> Vec<(i32, i32)> {
+        [(0, -1), (0, 1), (-1, 0), (1, 0)]
+            .into_iter()
+            .filter(|(dir_x, dir_y)| !self.contains(x + dir_x, y + dir_y))
+            .collect()
+    }
+
+    // Tiles of the room that get walls, every tile on its border except doors
+    fn is_wall(&self, x: i32, y: i32) -> bool {
+        let is_border = (-1..=1)
+            .flat_map(|dir_y| (-1..=1).map(move |dir_x| (dir_x, dir_y)))
+            .any(|(dir_x, dir_y)| !self.contains(x + dir_x, y + dir_y));
+        self.contains(x, y) && is_border && !self.doors.contains(&(x, y))
+    }
+}
+
+impl MapGenerator {
+    pub fn new(seed: u64, width: u32, height: u32) -> MapGenerator {
+        MapGenerator {
+            seed,
+            width,
+            height,
+            room_attempts: width * height / 64,
+            room_size: 6..14,
+            props_per_room: 1..4,
+        }
+    }
+
+    pub fn generate(&self) -> WorldMap {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let (width, height) = (self.width as i32, self.height as i32);
+
+        // Outside is grass with some patches of dirt
+        let mut ground =
+            vec![vec![GroundVarient::Grass; self.width as usize]; self.height as usize];
+        for _ in 0..self.width * self.height / 150 {
+            let center = (rng.gen_range(0..width), rng.gen_range(0..height));
+            let radius = rng.gen_range(1.5..4.0f32);
+            let reach = radius.ceil() as i32;
+            for y in center.1 - reach..=center.1 + reach {
+                for x in center.0 - reach..=center.0 + reach {
+                    let distance = (((x - center.0).pow(2) + (y - center.1).pow(2)) as f32).sqrt();
+                    if distance <= radius && x >= 0 && x < width && y >= 0 && y < height {
+                        ground[y as usize][x as usize] = GroundVarient::Dirt;
+                    }
+                }
+            }
+        }
+
+        let mut rooms = self.place_rooms(&mut rng);
+
+        // Connect every room to the closest room placed before it, so every room can be
+        // reached by following the paths
+        let mut paths = vec![];
+        for i in 1..rooms.len() {
+            let center = rooms[i].center();
+            let closest = (0..i)
+                .min_by_key(|j| {
+                    let other = rooms[*j].center();
+                    (other.0 - center.0).pow(2) + (other.1 - center.1).pow(2)
+                })
+                .unwrap();
+            let to_closest = (
+                rooms[closest].center().0 - center.0,
+                rooms[closest].center().1 - center.1,
+            );
+            let start = add_door(&mut rooms[i], to_closest, &mut rng);
+            let end = add_door(
+                &mut rooms[closest],
+                (-to_closest.0, -to_closest.1),
+                &mut rng,
+            );
+            paths.push((start, end));
+        }
+        for room in rooms.iter_mut().filter(|room| room.doors.is_empty()) {
+            let dir = [(0, -1), (0, 1), (-1, 0), (1, 0)][rng.gen_range(0..4)];
+            add_door(room, dir, &mut rng);
+        }
+
+        // Dirt paths bending once between the doors
+        let in_room = |x: i32, y: i32| rooms.iter().any(|room| room.contains(x, y));
+        for (start, end) in paths {
+            let corner = if rng.gen_bool(0.5) {
+                (end.0, start.1)
+            } else {
+                (start.0, end.1)
+            };
+            for (from, to) in [(start, corner), (corner, end)] {
+                for y in from.1.min(to.1)..=from.1.max(to.1) + PATH_WIDTH - 1 {
+                    for x in from.0.min(to.0)..=from.0.max(to.0) + PATH_WIDTH - 1 {
+                        if x >= 0 && x < width && y >= 0 && y < height && !in_room(x, y) {
+                            ground[y as usize][x as usize] = GroundVarient::Dirt;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut wall_mask = vec![vec![false; self.width as usize]; self.height as usize];
+        let mut wall_styles =
+            vec![vec![WallStyle::Plain; self.width as usize]; self.height as usize];
+        let mut objects = vec![];
+        for room in rooms.iter() {
+            for (x, y) in room.tiles() {
+                ground[y as usize][x as usize] = GroundVarient::Wood;
+                wall_mask[y as usize][x as usize] = room.is_wall(x, y);
+                wall_styles[y as usize][x as usize] = room.style;
+            }
+            self.place_room_objects(room, &mut objects, &mut rng);
+        }
+        // Player starts in the first room, as close to its middle as the notch allows
+        if let Some((x, y)) = rooms.first().and_then(|room| {
+            let center = room.center();
+            room.tiles()
+                .filter(|(x, y)| !room.is_wall(*x, *y))
+                .min_by_key(|(x, y)| (x - center.0).pow(2) + (y - center.1).pow(2))
+        }) {
+            objects.push(MapObject {
+                id: objects.len() as u32 + 1,
+                name: String::new(),
+                class: PLAYER_SPAWN_CLASS.to_string(),
+                layer: "Spawns".to_string(),
+                rect: Rectangle::new(
+                    (x as f32 + 0.5) * TILE_SIZE,
+                    (y as f32 + 0.5) * TILE_SIZE,
+                    0.0,
+                    0.0,
+                ),
+                gid: None,
+                is_point: true,
+                polygon: vec![],
+                properties: HashMap::new(),
+            });
+        }
+        let walls = get_wall_shapes(&wall_mask)
+            .into_iter()
+            .zip(wall_styles)
+            .map(|(shapes, styles)| {
+                shapes
+                    .into_iter()
+                    .zip(styles)
+                    .map(|(shape, style)| shape.map(|shape| shape.to_wall(style)))
+                    .collect()
+            })
+            .collect::<Vec<Vec<Option<Wall>>>>();
+
+        WorldMap::from_tiles(
+            ground
+                .into_iter()
+                .map(|line| {
+                    line.into_iter()
+                        .map(|varient| Ground {
+                            varient,
+                            rotation: TileRotation::None,
+                        })
+                        .collect()
+                })
+                .collect(),
+            walls,
+            objects,
+        )
+    }
+
+    fn place_rooms(&self, rng: &mut StdRng) -> Vec<Room> {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let mut rooms: Vec<Room> = vec![];
+        for _ in 0..self.room_attempts {
+            let room_width = rng.gen_range(self.room_size.clone()) as i32;
+            let room_height = rng.gen_range(self.room_size.clone()) as i32;
+            if room_width + MAP_MARGIN * 2 >= width || room_height + MAP_MARGIN * 2 >= height {
+                continue;
+            }
+            let mut room = Room {
+                x: rng.gen_range(MAP_MARGIN..width - MAP_MARGIN - room_width),
+                y: rng.gen_range(MAP_MARGIN..height - MAP_MARGIN - room_height),
+                width: room_width,
+                height: room_height,
+                notch: None,
+                style: if rng.gen_bool(0.5) {
+                    WallStyle::White
+                } else {
+                    WallStyle::Plain
+                },
+                doors: vec![],
+            };
+            if rooms.iter().any(|other| room.overlaps(other, ROOM_GAP)) {
+                continue;
+            }
+
+            // Cut a corner out of some rooms, leaving the rest at least 4 tiles wide
+            if room_width >= 6 && room_height >= 6 && rng.gen_bool(0.3) {
+                let notch_width = rng.gen_range(2..=room_width - 4);
+                let notch_height = rng.gen_range(2..=room_height - 4);
+                let notch_x = if rng.gen_bool(0.5) {
+                    room.x
+                } else {
+                    room.x + room_width - notch_width
+                };
+                let notch_y = if rng.gen_bool(0.5) {
+                    room.y
+                } else {
+                    room.y + room_height - notch_height
+                };
+                room.notch = Some((notch_x, notch_y, notch_width, notch_height));
+            }
+            rooms.push(room);
+        }
+        rooms
+    }
+
+    // Adds props and a light to a room
+    fn place_room_objects(&self, room: &Room, objects: &mut Vec<MapObject>, rng: &mut StdRng) {
+        // Only tiles away from the walls
+        let free_tiles = room
+            .tiles()
+            .filter(|(x, y)| {
+                room.get_outside_sides(*x, *y).is_empty()
+                    && [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+                        .iter()
+                        .all(|(dir_x, dir_y)| room.contains(x + dir_x, y + dir_y))
+            })
+            .collect::<Vec<(i32, i32)>>();
+        if free_tiles.is_empty() {
+            return;
+        }
+
+        for _ in 0..rng.gen_range(self.props_per_room.clone()) {
+            let (x, y) = free_tiles[rng.gen_range(0..free_tiles.len())];
+            objects.push(MapObject {
+                id: objects.len() as u32 + 1,
+                name: String::new(),
+                class: "prop".to_string(),
+                layer: "Objects".to_string(),
+                rect: Rectangle::new(
+                    x as f32 * TILE_SIZE,
+                    y as f32 * TILE_SIZE,
+                    TILE_SIZE,
+                    TILE_SIZE,
+                ),
+                gid: Some(rng.gen_range(PROP_GIDS)),
+                is_point: false,
+                polygon: vec![],
+                properties: HashMap::new(),
+            });
+        }
+
+        // Light in the middle of the free tiles
+        let (sum_x, sum_y) = free_tiles
+            .iter()
+            .fold((0, 0), |sum, (x, y)| (sum.0 + x, sum.1 + y));
+        let center = Vector2::new(
+            (sum_x as f32 / free_tiles.len() as f32 + 0.5) * TILE_SIZE,
+            (sum_y as f32 / free_tiles.len() as f32 + 0.5) * TILE_SIZE,
+        );
+        objects.push(MapObject {
+            id: objects.len() as u32 + 1,
+            name: String::new(),
+            class: "light".to_string(),
+            layer: "Lights".to_string(),
+            rect: Rectangle::new(center.x, center.y, 0.0, 0.0),
+            gid: None,
+            is_point: true,
+            polygon: vec![],
+            properties: HashMap::from([
+                (
+                    "radius".to_string(),
+                    (room.width.max(room.height) as f32 * TILE_SIZE * 0.6).to_string(),
+                ),
+                ("color".to_string(), ROOM_LIGHT_COLOR.to_string()),
+            ]),
+        });
+    }
+}
+
+// Opens a 2 tile wide door in the side of a room facing a direction, returning the tile just
+// outside of it
+fn add_door(room: &mut Room, towards: (i32, i32), rng: &mut StdRng) -> (i32, i32) {
+    let facing: (i32, i32) = if towards.0.abs() > towards.1.abs() {
+        (towards.0.signum(), 0)
+    } else {
+        (0, if towards.1 < 0 { -1 } else { 1 })
+    };
+    let along = (facing.1.abs(), facing.0.abs());
+    // Both tiles of the door need a straight wall on the facing side
+    let is_straight =
+        |x: i32, y: i32| room.contains(x, y) && room.get_outside_sides(x, y) == [facing];
+    let get_candidates = |length: i32| {
+        room.tiles()
+            .filter(|(x, y)| (0..length).all(|i| is_straight(x + along.0 * i, y + along.1 * i)))
+            .collect::<Vec<(i32, i32)>>()
+    };
+    // Prefer keeping a straight wall on both sides of the door so the walls next to it end
+    // cleanly. Every side of a room has at least one straight part 2 tiles long.
+    let flanked = get_candidates(4);
+    let (x, y) = if flanked.is_empty() {
+        let candidates = get_candidates(2);
+        candidates[rng.gen_range(0..candidates.len())]
+    } else {
+        let (x, y) = flanked[rng.gen_range(0..flanked.len())];
+        (x + along.0, y + along.1)
+    };
+    room.doors.push((x, y));
+    room.doors.push((x + along.0, y + along.1));
+    (x + facing.0, y + facing.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{navigation::NavGrid, Player};
+    use std::collections::BTreeMap;
+
+    type MapDescription = (Vec<(u32, u32)>, Vec<(String, BTreeMap<String, String>)>);
+
+    // Everything about a map that a seed decides, in a form that can be compared. Properties
+    // are kept apart and sorted, hash maps don't keep an order.
+    fn describe(map: &WorldMap) -> MapDescription {
+        let tiles = map
+            .iter_tiles(&map.get_full_tile_range())
+            .map(|tile| (tile.ground.to_raw_u32(), map.get_raw_wall(tile.x, tile.y)))
+            .collect();
+        let objects = map
+            .objects
+            .iter()
+            .map(|object| {
+                let without_properties = MapObject {
+                    properties: HashMap::new(),
+                    ..object.clone()
+                };
+                (
+                    format!("{without_properties:?}"),
+                    object.properties.clone().into_iter().collect(),
+                )
+            })
+            .collect();
+        (tiles, objects)
+    }
+
+    #[test]
+    fn same_seed_gives_same_map() {
+        let map = MapGenerator::new(7, 64, 64).generate();
+        let again = MapGenerator::new(7, 64, 64).generate();
+        assert!(describe(&map) == describe(&again));
+        let other = MapGenerator::new(8, 64, 64).generate();
+        assert!(describe(&map) != describe(&other));
+    }
+
+    #[test]
+    fn every_room_floor_can_be_walked_to_from_spawn() {
+        for seed in 0..3 {
+            let map = MapGenerator::new(seed, 48, 48).generate();
+            let spawn = map
+                .get_player_spawn()
+                .expect("Map should have a player spawn");
+            let mut nav_grid = NavGrid::new(Player::COLLIDER_SIZE / 2.0);
+            nav_grid.update(&map, &[]);
+            // Leads back to the spawn from everywhere it can be walked to from
+            let flow_field = nav_grid.build_flow_field(spawn, map.width.max(map.height));
+            let room_floors = map
+                .iter_tiles(&map.get_full_tile_range())
+                .filter(|tile| {
+                    matches!(tile.ground.varient, GroundVarient::Wood) && tile.wall.is_none()
+                })
+                .collect::<Vec<_>>();
+            assert!(!room_floors.is_empty());
+            for tile in room_floors {
+                let center = tile.world_pos() + Vector2::new(TILE_SIZE, TILE_SIZE) / 2.0;
+                assert!(
+                    center == spawn || flow_field.get_direction(&nav_grid, center).is_some(),
+                    "Seed {seed}: tile ({}, {}) can't be reached",
+                    tile.x,
+                    tile.y
+                );
+            }
+        }
+    }
+}
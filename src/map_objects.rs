@@ -0,0 +1,398 @@
+use crate::{
+    tile::{Circle, Collider},
+    world::{
+        ENEMY_SPAWNER_CLASS, EXTRACTION_CLASS, LIGHT_CLASS, PLAYER_SPAWN_CLASS, TRANSITION_CLASS,
+        TRIGGER_CLASS,
+    },
+    world_map::get_xml_attribute,
+};
+use raylib::prelude::*;
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+// An object placed in one of the object layers of a map in Tiled
+#[derive(Debug, Clone)]
+pub struct MapObject {
+    pub id: u32,
+    pub name: String,
+    // The objects class in Tiled, used to tell what the object is for
+    pub class: String,
+    // Name of the object layer the object is in
+    pub layer: String,
+    pub rect: Rectangle,
+    pub gid: Option<u32>,
+    pub is_point: bool,
+    // Points of the outline of polygon objects relative to the top left of the rect, empty for
+    // every other kind of object
+    pub polygon: Vec<Vector2>,
+    pub properties: HashMap<String, String>,
+}
+
+// What an object is for, worked out from its class and properties
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectKind {
+    // Where the player starts when the map is loaded without a transition
+    PlayerSpawn,
+    // Spawns enemies of a type over time, up to a number of them at once
+    EnemySpawner {
+        enemy: String,
+        count: u32,
+        interval: f32,
+    },
+    // Area that fires events when things go in and out of it
+    Trigger,
+    // Area the player has to get to with their loot
+    ExtractionZone,
+    Transition,
+    Light,
+    Other,
+}
+
+// An object layer of a map, objects refer to it by name
+#[derive(Debug, Clone)]
+pub struct ObjectLayer {
+    pub id: u32,
+    pub name: String,
+}
+
+impl MapObject {
+    pub fn get_property(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(|value| value.as_str())
+    }
+
+    // Works out what the object is for. Fails if a property it needs is missing or invalid.
+    pub fn get_kind(&self) -> Result<ObjectKind, String> {
+        let name = || {
+            if self.name.is_empty() {
+                format!("Object {}", self.id)
+            } else {
+                format!("Object {} ({})", self.id, self.name)
+            }
+        };
+        Ok(match self.class.as_str() {
+            PLAYER_SPAWN_CLASS => ObjectKind::PlayerSpawn,
+            ENEMY_SPAWNER_CLASS => ObjectKind::EnemySpawner {
+                enemy: self
+                    .get_property("enemy")
+                    .ok_or_else(|| format!("{} is an enemy spawner without an enemy", name()))?
+                    .to_string(),
+                count: match self.get_property("count") {
+                    Some(count) => count.parse::<u32>().map_err(|_| {
+                        format!("count of {} isn't a whole number: {count}", name())
+                    })?,
+                    None => 1,
+                },
+                interval: match self.get_property("interval") {
+                    Some(interval) => interval.parse::<f32>().map_err(|_| {
+                        format!("interval of {} isn't a number: {interval}", name())
+                    })?,
+                    None => 5.0,
+                },
+            },
+            TRIGGER_CLASS => ObjectKind::Trigger,
+            EXTRACTION_CLASS => ObjectKind::ExtractionZone,
+            TRANSITION_CLASS => ObjectKind::Transition,
+            LIGHT_CLASS => ObjectKind::Light,
+            _ => ObjectKind::Other,
+        })
+    }
+
+    // Whether things can be inside the object, points have no area
+    pub fn has_area(&self) -> bool {
+        !self.is_point
+            && (self.polygon.len() >= 3 || (self.rect.width > 0.0 && self.rect.height > 0.0))
+    }
+
+    // Smallest rect containing the whole object
+    pub fn get_bounds(&self) -> Rectangle {
+        let Some(first) = self.polygon.first() else {
+            return self.rect;
+        };
+        let (mut min, mut max) = (*first, *first);
+        for point in self.polygon.iter().skip(1) {
+            min.x = min.x.min(point.x);
+            min.y = min.y.min(point.y);
+            max.x = max.x.max(point.x);
+            max.y = max.y.max(point.y);
+        }
+        Rectangle::new(
+            self.rect.x + min.x,
+            self.rect.y + min.y,
+            max.x - min.x,
+            max.y - min.y,
+        )
+    }
+
+    pub fn get_center(&self) -> Vector2 {
+        let bounds = self.get_bounds();
+        Vector2::new(
+            bounds.x + bounds.width / 2.0,
+            bounds.y + bounds.height / 2.0,
+        )
+    }
+
+    // Footprint of the object that things can't go through, set with its collider property.
+    // Circles fit inside the object and polygons use its outline, which has to be convex.
+    pub fn get_collider(&self) -> Result<Option<Collider>, String> {
+        let Some(collider) = self.get_property("collider") else {
+            return Ok(None);
+        };
+        let bounds = self.get_bounds();
+        let origin = Vector2::new(self.rect.x, self.rect.y);
+        Ok(Some(match collider {
+            "rect" => Collider::from_rects(vec![bounds]),
+            "circle" => Collider::from_circle(Circle {
+                x: bounds.x + bounds.width / 2.0,
+                y: bounds.y + bounds.height / 2.0,
+                radius: bounds.width.min(bounds.height) / 2.0,
+            }),
+            "polygon" if self.polygon.len() >= 3 => Collider {
+                polygons: vec![self.polygon.iter().map(|point| origin + *point).collect()],
+                ..Default::default()
+            },
+            "polygon" => {
+                return Err(format!(
+                    "Object {} has a polygon collider but isn't a polygon",
+                    self.id
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "Object {} has an unknown collider: {collider}",
+                    self.id
+                ))
+            }
+        }))
+    }
+
+    pub fn contains(&self, world_pos: Vector2) -> bool {
+        if !self.has_area() {
+            return false;
+        }
+        if self.polygon.is_empty() {
+            return self.rect.check_collision_point_rec(world_pos);
+        }
+        // Counts how many edges a line going right from the point crosses, odd means inside
+        let pos = world_pos - Vector2::new(self.rect.x, self.rect.y);
+        let mut inside = false;
+        for (i, start) in self.polygon.iter().enumerate() {
+            let end = self.polygon[(i + 1) % self.polygon.len()];
+            if (start.y > pos.y) != (end.y > pos.y)
+                && pos.x < start.x + (pos.y - start.y) / (end.y - start.y) * (end.x - start.x)
+            {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+// Reads every object layer and the objects in them from a .tmx file. Expects the layout Tiled
+// saves with, one element per line.
+pub fn read_map_objects(reader: impl BufRead) -> (Vec<ObjectLayer>, Vec<MapObject>) {
+    let mut layers = vec![];
+    let mut objects = vec![];
+    let mut layer = String::new();
+    let mut current_object: Option<MapObject> = None;
+
+    for line in reader.lines() {
+        let line = line.unwrap();
+        let element = line.trim();
+        if element.starts_with("<objectgroup") {
+            layer = get_xml_attribute(element, "name")
+                .map(unescape_xml)
+                .unwrap_or_default();
+            layers.push(ObjectLayer {
+                id: get_xml_attribute(element, "id")
+                    .and_then(|id| id.parse::<u32>().ok())
+                    .unwrap_or(0),
+                name: layer.clone(),
+            });
+        } else if element.starts_with("<object ") {
+            let get_f32 = |attribute: &str| {
+                get_xml_attribute(element, attribute)
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .unwrap_or(0.0)
+            };
+            let gid = get_xml_attribute(element, "gid").and_then(|gid| gid.parse::<u32>().ok());
+            let (width, height) = (get_f32("width"), get_f32("height"));
+            let object = MapObject {
+                id: get_xml_attribute(element, "id")
+                    .and_then(|id| id.parse::<u32>().ok())
+                    .unwrap_or(0),
+                name: get_xml_attribute(element, "name")
+                    .map(unescape_xml)
+                    .unwrap_or_default(),
+                // Older versions of Tiled save the class as type
+                class: get_xml_attribute(element, "type")
+                    .or(get_xml_attribute(element, "class"))
+                    .map(unescape_xml)
+                    .unwrap_or_default(),
+                layer: layer.clone(),
+                rect: Rectangle {
+                    x: get_f32("x"),
+                    // Tile objects are positioned by their bottom left corner
+                    y: get_f32("y") - if gid.is_some() { height } else { 0.0 },
+                    width,
+                    height,
+                },
+                gid,
+                is_point: false,
+                polygon: vec![],
+                properties: HashMap::new(),
+            };
+            if element.ends_with("/>") {
+                objects.push(object);
+            } else {
+                current_object = Some(object);
+            }
+        } else if let Some(object) = current_object.as_mut() {
+            if element.starts_with("<property ") {
+                if let (Some(name), Some(value)) = (
+                    get_xml_attribute(element, "name"),
+                    get_xml_attribute(element, "value"),
+                ) {
+                    object
+                        .properties
+                        .insert(unescape_xml(name), unescape_xml(value));
+                }
+            } else if element.starts_with("<point") {
+                object.is_point = true;
+            } else if element.starts_with("<polygon ") {
+                // Saved as space separated x,y pairs
+                object.polygon = get_xml_attribute(element, "points")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .filter_map(|point| {
+                        let (x, y) = point.split_once(',')?;
+                        Some(Vector2::new(x.parse().ok()?, y.parse().ok()?))
+                    })
+                    .collect();
+            } else if element.starts_with("</object>") {
+                objects.extend(current_object.take());
+            }
+        }
+    }
+    (layers, objects)
+}
+
+// Writes the object layers and their objects in the same layout they are read with
+pub fn write_map_objects(
+    writer: &mut impl Write,
+    layers: &[ObjectLayer],
+    objects: &[MapObject],
+) -> std::io::Result<()> {
+    for layer in layers {
+        write!(
+            writer,
+            " <objectgroup id=\"{}\" name=\"{}\"",
+            layer.id,
+            escape_xml(&layer.name)
+        )?;
+        if !objects.iter().any(|object| object.layer == layer.name) {
+            writeln!(writer, "/>")?;
+            continue;
+        }
+        writeln!(writer, ">")?;
+        for object in objects.iter().filter(|object| object.layer == layer.name) {
+            write!(writer, "  <object id=\"{}\"", object.id)?;
+            if !object.name.is_empty() {
+                write!(writer, " name=\"{}\"", escape_xml(&object.name))?;
+            }
+            if !object.class.is_empty() {
+                write!(writer, " type=\"{}\"", escape_xml(&object.class))?;
+            }
+            if let Some(gid) = object.gid {
+                write!(writer, " gid=\"{gid}\"")?;
+            }
+            // Tile objects are positioned by their bottom left corner
+            let y = object.rect.y
+                + if object.gid.is_some() {
+                    object.rect.height
+                } else {
+                    0.0
+                };
+            write!(writer, " x=\"{}\" y=\"{}\"", object.rect.x, y)?;
+            if object.rect.width != 0.0 || object.rect.height != 0.0 {
+                write!(
+                    writer,
+                    " width=\"{}\" height=\"{}\"",
+                    object.rect.width, object.rect.height
+                )?;
+            }
+
+            if object.properties.is_empty() && !object.is_point && object.polygon.is_empty() {
+                writeln!(writer, "/>")?;
+                continue;
+            }
+            writeln!(writer, ">")?;
+            if !object.properties.is_empty() {
+                writeln!(writer, "   <properties>")?;
+                // Sorted so that saving the same map twice gives the same file
+                let mut properties = object.properties.iter().collect::<Vec<_>>();
+                properties.sort();
+                for (name, value) in properties {
+                    writeln!(
+                        writer,
+                        "    <property name=\"{}\" value=\"{}\"/>",
+                        escape_xml(name),
+                        escape_xml(value)
+                    )?;
+                }
+                writeln!(writer, "   </properties>")?;
+            }
+            if object.is_point {
+                writeln!(writer, "   <point/>")?;
+            }
+            if !object.polygon.is_empty() {
+                let points = object
+                    .polygon
+                    .iter()
+                    .map(|point| format!("{},{}", point.x, point.y))
+                    .collect::<Vec<String>>();
+                writeln!(writer, "   <polygon points=\"{}\"/>", points.join(" "))?;
+            }
+            writeln!(writer, "  </object>")?;
+        }
+        writeln!(writer, " </objectgroup>")?;
+    }
+    Ok(())
+}
+
+// Parses a color property from Tiled, saved as #AARRGGBB or #RRGGBB
+pub fn parse_tiled_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    match hex.len() {
+        6 => Some(Color::new(
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+            255,
+        )),
+        8 => Some(Color::new(
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+            (value >> 24) as u8,
+        )),
+        _ => None,
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn unescape_xml(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
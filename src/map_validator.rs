@@ -0,0 +1,566 @@
+use crate::{
+    get_xml_attribute,
+    map_objects::{read_map_objects, MapObject, ObjectKind},
+    world::{PLAYER_SPAWN_CLASS, TRANSITION_CLASS},
+    world_map::parse_layer_rows,
+    GroundVarient, TileRotation, Wall, WallVarient, FLOOR_FIRST_GID, OBJECT_FIRST_GID, TILE_SIZE,
+    WALL_FIRST_GID,
+};
+use std::{collections::VecDeque, fmt::Display, io::BufReader};
+
+// Line the floor layer data starts on and the lines between the floor and wall layer data, as
+// the game reads them
+const FIRST_DATA_LINE: usize = 8;
+const LINES_BETWEEN_LAYERS: usize = 4;
+
+// Something wrong with a map file, with where it is in the file when known
+pub struct MapProblem {
+    pub line: Option<usize>,
+    pub cell: Option<(u32, u32)>,
+    pub message: String,
+}
+
+impl Display for MapProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {line}: ")?;
+        }
+        if let Some((x, y)) = self.cell {
+            write!(f, "cell ({x}, {y}): ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+// A tile layer as written in the file
+struct TileLayer {
+    name: String,
+    // Line of the first row of data
+    data_line: usize,
+    rows: Vec<Vec<u32>>,
+}
+
+// Which sides of a tile a wall blocks walking through
+#[derive(Clone, Copy, Default)]
+struct BlockedSides {
+    left: bool,
+    right: bool,
+    top: bool,
+    bottom: bool,
+}
+
+// Checks a map file for everything that would make the game fail to load it or play it wrong,
+// reporting every problem instead of stopping at the first like the game does
+pub fn validate_map(path: &str) -> Vec<MapProblem> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => return vec![problem(None, None, format!("Unable to read file: {err}"))],
+    };
+    let lines = contents.lines().collect::<Vec<&str>>();
+    let mut problems = vec![];
+
+    let map_element = lines
+        .iter()
+        .position(|line| line.trim().starts_with("<map"));
+    let get_size = |attribute: &str| {
+        map_element
+            .and_then(|i| get_xml_attribute(lines[i], attribute))
+            .and_then(|value| value.parse::<u32>().ok())
+    };
+    let (Some(width), Some(height)) = (get_size("width"), get_size("height")) else {
+        problems.push(problem(
+            map_element.map(|i| i + 1),
+            None,
+            "Map element is missing its width or height".to_string(),
+        ));
+        return problems;
+    };
+
+    let layers = read_tile_layers(&lines, &mut problems);
+    for layer in layers.iter() {
+        check_layer_size(layer, width, height, &mut problems);
+    }
+    let (Some(floor), Some(walls)) = (layers.first(), layers.get(1)) else {
+        problems.push(problem(
+            None,
+            None,
+            "Map needs a floor and a wall layer".to_string(),
+        ));
+        return problems;
+    };
+    if layers.len() > 2 {
+        problems.push(problem(
+            Some(layers[2].data_line - 2),
+            None,
+            format!(
+                "Only the first two tile layers are read, layer {} is ignored",
+                layers[2].name
+            ),
+        ));
+    }
+    // The game skips a fixed amount of lines to find the layer data
+    if floor.data_line != FIRST_DATA_LINE
+        || walls.data_line != FIRST_DATA_LINE + height as usize + LINES_BETWEEN_LAYERS
+    {
+        problems.push(problem(
+            Some(floor.data_line),
+            None,
+            "Layer data isn't where the game expects it, save the map from Tiled with the \
+             default layout"
+                .to_string(),
+        ));
+    }
+
+    let mut blocked = vec![vec![BlockedSides::default(); width as usize]; height as usize];
+    let mut has_floor = vec![vec![false; width as usize]; height as usize];
+    for (y, row) in floor.rows.iter().enumerate().take(height as usize) {
+        for (x, gid) in row.iter().enumerate().take(width as usize) {
+            let at = (Some(floor.data_line + y), Some((x as u32, y as u32)));
+            if *gid == 0 {
+                problems.push(problem(at.0, at.1, "Tile has no floor".to_string()));
+                continue;
+            }
+            check_flip_flags(*gid, at, &mut problems);
+            let tile = *gid & 0x0FFFFFFF;
+            if !(FLOOR_FIRST_GID..WALL_FIRST_GID).contains(&tile)
+                || GroundVarient::from_raw_u32(tile).is_none()
+            {
+                problems.push(problem(
+                    at.0,
+                    at.1,
+                    format!("Unknown gid {tile} in layer {}", floor.name),
+                ));
+                continue;
+            }
+            has_floor[y][x] = true;
+        }
+    }
+    for (y, row) in walls.rows.iter().enumerate().take(height as usize) {
+        for (x, gid) in row.iter().enumerate().take(width as usize) {
+            let at = (Some(walls.data_line + y), Some((x as u32, y as u32)));
+            if *gid == 0 {
+                continue;
+            }
+            if !check_flip_flags(*gid, at, &mut problems) {
+                continue;
+            }
+            let tile = *gid & 0x0FFFFFFF;
+            if !(WALL_FIRST_GID..OBJECT_FIRST_GID).contains(&tile) {
+                problems.push(problem(
+                    at.0,
+                    at.1,
+                    format!("Unknown gid {tile} in layer {}", walls.name),
+                ));
+                continue;
+            }
+            let Some(varient) = WallVarient::from_raw_u32(tile) else {
+                problems.push(problem(
+                    at.0,
+                    at.1,
+                    format!("Wall tile {tile} has no collider, players walk through it"),
+                ));
+                continue;
+            };
+            let wall = Wall {
+                varient,
+                rotation: TileRotation::from_raw_u32(*gid),
+            };
+            blocked[y][x] = get_blocked_sides(&wall);
+        }
+    }
+
+    let (_, objects) = read_map_objects(BufReader::new(contents.as_bytes()));
+    check_objects(&lines, &objects, width, height, &mut problems);
+    check_reachable(
+        &has_floor,
+        &blocked,
+        &objects,
+        floor.data_line,
+        &mut problems,
+    );
+    problems
+}
+
+// Reads the csv data of every tile layer
+fn read_tile_layers(lines: &[&str], problems: &mut Vec<MapProblem>) -> Vec<TileLayer> {
+    let mut layers = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        let element = lines[i].trim();
+        i += 1;
+        if !element.starts_with("<layer") {
+            continue;
+        }
+        let name = get_xml_attribute(element, "name")
+            .unwrap_or_default()
+            .to_string();
+        // Layer sizes are checked against the map, only a missing size is a problem here
+        for attribute in ["width", "height"] {
+            if get_xml_attribute(element, attribute)
+                .and_then(|value| value.parse::<u32>().ok())
+                .is_none()
+            {
+                problems.push(problem(
+                    Some(i),
+                    None,
+                    format!("Layer {name} is missing its {attribute}"),
+                ));
+            }
+        }
+        let data = lines.get(i).map(|line| line.trim()).unwrap_or_default();
+        if get_xml_attribute(data, "encoding") != Some("csv")
+            || get_xml_attribute(data, "compression").is_some()
+        {
+            problems.push(problem(
+                Some(i + 1),
+                None,
+                format!("Layer {name} has to be saved as uncompressed csv"),
+            ));
+            continue;
+        }
+        i += 1;
+        let data_line = i + 1;
+        let data_start = i;
+        while i < lines.len() && !lines[i].trim().starts_with("</data>") {
+            i += 1;
+        }
+        let (rows, errors) = parse_layer_rows(&lines[data_start..i]);
+        for error in errors {
+            problems.push(problem(
+                Some(data_line + error.cell.1 as usize),
+                Some(error.cell),
+                error.message,
+            ));
+        }
+        layers.push(TileLayer {
+            name,
+            data_line,
+            rows,
+        });
+    }
+    layers
+}
+
+fn check_layer_size(layer: &TileLayer, width: u32, height: u32, problems: &mut Vec<MapProblem>) {
+    if layer.rows.len() != height as usize {
+        problems.push(problem(
+            Some(layer.data_line),
+            None,
+            format!(
+                "Layer {} has {} rows, the map is {height} tiles high",
+                layer.name,
+                layer.rows.len()
+            ),
+        ));
+    }
+    for (y, row) in layer.rows.iter().enumerate() {
+        if row.len() != width as usize {
+            problems.push(problem(
+                Some(layer.data_line + y),
+                None,
+                format!(
+                    "Row of layer {} has {} tiles, the map is {width} tiles wide",
+                    layer.name,
+                    row.len()
+                ),
+            ));
+        }
+    }
+}
+
+// Only rotations are supported, flipped tiles make the game panic. Returns if the flags are fine.
+fn check_flip_flags(
+    gid: u32,
+    (line, cell): (Option<usize>, Option<(u32, u32)>),
+    problems: &mut Vec<MapProblem>,
+) -> bool {
+    let flags = gid >> 28;
+    if matches!(flags, 0x0 | 0x6 | 0xA | 0xC) {
+        return true;
+    }
+    problems.push(problem(
+        line,
+        cell,
+        format!("Unsupported flip flags {flags:#x}, tiles can only be rotated"),
+    ));
+    false
+}
+
+fn check_objects(
+    lines: &[&str],
+    objects: &[MapObject],
+    width: u32,
+    height: u32,
+    problems: &mut Vec<MapProblem>,
+) {
+    let map_rect = (width as f32 * TILE_SIZE, height as f32 * TILE_SIZE);
+    for object in objects {
+        let element = format!("<object id=\"{}\"", object.id);
+        let line = lines
+            .iter()
+            .position(|line| line.trim().starts_with(&element))
+            .map(|i| i + 1);
+        let name = if object.name.is_empty() {
+            format!("Object {}", object.id)
+        } else {
+            format!("Object {} ({})", object.id, object.name)
+        };
+
+        let rect = object.get_bounds();
+        if rect.x < 0.0
+            || rect.y < 0.0
+            || rect.x + rect.width > map_rect.0
+            || rect.y + rect.height > map_rect.1
+        {
+            problems.push(problem(
+                line,
+                None,
+                format!("{name} in layer {} is outside the map", object.layer),
+            ));
+        }
+        match object.get_kind() {
+            Ok(ObjectKind::Trigger | ObjectKind::ExtractionZone) if !object.has_area() => problems
+                .push(problem(
+                    line,
+                    None,
+                    format!("{name} is a {} zone with no area", object.class),
+                )),
+            Ok(_) => {}
+            Err(err) => problems.push(problem(line, None, err)),
+        }
+        if let Err(err) = object.get_collider() {
+            problems.push(problem(line, None, err));
+        }
+    }
+}
+
+// Flood fills the floor between walls. The largest area and areas with a player spawn are taken
+// as ones the player starts in, areas with a transition zone or a transition target in them are
+// reached from other maps, every other area can never be walked to.
+fn check_reachable(
+    has_floor: &[Vec<bool>],
+    blocked: &[Vec<BlockedSides>],
+    objects: &[MapObject],
+    first_line: usize,
+    problems: &mut Vec<MapProblem>,
+) {
+    let height = has_floor.len();
+    let width = has_floor.first().map(|row| row.len()).unwrap_or(0);
+    let mut area_of = vec![vec![None; width]; height];
+    let mut areas: Vec<Vec<(usize, usize)>> = vec![];
+    for (start_y, start_x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+        if !has_floor[start_y][start_x] || area_of[start_y][start_x].is_some() {
+            continue;
+        }
+        let mut area = vec![];
+        let mut queue = VecDeque::from([(start_x, start_y)]);
+        area_of[start_y][start_x] = Some(areas.len());
+        while let Some((x, y)) = queue.pop_front() {
+            area.push((x, y));
+            let sides = blocked[y][x];
+            let neighbours = [
+                (
+                    x.wrapping_sub(1),
+                    y,
+                    sides.left || blocked[y][x.saturating_sub(1)].right,
+                ),
+                (
+                    x + 1,
+                    y,
+                    sides.right || blocked[y].get(x + 1).is_some_and(|s| s.left),
+                ),
+                (
+                    x,
+                    y.wrapping_sub(1),
+                    sides.top || blocked[y.saturating_sub(1)][x].bottom,
+                ),
+                (
+                    x,
+                    y + 1,
+                    sides.bottom || blocked.get(y + 1).is_some_and(|row| row[x].top),
+                ),
+            ];
+            for (next_x, next_y, is_blocked) in neighbours {
+                if is_blocked || next_x >= width || next_y >= height {
+                    continue;
+                }
+                if has_floor[next_y][next_x] && area_of[next_y][next_x].is_none() {
+                    area_of[next_y][next_x] = Some(areas.len());
+                    queue.push_back((next_x, next_y));
+                }
+            }
+        }
+        areas.push(area);
+    }
+
+    let mut reachable = vec![false; areas.len()];
+    if let Some(largest) = (0..areas.len()).max_by_key(|i| areas[*i].len()) {
+        reachable[largest] = true;
+    }
+    let targets = objects
+        .iter()
+        .filter_map(|object| object.get_property("target"))
+        .collect::<Vec<&str>>();
+    for object in objects {
+        if object.class != TRANSITION_CLASS
+            && object.class != PLAYER_SPAWN_CLASS
+            && !targets.contains(&object.name.as_str())
+        {
+            continue;
+        }
+        let center = object.get_center();
+        let (x, y) = (
+            (center.x / TILE_SIZE).floor() as usize,
+            (center.y / TILE_SIZE).floor() as usize,
+        );
+        if let Some(Some(area)) = area_of.get(y).and_then(|row| row.get(x)) {
+            reachable[*area] = true;
+        }
+    }
+    for (area, _) in areas
+        .iter()
+        .zip(reachable)
+        .filter(|(_, reachable)| !reachable)
+    {
+        let (x, y) = area[0];
+        problems.push(problem(
+            Some(first_line + y),
+            Some((x as u32, y as u32)),
+            format!("Area of {} tiles can't be reached", area.len()),
+        ));
+    }
+}
+
+// A wall blocks a side of its tile when one of its collider rects runs along most of it
+fn get_blocked_sides(wall: &Wall) -> BlockedSides {
+    let mut sides = BlockedSides::default();
+    for rect in wall.get_collider().rects {
+        let spans_width = rect.width >= TILE_SIZE / 2.0;
+        let spans_height = rect.height >= TILE_SIZE / 2.0;
+        sides.left |= rect.x <= 0.0 && spans_height;
+        sides.right |= rect.x + rect.width >= TILE_SIZE && spans_height;
+        sides.top |= rect.y <= 0.0 && spans_width;
+        sides.bottom |= rect.y + rect.height >= TILE_SIZE && spans_width;
+    }
+    sides
+}
+
+fn problem(line: Option<usize>, cell: Option<(u32, u32)>, message: String) -> MapProblem {
+    MapProblem {
+        line,
+        cell,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_map::write_test_tmx;
+
+    // Problems of a map written from layer rows and object groups, with the cells they are at.
+    // Tests run at the same time, so each one names its own file.
+    fn validate(
+        name: &str,
+        floor: &[&str],
+        walls: &[&str],
+        objects: &str,
+    ) -> Vec<(Option<(u32, u32)>, String)> {
+        let path = write_test_tmx(name, floor, walls, objects);
+        let problems = validate_map(&path);
+        std::fs::remove_file(path).unwrap();
+        problems
+            .into_iter()
+            .map(|problem| (problem.cell, problem.message))
+            .collect()
+    }
+
+    const FLOOR: [&str; 3] = ["1,1,1", "1,1,1", "1,1,1"];
+    const NO_WALLS: [&str; 3] = ["0,0,0", "0,0,0", "0,0,0"];
+
+    #[test]
+    fn plain_map_has_no_problems() {
+        assert!(validate("validate_valid.tmx", &FLOOR, &NO_WALLS, "").is_empty());
+    }
+
+    #[test]
+    fn unknown_gids_are_reported() {
+        // A wall on the floor layer and an object on the wall layer
+        let problems = validate(
+            "validate_unknown_gids.tmx",
+            &["1,1,1", "1,70,1", "1,1,1"],
+            &["0,0,0", "0,0,0", "0,0,200"],
+            "",
+        );
+        assert_eq!(
+            problems,
+            vec![
+                (Some((1, 1)), "Unknown gid 70 in layer Floor".to_string()),
+                (Some((2, 2)), "Unknown gid 200 in layer Walls".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn flipped_tiles_are_reported() {
+        // Flipped horizontally, which isn't a rotation
+        let problems = validate(
+            "validate_flipped.tmx",
+            &["1,1,1", "1,2147483649,1", "1,1,1"],
+            &NO_WALLS,
+            "",
+        );
+        assert_eq!(
+            problems,
+            vec![(
+                Some((1, 1)),
+                "Unsupported flip flags 0x8, tiles can only be rotated".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn layer_size_has_to_match_map() {
+        let problems = validate(
+            "validate_layer_size.tmx",
+            &FLOOR,
+            &["0,0,0", "0,0", "0,0,0"],
+            "",
+        );
+        assert!(problems.contains(&(
+            None,
+            "Row of layer Walls has 2 tiles, the map is 3 tiles wide".to_string()
+        )));
+    }
+
+    #[test]
+    fn walled_off_area_is_unreachable() {
+        // Straight walls on the left side of the second column cut off the first one
+        let problems = validate(
+            "validate_unreachable.tmx",
+            &["1,1,1,1,1", "1,1,1,1,1", "1,1,1,1,1"],
+            &["0,65,0,0,0", "0,65,0,0,0", "0,65,0,0,0"],
+            "",
+        );
+        assert_eq!(
+            problems,
+            vec![(Some((0, 0)), "Area of 3 tiles can't be reached".to_string())]
+        );
+    }
+
+    #[test]
+    fn object_outside_map_is_reported() {
+        let objects = r#" <objectgroup id="3" name="Objects">
+  <object id="1" name="far" x="200" y="16" width="8" height="8"/>
+ </objectgroup>
+"#;
+        let problems = validate("validate_outside.tmx", &FLOOR, &NO_WALLS, objects);
+        assert_eq!(
+            problems,
+            vec![(
+                None,
+                "Object 1 (far) in layer Objects is outside the map".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use crate::{
+    map_objects::ObjectKind, world::World, Player, WorldMap, FLOOR_TILE_SHEET_PATH, TILE_SIZE,
+};
+use raylib::prelude::*;
+use std::{collections::HashSet, ops::RangeInclusive};
+
+pub const MINIMAP_FRAME_PATH: &str = "assets/zetores_2dTDS_assets/future-tds/hud/hud_minimap.png";
+// Width of the minimap frame in the corner of the screen and the gap around it, in pixels
+const MINIMAP_WIDTH: f32 = 220.0;
+const MINIMAP_MARGIN: f32 = 16.0;
+// Part of the frame around the map, as a fraction of the frame's width
+const FRAME_BORDER: f32 = 0.08;
+// Screen pixels per tile on the minimap
+const MINIMAP_ZOOM: f32 = 4.0;
+// Screen pixels per tile the full-screen map can be zoomed between
+const FULL_MAP_ZOOM: RangeInclusive<f32> = 1.0..=32.0;
+const WALL_COLOR: Color = Color::new(30, 30, 36, 255);
+
+// Map of everything that has been loaded so far, drawn in the corner of the screen or over the
+// whole screen. Every tile is a single pixel of a texture, colored like the tile looks.
+pub struct Minimap {
+    frame: Texture2D,
+    // Average color of every tile in the floor tile sheet
+    ground_colors: Vec<Color>,
+    map_id: Option<u32>,
+    texture: Option<Texture2D>,
+    // RGBA pixels of the texture, kept around to update it when tiles change
+    pixels: Vec<u8>,
+    drawn_chunks: HashSet<(u32, u32)>,
+    pub full_screen: bool,
+    // Tile in the middle of the full-screen map and how many pixels a tile takes up on it
+    view_center: Vector2,
+    view_zoom: f32,
+    pub show_lights: bool,
+    pub show_enemies: bool,
+    pub show_objectives: bool,
+}
+
+impl Minimap {
+    pub fn new(rl: &mut RaylibHandle, thread: &RaylibThread) -> Minimap {
+        Minimap {
+            frame: rl
+                .load_texture(thread, MINIMAP_FRAME_PATH)
+                .expect("Unable to load minimap frame"),
+            ground_colors: get_tile_colors(FLOOR_TILE_SHEET_PATH),
+            map_id: None,
+            texture: None,
+            pixels: vec![],
+            drawn_chunks: HashSet::new(),
+            full_screen: false,
+            view_center: Vector2::zero(),
+            view_zoom: 8.0,
+            show_lights: true,
+            show_enemies: true,
+            show_objectives: true,
+        }
+    }
+
+    // Opens and closes the full-screen map with M and pans and zooms it while it is open
+    pub fn update(&mut self, rl: &mut RaylibHandle, player: &Player) {
+        if rl.is_key_pressed(KeyboardKey::KEY_M) {
+            self.full_screen = !self.full_screen;
+            self.view_center = player.pos / TILE_SIZE;
+        }
+        if !self.full_screen {
+            return;
+        }
+        if rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT) {
+            self.view_center -= rl.get_mouse_delta() / self.view_zoom;
+        }
+        let mouse_wheel_move = rl.get_mouse_wheel_move();
+        if mouse_wheel_move != 0.0 {
+            self.view_zoom = (self.view_zoom * (1.0 + mouse_wheel_move / 10.0))
+                .clamp(*FULL_MAP_ZOOM.start(), *FULL_MAP_ZOOM.end());
+        }
+    }
+
+    // Draws newly loaded chunks and changed tiles onto the map texture
+    pub fn sync(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        map: &WorldMap,
+        changed_tiles: &[(u32, u32)],
+    ) {
+        // A different map was loaded, start over with nothing explored
+        if self.map_id != Some(map.id()) {
+            self.map_id = Some(map.id());
+            self.pixels = vec![0; (map.width * map.height * 4) as usize];
+            self.drawn_chunks.clear();
+            let image = Image::gen_image_color(map.width as i32, map.height as i32, Color::BLANK);
+            self.texture = Some(
+                rl.load_texture_from_image(thread, &image)
+                    .expect("Unable to create minimap texture"),
+            );
+        }
+
+        let mut changed = !changed_tiles.is_empty();
+        for (x, y) in changed_tiles.iter() {
+            self.draw_tile(map, *x, *y);
+        }
+        let new_chunks = map
+            .loaded_chunks()
+            .filter(|chunk_pos| !self.drawn_chunks.contains(chunk_pos))
+            .collect::<Vec<(u32, u32)>>();
+        for chunk_pos in new_chunks {
+            self.drawn_chunks.insert(chunk_pos);
+            for (x, y) in map.get_chunk_tile_range(chunk_pos).iter() {
+                self.draw_tile(map, x, y);
+            }
+            changed = true;
+        }
+        if let (true, Some(texture)) = (changed, self.texture.as_mut()) {
+            texture.update_texture(&self.pixels);
+        }
+    }
+
+    fn draw_tile(&mut self, map: &WorldMap, x: u32, y: u32) {
+        let color = if map.get_wall(x, y).is_some() {
+            WALL_COLOR
+        } else if let Some(ground) = map.get_ground(x, y) {
+            self.ground_colors
+                .get(ground.varient as usize)
+                .copied()
+                .unwrap_or(Color::MAGENTA)
+        } else {
+            return;
+        };
+        let i = ((y * map.width + x) * 4) as usize;
+        self.pixels[i..i + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+    }
+
+    pub fn draw(
+        &self,
+        d: &mut RaylibDrawHandle,
+        world: &World,
+        player: &Player,
+        camera: &Camera2D,
+    ) {
+        // Player arrow points where they are aiming
+        let player_arrow = (
+            player.pos,
+            player.get_angle_to_screen_pos(d.get_mouse_position(), camera),
+        );
+        let screen_size = Vector2::new(d.get_screen_width() as f32, d.get_screen_height() as f32);
+        if self.full_screen {
+            d.draw_rectangle(
+                0,
+                0,
+                screen_size.x as i32,
+                screen_size.y as i32,
+                Color::new(0, 0, 0, 220),
+            );
+            self.draw_map(
+                d,
+                Rectangle::new(0.0, 0.0, screen_size.x, screen_size.y),
+                self.view_center,
+                self.view_zoom,
+                world,
+                player_arrow,
+            );
+            d.draw_text(
+                "MAP  M close, drag to pan, scroll to zoom",
+                10,
+                screen_size.y as i32 - 40,
+                30,
+                Color::WHITE,
+            );
+            return;
+        }
+
+        // Frame in the top right corner with the map inside its border
+        let frame_height = MINIMAP_WIDTH * self.frame.height() as f32 / self.frame.width() as f32;
+        let frame_rect = Rectangle::new(
+            screen_size.x - MINIMAP_WIDTH - MINIMAP_MARGIN,
+            MINIMAP_MARGIN,
+            MINIMAP_WIDTH,
+            frame_height,
+        );
+        d.draw_texture_pro(
+            &self.frame,
+            Rectangle::new(
+                0.0,
+                0.0,
+                self.frame.width() as f32,
+                self.frame.height() as f32,
+            ),
+            frame_rect,
+            Vector2::zero(),
+            0.0,
+            Color::WHITE,
+        );
+        let border = MINIMAP_WIDTH * FRAME_BORDER;
+        let map_size = MINIMAP_WIDTH - border * 2.0;
+        self.draw_map(
+            d,
+            Rectangle::new(
+                frame_rect.x + border,
+                frame_rect.y + border,
+                map_size,
+                map_size,
+            ),
+            player.pos / TILE_SIZE,
+            MINIMAP_ZOOM,
+            world,
+            player_arrow,
+        );
+    }
+
+    // Draws the part of the map around a tile into an area of the screen, with an arrow for the
+    // player and markers for the objects that are shown
+    fn draw_map(
+        &self,
+        d: &mut RaylibDrawHandle,
+        area: Rectangle,
+        center: Vector2,
+        zoom: f32,
+        world: &World,
+        (player_pos, aim_angle): (Vector2, f32),
+    ) {
+        let to_screen = |world_pos: Vector2| {
+            Vector2::new(area.x + area.width / 2.0, area.y + area.height / 2.0)
+                + (world_pos / TILE_SIZE - center) * zoom
+        };
+        let mut sc = d.begin_scissor_mode(
+            area.x as i32,
+            area.y as i32,
+            area.width as i32,
+            area.height as i32,
+        );
+        sc.draw_rectangle_rec(area, Color::new(10, 10, 14, 255));
+        if let Some(texture) = &self.texture {
+            sc.draw_texture_ex(texture, to_screen(Vector2::zero()), 0.0, zoom, Color::WHITE);
+        }
+
+        for object in world.map.objects.iter() {
+            let color = match object.get_kind() {
+                Ok(ObjectKind::Light) if self.show_lights => Color::YELLOW,
+                Ok(ObjectKind::EnemySpawner { .. }) if self.show_enemies => Color::RED,
+                Ok(ObjectKind::ExtractionZone | ObjectKind::Trigger) if self.show_objectives => {
+                    Color::LIME
+                }
+                _ => continue,
+            };
+            sc.draw_circle_v(to_screen(object.get_center()), 3.0, color);
+        }
+
+        let pos = to_screen(player_pos);
+        let point = |angle: f32, length: f32| {
+            pos + Vector2::new((aim_angle + angle).cos(), (aim_angle + angle).sin()) * length
+        };
+        sc.draw_triangle(
+            point(0.0, 9.0),
+            point(-2.5, 6.0),
+            point(2.5, 6.0),
+            Color::WHITE,
+        );
+    }
+}
+
+// Average color of every tile in a tile sheet, going row by row
+fn get_tile_colors(sheet_path: &str) -> Vec<Color> {
+    let mut sheet = Image::load_image(sheet_path).expect("Unable to load tile sheet");
+    let tile_size = TILE_SIZE as i32;
+    let (columns, rows) = (sheet.width() / tile_size, sheet.height() / tile_size);
+    let mut colors = vec![];
+    for tile in 0..columns * rows {
+        let (tile_x, tile_y) = (tile % columns * tile_size, tile / columns * tile_size);
+        let mut sum = [0u32; 3];
+        for y in tile_y..tile_y + tile_size {
+            for x in tile_x..tile_x + tile_size {
+                let color = sheet.get_color(x, y);
+                sum[0] += color.r as u32;
+                sum[1] += color.g as u32;
+                sum[2] += color.b as u32;
+            }
+        }
+        let pixels = (tile_size * tile_size) as u32;
+        colors.push(Color::new(
+            (sum[0] / pixels) as u8,
+            (sum[1] / pixels) as u8,
+            (sum[2] / pixels) as u8,
+            255,
+        ));
+    }
+    colors
+}
@@ -0,0 +1,392 @@
+use crate::{TileRange, WorldMap, TILE_SIZE};
+use raylib::prelude::*;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+};
+
+// Navigation cells along each side of a tile, so gaps narrower than a tile are found
+pub const CELLS_PER_TILE: u32 = 4;
+pub const CELL_SIZE: f32 = TILE_SIZE / CELLS_PER_TILE as f32;
+// A* gives up after looking at this many cells, so paths to unreachable places don't stall
+const MAX_SEARCH_CELLS: usize = 60_000;
+// Changes kept around so flow fields can tell if they need rebuilding
+const CHANGE_HISTORY: usize = 64;
+// Cost of moving straight and diagonally between cells
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Cell {
+    // Never loaded, treated as walkable so paths can head into unexplored parts of the map
+    Unknown,
+    Open,
+    Blocked,
+}
+
+// Where an agent of a given radius can stand on the map, in cells smaller than a tile. Cells are
+// blocked when the agent would overlap a wall collider standing in the middle of them.
+pub struct NavGrid {
+    agent_radius: f32,
+    map_id: Option<u32>,
+    width: u32,
+    height: u32,
+    cells: Vec<Cell>,
+    built_chunks: HashSet<(u32, u32)>,
+    // Goes up on every change to the grid
+    revision: u32,
+    // Recently changed cells and the revision they changed in, oldest first
+    recent_changes: VecDeque<(u32, TileRange)>,
+}
+
+impl NavGrid {
+    pub fn new(agent_radius: f32) -> NavGrid {
+        NavGrid {
+            agent_radius,
+            map_id: None,
+            width: 0,
+            height: 0,
+            cells: vec![],
+            built_chunks: HashSet::new(),
+            revision: 0,
+            recent_changes: VecDeque::new(),
+        }
+    }
+
+    // Builds the cells of newly loaded chunks and rebuilds the ones around changed tiles
+    pub fn update(&mut self, map: &WorldMap, changed_tiles: &[(u32, u32)]) {
+        // A different map was loaded, nothing known about the old one applies
+        if self.map_id != Some(map.id()) {
+            self.map_id = Some(map.id());
+            self.width = map.width * CELLS_PER_TILE;
+            self.height = map.height * CELLS_PER_TILE;
+            self.cells = vec![Cell::Unknown; (self.width * self.height) as usize];
+            self.built_chunks.clear();
+            self.recent_changes.clear();
+            self.revision += 1;
+        }
+
+        // Chunks stay known once built, walls can only change in loaded chunks
+        let new_chunks = map
+            .loaded_chunks()
+            .filter(|chunk_pos| !self.built_chunks.contains(chunk_pos))
+            .collect::<Vec<(u32, u32)>>();
+        for chunk_pos in new_chunks {
+            self.built_chunks.insert(chunk_pos);
+            self.rebuild_tiles(map, map.get_chunk_tile_range(chunk_pos));
+        }
+        for (x, y) in changed_tiles.iter() {
+            self.rebuild_tiles(
+                map,
+                TileRange {
+                    x: *x..x + 1,
+                    y: *y..y + 1,
+                },
+            );
+        }
+    }
+
+    // Works out the cells of a block of tiles again, along with the cells around it a wall in
+    // the block could reach with the agent radius
+    fn rebuild_tiles(&mut self, map: &WorldMap, tiles: TileRange) {
+        let margin = (self.agent_radius / CELL_SIZE).ceil() as u32 + 1;
+        let cells = TileRange {
+            x: (tiles.x.start * CELLS_PER_TILE).saturating_sub(margin)
+                ..(tiles.x.end * CELLS_PER_TILE + margin).min(self.width),
+            y: (tiles.y.start * CELLS_PER_TILE).saturating_sub(margin)
+                ..(tiles.y.end * CELLS_PER_TILE + margin).min(self.height),
+        };
+        for (cell_x, cell_y) in cells.iter() {
+            let tile = (cell_x / CELLS_PER_TILE, cell_y / CELLS_PER_TILE);
+            let cell = if map.get_ground(tile.0, tile.1).is_none() {
+                // Cells of chunks that aren't loaded keep what was known about them
+                continue;
+            } else if self.overlaps_wall(map, get_cell_center(cell_x, cell_y)) {
+                Cell::Blocked
+            } else {
+                Cell::Open
+            };
+            self.cells[(cell_y * self.width + cell_x) as usize] = cell;
+        }
+
+        self.revision += 1;
+        self.recent_changes.push_back((self.revision, cells));
+        if self.recent_changes.len() > CHANGE_HISTORY {
+            self.recent_changes.pop_front();
+        }
+    }
+
+    // Whether an agent standing at a point would overlap a wall collider
+    fn overlaps_wall(&self, map: &WorldMap, pos: Vector2) -> bool {
+        let radius = self.agent_radius;
+        let nearby_tiles = map.get_tile_range(
+            &Rectangle::new(pos.x - radius, pos.y - radius, radius * 2.0, radius * 2.0),
+            0,
+        );
+        map.iter_walls(&nearby_tiles).any(|(tile_pos, wall)| {
+            wall.get_collider()
+                .with_pos(tile_pos)
+                .rects
+                .iter()
+                .any(|rect| {
+                    // Closest point on the rect to the agent
+                    let closest = Vector2::new(
+                        pos.x.clamp(rect.x, rect.x + rect.width),
+                        pos.y.clamp(rect.y, rect.y + rect.height),
+                    );
+                    closest.distance_to(pos) < radius
+                })
+        })
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    // Whether any cell in a range changed after a revision
+    pub fn changed_since(&self, revision: u32, cells: &TileRange) -> bool {
+        // Changes older than the history could have touched anything
+        match self.recent_changes.front() {
+            Some((oldest, _)) if *oldest <= revision + 1 => {}
+            _ => return revision != self.revision,
+        }
+        self.recent_changes
+            .iter()
+            .filter(|(changed_revision, _)| *changed_revision > revision)
+            .any(|(_, changed)| {
+                changed.x.start < cells.x.end
+                    && cells.x.start < changed.x.end
+                    && changed.y.start < cells.y.end
+                    && cells.y.start < changed.y.end
+            })
+    }
+
+    pub fn is_walkable(&self, pos: Vector2) -> bool {
+        get_cell(pos).is_some_and(|(x, y)| self.is_cell_walkable(x, y))
+    }
+
+    fn is_cell_walkable(&self, x: u32, y: u32) -> bool {
+        x < self.width
+            && y < self.height
+            && self.cells[(y * self.width + x) as usize] != Cell::Blocked
+    }
+
+    // Walkable cells next to a cell with the cost of moving to them. Diagonals can't cut past
+    // the corner of a blocked cell.
+    fn get_neighbours(&self, x: u32, y: u32) -> Vec<((u32, u32), u32)> {
+        let mut neighbours = Vec::with_capacity(8);
+        for (dx, dy) in [
+            (-1, 0),
+            (1, 0),
+            (0, -1),
+            (0, 1),
+            (-1, -1),
+            (1, -1),
+            (-1, 1),
+            (1, 1),
+        ] {
+            let (next_x, next_y) = (x as i64 + dx, y as i64 + dy);
+            if next_x < 0 || next_y < 0 {
+                continue;
+            }
+            let (next_x, next_y) = (next_x as u32, next_y as u32);
+            if !self.is_cell_walkable(next_x, next_y) {
+                continue;
+            }
+            if dx != 0 && dy != 0 {
+                if !self.is_cell_walkable(next_x, y) || !self.is_cell_walkable(x, next_y) {
+                    continue;
+                }
+                neighbours.push(((next_x, next_y), DIAGONAL_COST));
+            } else {
+                neighbours.push(((next_x, next_y), STRAIGHT_COST));
+            }
+        }
+        neighbours
+    }
+
+    // Finds a path between two points with A*, smoothed so it only turns where it has to. The
+    // path starts at the start and ends at the goal. None if there is no way there.
+    pub fn find_path(&self, start: Vector2, goal: Vector2) -> Option<Vec<Vector2>> {
+        let start_cell = get_cell(start)?;
+        let goal_cell = get_cell(goal)?;
+        if !self.is_cell_walkable(goal_cell.0, goal_cell.1) {
+            return None;
+        }
+
+        let heuristic = |(x, y): (u32, u32)| {
+            let dx = x.abs_diff(goal_cell.0);
+            let dy = y.abs_diff(goal_cell.1);
+            STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+        };
+        let mut open = BinaryHeap::from([Reverse((heuristic(start_cell), start_cell))]);
+        let mut costs = HashMap::from([(start_cell, 0)]);
+        let mut came_from = HashMap::new();
+        while let Some(Reverse((_, cell))) = open.pop() {
+            if cell == goal_cell {
+                let mut cells = vec![cell];
+                while let Some(previous) = came_from.get(cells.last().unwrap()) {
+                    cells.push(*previous);
+                }
+                cells.reverse();
+                let mut path = vec![start];
+                // Every cell between the start and goal ones, none when they are the same cell
+                path.extend(
+                    cells
+                        .iter()
+                        .skip(1)
+                        .take(cells.len().saturating_sub(2))
+                        .map(|(x, y)| get_cell_center(*x, *y)),
+                );
+                path.push(goal);
+                return Some(self.smooth_path(path));
+            }
+            if costs.len() > MAX_SEARCH_CELLS {
+                return None;
+            }
+            let cost = costs[&cell];
+            for (next, step_cost) in self.get_neighbours(cell.0, cell.1) {
+                let next_cost = cost + step_cost;
+                if costs.get(&next).is_some_and(|known| *known <= next_cost) {
+                    continue;
+                }
+                costs.insert(next, next_cost);
+                came_from.insert(next, cell);
+                open.push(Reverse((next_cost + heuristic(next), next)));
+            }
+        }
+        None
+    }
+
+    // Skips every point of a path that can be walked past in a straight line
+    fn smooth_path(&self, path: Vec<Vector2>) -> Vec<Vector2> {
+        let mut smoothed = vec![path[0]];
+        let mut i = 0;
+        while i < path.len() - 1 {
+            // Furthest point still in sight, the next point always is
+            let next = (i + 1..path.len())
+                .rev()
+                .find(|j| *j == i + 1 || self.has_line_of_sight(path[i], path[*j]))
+                .unwrap();
+            smoothed.push(path[next]);
+            i = next;
+        }
+        smoothed
+    }
+
+    // Whether an agent can walk in a straight line between two points
+    pub fn has_line_of_sight(&self, from: Vector2, to: Vector2) -> bool {
+        let steps = (from.distance_to(to) / (CELL_SIZE / 2.0)).ceil() as u32;
+        (0..=steps).all(|step| {
+            let pos = from.lerp(to, step as f32 / steps.max(1) as f32);
+            self.is_walkable(pos)
+        })
+    }
+
+    // Builds a flow field leading to a target from every cell within a radius of tiles around
+    // it, for moving lots of agents to the same place
+    pub fn build_flow_field(&self, target: Vector2, radius: u32) -> FlowField {
+        let (target_x, target_y) = get_cell(target).unwrap_or((0, 0));
+        let radius = radius * CELLS_PER_TILE;
+        let region = TileRange {
+            x: target_x.saturating_sub(radius)..(target_x + radius + 1).min(self.width),
+            y: target_y.saturating_sub(radius)..(target_y + radius + 1).min(self.height),
+        };
+        let region_width = region.x.len();
+        let index = |(x, y): (u32, u32)| {
+            (y - region.y.start) as usize * region_width + (x - region.x.start) as usize
+        };
+        let in_region = |(x, y): (u32, u32)| region.x.contains(&x) && region.y.contains(&y);
+
+        // Dijkstra out from the target
+        let mut costs = vec![u32::MAX; region_width * region.y.len()];
+        // The target counts even in a blocked cell, so agents still head for it when it is
+        // right up against a wall
+        if in_region((target_x, target_y)) {
+            costs[index((target_x, target_y))] = 0;
+        }
+        let mut open = BinaryHeap::from([Reverse((0, (target_x, target_y)))]);
+        while let Some(Reverse((cost, cell))) = open.pop() {
+            if !in_region(cell) || cost > costs[index(cell)] {
+                continue;
+            }
+            for (next, step_cost) in self.get_neighbours(cell.0, cell.1) {
+                if !in_region(next) || cost + step_cost >= costs[index(next)] {
+                    continue;
+                }
+                costs[index(next)] = cost + step_cost;
+                open.push(Reverse((cost + step_cost, next)));
+            }
+        }
+
+        FlowField {
+            target,
+            radius: radius / CELLS_PER_TILE,
+            region,
+            costs,
+            revision: self.revision,
+        }
+    }
+}
+
+// Costs of getting to a target from every cell around it. Agents follow the field by moving
+// towards the cheapest cell next to them.
+pub struct FlowField {
+    pub target: Vector2,
+    radius: u32,
+    region: TileRange,
+    costs: Vec<u32>,
+    // Revision of the nav grid it was built from
+    revision: u32,
+}
+
+impl FlowField {
+    fn get_cost(&self, (x, y): (u32, u32)) -> Option<u32> {
+        if !self.region.x.contains(&x) || !self.region.y.contains(&y) {
+            return None;
+        }
+        let index = (y - self.region.y.start) as usize * self.region.x.len()
+            + (x - self.region.x.start) as usize;
+        Some(self.costs[index]).filter(|cost| *cost != u32::MAX)
+    }
+
+    // Direction to move in from a point to get closer to the target. None at the target, outside
+    // the field or where the target can't be reached from.
+    pub fn get_direction(&self, grid: &NavGrid, pos: Vector2) -> Option<Vector2> {
+        let cell = get_cell(pos)?;
+        let cost = self.get_cost(cell)?;
+        if cost == 0 {
+            return None;
+        }
+        let (next, _) = grid
+            .get_neighbours(cell.0, cell.1)
+            .into_iter()
+            .filter_map(|(next, _)| self.get_cost(next).map(|cost| (next, cost)))
+            .min_by_key(|(_, cost)| *cost)?;
+        Some((get_cell_center(next.0, next.1) - pos).normalized())
+    }
+
+    // Whether walls changed inside the field since it was built
+    pub fn is_outdated(&self, grid: &NavGrid) -> bool {
+        grid.changed_since(self.revision, &self.region)
+    }
+
+    // Rebuilds the field if it is outdated or the target moved to another cell
+    pub fn update(&mut self, grid: &NavGrid, target: Vector2) {
+        if self.is_outdated(grid) || get_cell(target) != get_cell(self.target) {
+            *self = grid.build_flow_field(target, self.radius);
+        }
+    }
+}
+
+// Cell a point is in, None if it is off the map to the top or left
+fn get_cell(pos: Vector2) -> Option<(u32, u32)> {
+    if pos.x < 0.0 || pos.y < 0.0 {
+        return None;
+    }
+    Some(((pos.x / CELL_SIZE) as u32, (pos.y / CELL_SIZE) as u32))
+}
+
+fn get_cell_center(x: u32, y: u32) -> Vector2 {
+    Vector2::new((x as f32 + 0.5) * CELL_SIZE, (y as f32 + 0.5) * CELL_SIZE)
+}
@@ -1,8 +1,16 @@
-use crate::{items::*, world::World, Collider, ImprovedCamera, Light, LightEngine, LightHandle};
+use crate::{
+    ground_material::GroundMaterial,
+    input::{Actions, GunSlot},
+    items::*,
+    world::World,
+    Circle, Collider, ImprovedCamera, Light, LightEngine, LightHandle,
+};
 use raylib::prelude::*;
 
 pub struct Player {
     pub pos: Vector2,
+    // Position at the start of the last tick, the player is drawn between it and pos
+    pub prev_pos: Vector2,
     pub vel: Vector2,
     pub ambient_light: LightHandle,
     animation: PlayerAnimation,
@@ -10,27 +18,46 @@ pub struct Player {
     pub muzzle_light: LightHandle,
     pub gun: GunItem,
     pub is_sprinting: bool,
+    // Distance walked since the last footstep
+    step_distance: f32,
+    // Point in the world the player is aiming at
+    pub aim_pos: Vector2,
 }
 
 impl Player {
     pub const RENDER_SIZE: Vector2 = Vector2::new(23.4, 37.8);
+    // Width of the circle the player takes up
     pub const COLLIDER_SIZE: f32 = 13.0;
     pub const MUZZLE_FLASH_COLOR: Color = Color::new(255, 87, 51, 255);
     const SPRINT_SPEED: f32 = 60.0;
     const WALK_SPEED: f32 = 30.0;
     const WALK_ACC: f32 = 3.8;
     const WALK_DEACC: f32 = 1.6;
+    // Distance walked between footsteps
+    const STEP_LENGTH: f32 = 24.0;
 
     pub fn new(
         rl: &mut RaylibHandle,
         thread: &RaylibThread,
         light_engine: &mut LightEngine,
     ) -> Player {
+        Self::with_animation(PlayerAnimation::new(rl, thread), light_engine)
+    }
+
+    // Player without any textures, for running the world without a window
+    pub fn headless(light_engine: &mut LightEngine) -> Player {
+        Self::with_animation(PlayerAnimation::without_textures(), light_engine)
+    }
+
+    fn with_animation(animation: PlayerAnimation, light_engine: &mut LightEngine) -> Player {
         Player {
             pos: Vector2::zero(),
+            prev_pos: Vector2::zero(),
             vel: Vector2::zero(),
-            animation: PlayerAnimation::new(rl, thread),
+            animation,
             is_sprinting: false,
+            step_distance: 0.0,
+            aim_pos: Vector2::zero(),
             flashlight: FlashLight {
                 light_handle: light_engine.spawn_light(Light::default_cone()).unwrap(),
                 active: false,
@@ -53,15 +80,40 @@ impl Player {
         }
     }
 
-    pub fn get_world_collider(&self) -> Collider {
-        Collider {
-            rects: vec![Rectangle {
-                x: self.pos.x - Self::COLLIDER_SIZE / 2.0,
-                y: self.pos.y - Self::COLLIDER_SIZE / 2.0,
-                width: Self::COLLIDER_SIZE,
-                height: Self::COLLIDER_SIZE,
-            }],
+    // Paths of every texture the player is drawn with
+    pub fn get_texture_paths() -> Vec<String> {
+        [
+            PlayerAnimation::get_frame_paths("ar15"),
+            PlayerAnimation::get_frame_paths("pistol"),
+        ]
+        .concat()
+    }
+
+    // Loads a texture again from disk if it is one of the players animation frames
+    pub fn reload_texture(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        path: &str,
+    ) -> Result<(), String> {
+        for (gun, frames) in [
+            ("ar15", &mut self.animation.ar15_frames),
+            ("pistol", &mut self.animation.pistol_frames),
+        ] {
+            let frame_paths = PlayerAnimation::get_frame_paths(gun);
+            if let Some(i) = frame_paths.iter().position(|frame_path| frame_path == path) {
+                frames[i] = rl.load_texture(thread, path)?;
+            }
         }
+        Ok(())
+    }
+
+    pub fn get_world_collider(&self) -> Collider {
+        Collider::from_circle(Circle {
+            x: self.pos.x,
+            y: self.pos.y,
+            radius: Self::COLLIDER_SIZE / 2.0,
+        })
     }
     pub fn get_animation_frame(&self) -> &Texture2D {
         match &self.gun {
@@ -80,35 +132,54 @@ impl Player {
         Vector2::new(angle_to_pos.cos(), angle_to_pos.sin())
     }
 
+    // Moves the player and uses their gun for a tick, doing what the actions say
     pub fn update(
         &mut self,
-        rl: &mut RaylibHandle,
+        actions: &Actions,
         light_engine: &mut LightEngine,
-        camera: &Camera2D,
         world: &mut World,
+        dt: f32,
     ) {
-        self.update_player_lights(light_engine, rl, camera);
-        self.handle_flashlight_controls(rl);
-        self.update_flashlight(rl, camera, light_engine);
-        self.handle_movement_controls(rl);
-        self.handle_gun_controls(rl);
+        self.prev_pos = self.pos;
+        self.aim_pos = actions.aim_pos;
+        self.update_player_lights(light_engine, dt);
+        self.handle_flashlight_controls(actions);
+        self.update_flashlight(light_engine);
+        self.handle_movement_controls(actions, world.get_ground_material(self.pos), dt);
+        self.handle_gun_controls(actions);
         world.map.handle_player_collisions(self);
         self.apply_velocity();
-        self.animation.handle_animation(rl);
-        self.handle_shooting(light_engine, rl, world, camera);
+        self.handle_footsteps(world);
+        self.animation.handle_animation(actions.aim_down_sights, dt);
+        self.handle_shooting(actions, light_engine, world, dt);
+    }
+
+    // Where the player is drawn, between where they were last tick and where they are now
+    pub fn get_render_pos(&self, alpha: f32) -> Vector2 {
+        self.prev_pos.lerp(self.pos, alpha)
+    }
+
+    // Direction the player is aiming in
+    pub fn get_aim_dir(&self) -> Vector2 {
+        let angle = (self.aim_pos.y - self.pos.y).atan2(self.aim_pos.x - self.pos.x);
+        Vector2::new(angle.cos(), angle.sin())
     }
 
-    fn handle_gun_controls(&mut self, rl: &RaylibHandle) {
-        if rl.is_key_down(KeyboardKey::KEY_FIVE) {
-            self.gun = GunItem::DEFAULT_AR15;
+    fn handle_gun_controls(&mut self, actions: &Actions) {
+        match actions.switch_gun {
+            Some(GunSlot::Rifle) => self.gun = GunItem::DEFAULT_AR15,
+            Some(GunSlot::Pistol) => self.gun = GunItem::DEFAULT_PISTOL,
+            None => {}
         }
-        if rl.is_key_down(KeyboardKey::KEY_SIX) {
-            self.gun = GunItem::DEFAULT_PISTOL;
+        // If player is trying to reload
+        if actions.reload {
+            let gun_body = self.gun.get_gun_body();
+            gun_body.mag.bullets = gun_body.mag.max_bullets;
         }
     }
 
-    fn handle_flashlight_controls(&mut self, rl: &RaylibHandle) {
-        if rl.is_key_pressed(KeyboardKey::KEY_F) {
+    fn handle_flashlight_controls(&mut self, actions: &Actions) {
+        if actions.toggle_flashlight {
             self.flashlight.active = !self.flashlight.active;
         }
     }
@@ -117,30 +188,28 @@ impl Player {
         self.pos += self.vel
     }
 
-    fn update_player_lights(
-        &mut self,
-        light_engine: &mut LightEngine,
-        rl: &RaylibHandle,
-        camera: &Camera2D,
-    ) {
-        let light = light_engine.get_mut_light(&self.muzzle_light).set_pos(
-            self.pos + self.get_vector_to_screen_pos(rl.get_mouse_position(), camera) * 15.0,
-        );
+    // Makes a footstep noise every few steps, louder when sprinting
+    fn handle_footsteps(&mut self, world: &mut World) {
+        self.step_distance += self.vel.length();
+        if self.step_distance >= Self::STEP_LENGTH {
+            self.step_distance = 0.0;
+            world.make_footstep(self.pos, if self.is_sprinting { 2.0 } else { 1.0 });
+        }
+    }
+
+    fn update_player_lights(&mut self, light_engine: &mut LightEngine, dt: f32) {
+        let light = light_engine
+            .get_mut_light(&self.muzzle_light)
+            .set_pos(self.pos + self.get_aim_dir() * 15.0);
         let old_color = light.color();
         light.set_color(Vector4::new(
             old_color.x,
             old_color.y,
             old_color.w,
-            (old_color.z - (25.0 * rl.get_frame_time())).max(0.0),
+            (old_color.z - (25.0 * dt)).max(0.0),
         ));
     }
-    fn handle_lighting(
-        &mut self,
-        light_engine: &mut LightEngine,
-        rl: &RaylibHandle,
-        camera: &Camera2D,
-        player_shooting: bool,
-    ) {
+    fn handle_lighting(&mut self, light_engine: &mut LightEngine, player_shooting: bool, dt: f32) {
         // Ambient light
         light_engine
             .get_mut_light(&self.ambient_light)
@@ -151,44 +220,36 @@ impl Player {
             // Set muzzle light to on and to the end of the players gun
             light_engine
                 .get_mut_light(&self.muzzle_light)
-                .set_pos(
-                    self.pos
-                        + self.get_vector_to_screen_pos(rl.get_mouse_position(), camera) * 15.0,
-                )
+                .set_pos(self.pos + self.get_aim_dir() * 15.0)
                 .set_color(Vector4::new(1.0, 0.73, 0.41, 1.5));
         // Else reduce the brightness of the muzzle light
         } else {
-            let light = light_engine.get_mut_light(&self.muzzle_light).set_pos(
-                self.pos + self.get_vector_to_screen_pos(rl.get_mouse_position(), camera) * 15.0,
-            );
+            let light = light_engine
+                .get_mut_light(&self.muzzle_light)
+                .set_pos(self.pos + self.get_aim_dir() * 15.0);
             let old_color = light.color();
             light.set_color(Vector4::new(
                 old_color.x,
                 old_color.y,
                 old_color.w,
-                (old_color.z - (25.0 * rl.get_frame_time())).max(0.0),
+                (old_color.z - (25.0 * dt)).max(0.0),
             ));
         }
     }
 
     pub fn handle_shooting(
         &mut self,
+        actions: &Actions,
         light_engine: &mut LightEngine,
-        rl: &RaylibHandle,
         world: &mut World,
-        camera: &Camera2D,
+        dt: f32,
     ) {
         let gun_body = self.gun.get_gun_body();
-        gun_body.time_since_shot += rl.get_frame_time(); // Update time since shot
-
-        // If player is trying to reload
-        if rl.is_key_pressed(KeyboardKey::KEY_R) {
-            gun_body.mag.bullets = gun_body.mag.max_bullets;
-        };
+        gun_body.time_since_shot += dt; // Update time since shot
 
         let trigger_pressed = match gun_body.fire_mode {
-            FireMode::SemiAutomatic => rl.is_key_pressed(KeyboardKey::KEY_E),
-            FireMode::Automatic => rl.is_key_down(KeyboardKey::KEY_E),
+            FireMode::SemiAutomatic => actions.fire_pressed,
+            FireMode::Automatic => actions.fire,
         };
 
         let mut is_shooting = false;
@@ -200,49 +261,30 @@ impl Player {
                 && self.animation.current_frame == PlayerAnimation::FRAME_AMOUNT
         {
             gun_body.fire_bullet();
-            world.spawn_bullet(rl, camera, self);
+            world.spawn_bullet(self);
             is_shooting = true;
         }
 
-        self.handle_lighting(light_engine, rl, camera, is_shooting);
+        self.handle_lighting(light_engine, is_shooting, dt);
     }
 
-    fn handle_movement_controls(&mut self, rl: &RaylibHandle) {
-        // Constants that are adjusted with frame time to be consistent across fps
-        let player_speed = match rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {
-            true => {
-                self.is_sprinting = true;
-                Self::SPRINT_SPEED * rl.get_frame_time()
-            }
-            false => {
-                self.is_sprinting = false;
-                Self::WALK_SPEED * rl.get_frame_time()
-            }
-        };
-        let player_acc = Self::WALK_ACC * rl.get_frame_time();
-        let player_deacc = Self::WALK_DEACC * rl.get_frame_time();
-
-        // Calculate direction vectors for movement
-        let mut direction = Vector2::new(0.0, 0.0);
-        if rl.is_key_down(KeyboardKey::KEY_W) {
-            direction.y -= 1.0;
-        }
-        if rl.is_key_down(KeyboardKey::KEY_S) {
-            direction.y += 1.0;
-        }
-        if rl.is_key_down(KeyboardKey::KEY_A) {
-            direction.x -= 1.0;
-        }
-        if rl.is_key_down(KeyboardKey::KEY_D) {
-            direction.x += 1.0;
-        }
+    fn handle_movement_controls(&mut self, actions: &Actions, ground: &GroundMaterial, dt: f32) {
+        // Constants that are adjusted with the tick time, and with the ground being walked on
+        self.is_sprinting = actions.sprint;
+        let player_speed = if actions.sprint {
+            Self::SPRINT_SPEED * dt
+        } else {
+            Self::WALK_SPEED * dt
+        } * ground.speed;
+        let player_acc = Self::WALK_ACC * dt * ground.acceleration;
+        let player_deacc = Self::WALK_DEACC * dt * ground.friction;
 
-        // Normalize the direction vector if it's not zero
-        if direction.x != 0.0 || direction.y != 0.0 {
-            let length = (direction.x * direction.x + direction.y * direction.y).sqrt();
-            direction.x /= length;
-            direction.y /= length;
-        }
+        // Movement can be any length up to 1, an analog stick pushed halfway walks slower
+        let direction = if actions.movement.length() > 1.0 {
+            actions.movement.normalized()
+        } else {
+            actions.movement
+        };
 
         // Apply acceleration and deacceleration based on the normalized direction
         self.vel.x += direction.x * player_acc;
@@ -264,22 +306,14 @@ impl Player {
         }
     }
 
-    pub fn update_flashlight(
-        &mut self,
-        rl: &mut RaylibHandle,
-        camera: &Camera2D,
-        light_engine: &mut LightEngine,
-    ) {
-        let player_screen_pos = camera.to_screen(self.pos);
-        let mouse_pos = rl.get_mouse_position();
-        let dx = mouse_pos.x - player_screen_pos.x;
-        let dy = -(mouse_pos.y - player_screen_pos.y);
-        let rotation = dy.atan2(dx) + PI as f32;
+    pub fn update_flashlight(&mut self, light_engine: &mut LightEngine) {
+        let aim_dir = self.get_aim_dir();
+        let rotation = (-aim_dir.y).atan2(aim_dir.x) + PI as f32;
 
         light_engine.update_light(
             &self.flashlight.light_handle,
             Light::Cone {
-                pos: self.pos + Vector2::new(dx, -dy).normalized() * 5.0,
+                pos: self.pos + aim_dir * 5.0,
                 color: if self.flashlight.active {
                     Color::WHEAT.into()
                 } else {
@@ -306,41 +340,50 @@ impl PlayerAnimation {
 
     fn new(rl: &mut RaylibHandle, thread: &RaylibThread) -> PlayerAnimation {
         PlayerAnimation {
-            ar15_frames: (1..=5)
-                .map(|i| {
-                    rl.load_texture(thread, &format!("assets/player/player_ar15_{i}.png"))
-                        .unwrap()
-                })
+            ar15_frames: Self::get_frame_paths("ar15")
+                .iter()
+                .map(|path| rl.load_texture(thread, path).unwrap())
                 .collect::<Vec<Texture2D>>(),
-            pistol_frames: (1..=5)
-                .map(|i| {
-                    rl.load_texture(thread, &format!("assets/player/player_pistol_{i}.png"))
-                        .unwrap()
-                })
+            pistol_frames: Self::get_frame_paths("pistol")
+                .iter()
+                .map(|path| rl.load_texture(thread, path).unwrap())
                 .collect::<Vec<Texture2D>>(),
             current_frame: 0,
             elapsed_time: 0.0,
         }
     }
 
-    pub fn handle_animation(&mut self, rl: &RaylibHandle) {
+    fn without_textures() -> PlayerAnimation {
+        PlayerAnimation {
+            ar15_frames: vec![],
+            pistol_frames: vec![],
+            current_frame: 0,
+            elapsed_time: 0.0,
+        }
+    }
+
+    fn get_frame_paths(gun: &str) -> Vec<String> {
+        (1..=5)
+            .map(|i| format!("assets/player/player_{gun}_{i}.png"))
+            .collect()
+    }
+
+    pub fn handle_animation(&mut self, aim_down_sights: bool, dt: f32) {
         // If player is trying to ADS
-        if rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_RIGHT)
-            || rl.is_key_down(KeyboardKey::KEY_SPACE)
-        {
+        if aim_down_sights {
             if self.elapsed_time > Self::FRAME_TIME || self.current_frame == 0 {
                 if self.current_frame < Self::FRAME_AMOUNT {
                     self.current_frame += 1;
                 }
                 self.elapsed_time = 0.0;
             }
-            self.elapsed_time += rl.get_frame_time();
+            self.elapsed_time += dt;
         } else if self.current_frame > 0 {
             if self.elapsed_time <= -Self::FRAME_TIME || self.current_frame == Self::FRAME_AMOUNT {
                 self.current_frame -= 1;
                 self.elapsed_time = 0.0;
             }
-            self.elapsed_time -= rl.get_frame_time();
+            self.elapsed_time -= dt;
         }
     }
 }
@@ -0,0 +1,234 @@
+use crate::{
+    input::{ActionSource, Actions},
+    timestep::TICK_TIME,
+    world::World,
+    LightEngine, Player, WorldMap,
+};
+use raylib::prelude::*;
+
+// Runs one tick of the world, returns whether the player went through to another map
+pub fn tick(
+    actions: &Actions,
+    light_engine: &mut LightEngine,
+    world: &mut World,
+    player: &mut Player,
+) -> bool {
+    world.day_cycle.handle_actions(actions);
+    light_engine.handle_spawning_light(actions);
+    player.update(actions, light_engine, world, TICK_TIME);
+    let changed_map = world.handle_map_transitions(light_engine, player);
+    world.update_triggers(player);
+    world.update_bullets(TICK_TIME);
+    world.update_effects(TICK_TIME);
+    world.day_cycle.update(light_engine, TICK_TIME);
+    changed_map
+}
+
+// The world and the player without a window, textures or shader, for stepping through ticks
+// in tests and tools
+pub struct Simulation {
+    pub light_engine: LightEngine,
+    pub world: World,
+    pub player: Player,
+    // Ticks run so far
+    pub ticks: u32,
+}
+
+impl Simulation {
+    // Player starts at the map's spawn point
+    pub fn new(map: WorldMap) -> Simulation {
+        let mut light_engine = LightEngine::headless();
+        let world = World::from_map(&mut light_engine, map);
+        let mut player = Player::headless(&mut light_engine);
+        player.pos = world
+            .map
+            .get_player_spawn()
+            .unwrap_or(Vector2::new(64.0, 64.0));
+        player.prev_pos = player.pos;
+        Simulation {
+            light_engine,
+            world,
+            player,
+            ticks: 0,
+        }
+    }
+
+    // Loads the whole map up front, instead of streaming it in around the player
+    pub fn from_file(path: &str) -> Simulation {
+        Self::new(WorldMap::load_from_file(path))
+    }
+
+    // Runs a number of ticks with actions from the source
+    pub fn step(&mut self, input: &mut impl ActionSource, ticks: u32) {
+        for _ in 0..ticks {
+            let actions = input.next_tick();
+            tick(
+                &actions,
+                &mut self.light_engine,
+                &mut self.world,
+                &mut self.player,
+            );
+            self.ticks += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        day_cycle::{FULL_CYCLE_LENGTH, MIDNIGHT, NOON},
+        input::ScriptedInput,
+        lighting::Light,
+        world_map::test_map,
+        TileRotation, WallVarient, TILE_SIZE,
+    };
+
+    // 8x8 tile map with straight walls down some of the columns, which stand on the left side of
+    // their tiles
+    fn simulation_with_wall_columns(columns: &[usize]) -> Simulation {
+        let walls = columns
+            .iter()
+            .flat_map(|x| (0..8).map(move |y| ((*x, y), WallVarient::Staight, TileRotation::None)))
+            .collect();
+        let mut simulation = Simulation::new(test_map(8, 8, walls));
+        simulation.player.pos = Vector2::new(48.0, 112.0);
+        simulation.player.prev_pos = simulation.player.pos;
+        simulation
+    }
+
+    fn walk_right(ticks: u32) -> ScriptedInput {
+        ScriptedInput::new(vec![(
+            Actions {
+                movement: Vector2::new(1.0, 0.0),
+                aim_pos: Vector2::new(1000.0, 112.0),
+                ..Default::default()
+            },
+            ticks,
+        )])
+    }
+
+    #[test]
+    fn player_walks_where_told() {
+        let mut simulation = simulation_with_wall_columns(&[]);
+        simulation.step(&mut walk_right(60), 60);
+        assert_eq!(simulation.ticks, 60);
+        assert!(simulation.player.pos.x > 60.0);
+        assert!((simulation.player.pos.y - 112.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn player_stops_when_script_ends() {
+        let mut simulation = simulation_with_wall_columns(&[]);
+        let mut input = walk_right(30);
+        simulation.step(&mut input, 30);
+        assert!(input.is_finished());
+        simulation.step(&mut input, 120);
+        let stopped_at = simulation.player.pos;
+        simulation.step(&mut input, 10);
+        assert!(simulation.player.pos.distance_to(stopped_at) < 0.01);
+    }
+
+    #[test]
+    fn player_is_stopped_by_wall() {
+        // Wall strip from x 128 to 138
+        let mut simulation = simulation_with_wall_columns(&[4]);
+        simulation.step(&mut walk_right(600), 600);
+        let right_edge = simulation.player.pos.x + Player::COLLIDER_SIZE / 2.0;
+        assert!(right_edge <= 4.0 * TILE_SIZE);
+        assert!(right_edge > 4.0 * TILE_SIZE - 1.0);
+    }
+
+    #[test]
+    fn rifle_fires_bullets_that_fly() {
+        let mut simulation = simulation_with_wall_columns(&[]);
+        let mut input = ScriptedInput::new(vec![(
+            Actions {
+                aim_pos: Vector2::new(1000.0, 112.0),
+                aim_down_sights: true,
+                fire: true,
+                ..Default::default()
+            },
+            12,
+        )]);
+        simulation.step(&mut input, 12);
+        assert!(!simulation.world.bullets.is_empty());
+        assert!(simulation.player.gun.get_gun_body().mag.bullets < 30);
+
+        let bullet_x = simulation.world.bullets[0].pos.x;
+        simulation.step(&mut input, 1);
+        assert!(simulation.world.bullets[0].pos.x > bullet_x);
+    }
+
+    #[test]
+    fn reload_refills_magazine() {
+        let mut simulation = simulation_with_wall_columns(&[]);
+        simulation.player.gun.get_gun_body().mag.bullets = 3;
+        let mut input = ScriptedInput::new(vec![(
+            Actions {
+                reload: true,
+                ..Default::default()
+            },
+            1,
+        )]);
+        simulation.step(&mut input, 1);
+        assert_eq!(simulation.player.gun.get_gun_body().mag.bullets, 30);
+    }
+
+    #[test]
+    fn day_cycle_runs_with_ticks() {
+        let mut simulation = simulation_with_wall_columns(&[]);
+        let mut input = ScriptedInput::new(vec![(
+            Actions {
+                skip_to_time: Some(FULL_CYCLE_LENGTH * NOON),
+                ..Default::default()
+            },
+            60,
+        )]);
+        simulation.step(&mut input, 60);
+        // Skipped on the first tick, then a second of ticks passed
+        assert!((simulation.world.day_cycle.time - (FULL_CYCLE_LENGTH * NOON + 1.0)).abs() < 0.01);
+        let noon_light = simulation
+            .light_engine
+            .get_light(simulation.world.day_cycle.ambient_light_handle())
+            .color();
+
+        let mut input = ScriptedInput::new(vec![(
+            Actions {
+                skip_to_time: Some(FULL_CYCLE_LENGTH * MIDNIGHT),
+                ..Default::default()
+            },
+            1,
+        )]);
+        simulation.step(&mut input, 1);
+        let midnight_light = simulation
+            .light_engine
+            .get_light(simulation.world.day_cycle.ambient_light_handle())
+            .color();
+        assert!(midnight_light.x < noon_light.x);
+    }
+
+    #[test]
+    fn light_spawns_where_player_aims() {
+        let mut simulation = simulation_with_wall_columns(&[]);
+        let lights = simulation.light_engine.spawned_lights();
+        let mut input = ScriptedInput::new(vec![(
+            Actions {
+                aim_pos: Vector2::new(200.0, 40.0),
+                spawn_light: Some(Color::RED),
+                ..Default::default()
+            },
+            10,
+        )]);
+        simulation.step(&mut input, 10);
+        // Only spawned on the first tick the press was seen
+        assert_eq!(simulation.light_engine.spawned_lights(), lights + 1);
+        let spawned = simulation
+            .light_engine
+            .lights()
+            .filter(|light| matches!(light, Light::Radial { .. }))
+            .filter(|light| light.pos().distance_to(Vector2::new(200.0, 40.0)) < 0.01)
+            .count();
+        assert_eq!(spawned, 1);
+    }
+}
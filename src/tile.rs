@@ -1,13 +1,22 @@
 use crate::TILE_SIZE;
 use raylib::prelude::*;
 
+// Ids of the first tile of each tile sheet in the maps
+pub const FLOOR_FIRST_GID: u32 = 1;
+pub const WALL_FIRST_GID: u32 = 65;
+pub const OBJECT_FIRST_GID: u32 = 129;
+// Tilesets the maps use, holding the tile properties and animations
+pub const FLOOR_TILESET_PATH: &str = "assets/tiled/floor_tile_sheet.tsx";
+pub const WALL_TILESET_PATH: &str = "assets/tiled/wall_tile_sheet.tsx";
+pub const OBJECT_TILESET_PATH: &str = "assets/tiled/object_tile_sheet.tsx";
+
 #[derive(Debug)]
 pub struct Wall {
     pub varient: WallVarient,
     pub rotation: TileRotation,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Circle {
     pub x: f32,
     pub y: f32,
@@ -38,7 +47,8 @@ pub enum GroundVarient {
 impl GroundVarient {
     // Used for parsing map data
     pub fn from_raw_u32(ground: u32) -> Option<GroundVarient> {
-        match (ground - 1) & 0x0FFFFFBF {
+        // Gid 0 is an empty tile
+        match ground.checked_sub(1)? & 0x0FFFFFBF {
             // Remove first byte and 64 bit
             0 => Some(GroundVarient::Dirt),
             1 => Some(GroundVarient::Wood),
@@ -70,8 +80,18 @@ impl Line {
 
         let r_cross_s = cross(r, s);
         if r_cross_s == 0.0 {
-            // Lines are parallel or coincident
-            return None;
+            // Parallel lines never meet, lines on top of each other meet where they first
+            // overlap along this line
+            if cross(q - p, r) != 0.0 || r.length_sqr() == 0.0 {
+                return None;
+            }
+            let t0 = (q - p).dot(r) / r.length_sqr();
+            let t1 = t0 + s.dot(r) / r.length_sqr();
+            let (t_min, t_max) = (t0.min(t1), t0.max(t1));
+            if t_max < 0.0 || t_min > 1.0 {
+                return None;
+            }
+            return Some(p + r * t_min.max(0.0));
         }
 
         let q_minus_p = q - p;
@@ -86,6 +106,18 @@ impl Line {
         }
     }
 
+    // Unit vector at a right angle to the line, on the side a point is on. Points on the line
+    // get the normal on the left of it.
+    pub fn get_normal(&self, towards: Vector2) -> Vector2 {
+        let dir = self.end - self.start;
+        let normal = Vector2::new(dir.y, -dir.x).normalized();
+        if cross(dir, towards - self.start) > 0.0 {
+            -normal
+        } else {
+            normal
+        }
+    }
+
     // Returns lines from a rectangle (Top, Bottom, Left, Right)
     pub fn from_rect(rect: &Rectangle) -> Vec<Line> {
         vec![
@@ -116,7 +148,8 @@ impl Line {
 impl WallVarient {
     // Used for parsing map data
     pub fn from_raw_u32(wall: u32) -> Option<WallVarient> {
-        match (wall - 1) & 0x0FFFFFBF {
+        // Gid 0 is an empty tile
+        match wall.checked_sub(1)? & 0x0FFFFFBF {
             // Remove first byte and 64 bit
             0 => Some(WallVarient::Staight),
             1 => Some(WallVarient::Elbow),
@@ -131,29 +164,32 @@ impl WallVarient {
 }
 
 impl Wall {
+    // Used for saving map data
+    pub fn to_raw_u32(&self) -> u32 {
+        (WALL_FIRST_GID + self.varient as u32) | self.rotation.to_raw_flags()
+    }
+
     // TODO: Rework all of this
     pub fn get_collider(&self) -> Collider {
         match self.varient {
-            WallVarient::Staight | WallVarient::WhiteStraight => Collider {
-                rects: vec![self.rotation.get_collider_rect()],
-            },
-            WallVarient::Elbow | WallVarient::WhiteElbow => Collider {
-                rects: vec![
-                    self.rotation.get_collider_rect(),
-                    self.rotation.rotate().get_collider_rect(),
-                ],
-            },
-            WallVarient::TinyElbow | WallVarient::WhiteTinyElbow => Collider {
-                rects: vec![match self.rotation {
+            WallVarient::Staight | WallVarient::WhiteStraight => {
+                Collider::from_rects(vec![self.rotation.get_collider_rect()])
+            }
+            WallVarient::Elbow | WallVarient::WhiteElbow => Collider::from_rects(vec![
+                self.rotation.get_collider_rect(),
+                self.rotation.rotate().get_collider_rect(),
+            ]),
+            WallVarient::TinyElbow | WallVarient::WhiteTinyElbow => {
+                Collider::from_rects(vec![match self.rotation {
                     TileRotation::None => Rectangle::new(0.0, 22.0, 10.0, 10.0),
                     TileRotation::One => Rectangle::new(22.0, 22.0, 10.0, 10.0),
                     TileRotation::Two => Rectangle::new(0.0, 0.0, 10.0, 10.0),
                     TileRotation::Three => Rectangle::new(22.0, 0.0, 10.0, 10.0),
-                }],
-            },
-            WallVarient::WhitePillar => Collider {
-                rects: vec![Rectangle::new(9.0, 9.0, 14.0, 14.0)],
-            },
+                }])
+            }
+            WallVarient::WhitePillar => {
+                Collider::from_rects(vec![Rectangle::new(9.0, 9.0, 14.0, 14.0)])
+            }
         }
     }
 }
@@ -163,6 +199,13 @@ pub struct Ground {
     pub rotation: TileRotation,
 }
 
+impl Ground {
+    // Used for saving map data
+    pub fn to_raw_u32(&self) -> u32 {
+        (FLOOR_FIRST_GID + self.varient as u32) | self.rotation.to_raw_flags()
+    }
+}
+
 #[derive(Debug)]
 pub enum TileRotation {
     None,
@@ -171,22 +214,97 @@ pub enum TileRotation {
     Three,
 }
 
-#[derive(Debug)]
+// Shapes something takes up in the world. Polygons have to be convex, with their points going
+// around the outside in order.
+#[derive(Debug, Clone, Default)]
 pub struct Collider {
     pub rects: Vec<Rectangle>,
+    pub circles: Vec<Circle>,
+    pub polygons: Vec<Vec<Vector2>>,
+}
+
+// A single shape of a collider
+#[derive(Clone, Copy)]
+enum Shape<'a> {
+    Rect(&'a Rectangle),
+    Circle(&'a Circle),
+    Polygon(&'a [Vector2]),
 }
 
 impl Collider {
-    pub fn collides(&self, other_collider: &Self) -> Option<Rectangle> {
-        for self_rect in self.rects.iter() {
-            for other_rect in other_collider.rects.iter() {
-                let collision = self_rect.get_collision_rec(other_rect);
-                if let Some(collision) = collision {
-                    return Some(collision);
+    pub fn from_rects(rects: Vec<Rectangle>) -> Collider {
+        Collider {
+            rects,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_circle(circle: Circle) -> Collider {
+        Collider {
+            circles: vec![circle],
+            ..Default::default()
+        }
+    }
+
+    pub fn collides(&self, other_collider: &Self) -> bool {
+        self.get_penetration(other_collider).is_some()
+    }
+
+    // How far and which way this collider has to move to stop overlapping another one. When
+    // more than one of their shapes overlap it is the deepest overlap.
+    pub fn get_penetration(&self, other_collider: &Self) -> Option<Vector2> {
+        let mut deepest: Option<Vector2> = None;
+        for self_shape in self.shapes() {
+            for other_shape in other_collider.shapes() {
+                let Some(penetration) = get_shape_penetration(self_shape, other_shape) else {
+                    continue;
+                };
+                if deepest.is_none_or(|deepest| penetration.length() > deepest.length()) {
+                    deepest = Some(penetration);
                 }
             }
         }
-        None
+        deepest
+    }
+
+    fn shapes(&self) -> impl Iterator<Item = Shape<'_>> {
+        self.rects
+            .iter()
+            .map(Shape::Rect)
+            .chain(self.circles.iter().map(Shape::Circle))
+            .chain(self.polygons.iter().map(|polygon| Shape::Polygon(polygon)))
+    }
+
+    // Smallest rect containing every shape of the collider
+    pub fn get_bounds(&self) -> Rectangle {
+        let corners = self
+            .rects
+            .iter()
+            .flat_map(|rect| {
+                [
+                    Vector2::new(rect.x, rect.y),
+                    Vector2::new(rect.x + rect.width, rect.y + rect.height),
+                ]
+            })
+            .chain(self.circles.iter().flat_map(|circle| {
+                [
+                    Vector2::new(circle.x - circle.radius, circle.y - circle.radius),
+                    Vector2::new(circle.x + circle.radius, circle.y + circle.radius),
+                ]
+            }))
+            .chain(self.polygons.iter().flatten().copied())
+            .collect::<Vec<Vector2>>();
+        let Some(first) = corners.first() else {
+            return Rectangle::new(0.0, 0.0, 0.0, 0.0);
+        };
+        let (mut min, mut max) = (*first, *first);
+        for corner in corners.iter().skip(1) {
+            min.x = min.x.min(corner.x);
+            min.y = min.y.min(corner.y);
+            max.x = max.x.max(corner.x);
+            max.y = max.y.max(corner.y);
+        }
+        Rectangle::new(min.x, min.y, max.x - min.x, max.y - min.y)
     }
 
     pub fn with_pos(&self, pos: Vector2) -> Collider {
@@ -201,19 +319,206 @@ impl Collider {
                     height: rect.height,
                 })
                 .collect(),
+            circles: self
+                .circles
+                .iter()
+                .map(|circle| Circle {
+                    x: circle.x + pos.x,
+                    y: circle.y + pos.y,
+                    radius: circle.radius,
+                })
+                .collect(),
+            polygons: self
+                .polygons
+                .iter()
+                .map(|polygon| polygon.iter().map(|point| *point + pos).collect())
+                .collect(),
+        }
+    }
+}
+
+// How far and which way the first shape has to move to stop overlapping the second one
+fn get_shape_penetration(shape: Shape, other_shape: Shape) -> Option<Vector2> {
+    match (shape, other_shape) {
+        (Shape::Circle(circle), Shape::Circle(other)) => {
+            get_circle_circle_penetration(circle, other)
+        }
+        (Shape::Circle(circle), Shape::Rect(rect)) => get_circle_rect_penetration(circle, rect),
+        (Shape::Rect(rect), Shape::Circle(circle)) => {
+            get_circle_rect_penetration(circle, rect).map(|penetration| -penetration)
+        }
+        (Shape::Circle(circle), Shape::Polygon(polygon)) => {
+            get_circle_polygon_penetration(circle, polygon)
+        }
+        (Shape::Polygon(polygon), Shape::Circle(circle)) => {
+            get_circle_polygon_penetration(circle, polygon).map(|penetration| -penetration)
+        }
+        (Shape::Rect(rect), _) => {
+            get_shape_penetration(Shape::Polygon(&get_rect_points(rect)), other_shape)
+        }
+        (_, Shape::Rect(rect)) => {
+            get_shape_penetration(shape, Shape::Polygon(&get_rect_points(rect)))
+        }
+        (Shape::Polygon(polygon), Shape::Polygon(other)) => get_polygon_penetration(polygon, other),
+    }
+}
+
+// Corners of a rect going clockwise from the top left
+pub fn get_rect_points(rect: &Rectangle) -> [Vector2; 4] {
+    [
+        Vector2::new(rect.x, rect.y),
+        Vector2::new(rect.x + rect.width, rect.y),
+        Vector2::new(rect.x + rect.width, rect.y + rect.height),
+        Vector2::new(rect.x, rect.y + rect.height),
+    ]
+}
+
+// How far and which way a circle has to move to stop overlapping another one
+pub fn get_circle_circle_penetration(circle: &Circle, other: &Circle) -> Option<Vector2> {
+    let offset = Vector2::new(circle.x - other.x, circle.y - other.y);
+    let distance = offset.length();
+    let depth = circle.radius + other.radius - distance;
+    if depth <= 0.0 {
+        return None;
+    }
+    // Circles right on top of each other could go any way
+    let dir = if distance > 0.0 {
+        offset / distance
+    } else {
+        Vector2::new(1.0, 0.0)
+    };
+    Some(dir * depth)
+}
+
+// How far and which way a circle has to move to stop overlapping a rect
+pub fn get_circle_rect_penetration(circle: &Circle, rect: &Rectangle) -> Option<Vector2> {
+    let center = Vector2::new(circle.x, circle.y);
+    let closest = Vector2::new(
+        center.x.clamp(rect.x, rect.x + rect.width),
+        center.y.clamp(rect.y, rect.y + rect.height),
+    );
+    if closest != center {
+        let offset = center - closest;
+        let distance = offset.length();
+        if distance >= circle.radius {
+            return None;
         }
+        return Some(offset / distance * (circle.radius - distance));
     }
+    // The middle of the circle is inside the rect, it goes out the closest side
+    [
+        (center.x - rect.x, Vector2::new(-1.0, 0.0)),
+        (rect.x + rect.width - center.x, Vector2::new(1.0, 0.0)),
+        (center.y - rect.y, Vector2::new(0.0, -1.0)),
+        (rect.y + rect.height - center.y, Vector2::new(0.0, 1.0)),
+    ]
+    .into_iter()
+    .min_by(|a, b| a.0.total_cmp(&b.0))
+    .map(|(distance, normal)| normal * (distance + circle.radius))
+}
+
+// How far and which way a circle has to move to stop overlapping a convex polygon
+pub fn get_circle_polygon_penetration(circle: &Circle, polygon: &[Vector2]) -> Option<Vector2> {
+    let center = Vector2::new(circle.x, circle.y);
+    // Besides the sides of the polygon, the circle can only be kept apart from it along the line
+    // to its closest corner
+    let closest_point = polygon
+        .iter()
+        .min_by(|a, b| a.distance_to(center).total_cmp(&b.distance_to(center)))?;
+    let axes = get_polygon_axes(polygon).chain([(center - *closest_point).normalized()]);
+    get_sat_penetration(
+        axes,
+        |axis| {
+            (
+                center.dot(axis) - circle.radius,
+                center.dot(axis) + circle.radius,
+            )
+        },
+        |axis| project_polygon(polygon, axis),
+    )
+}
+
+// How far and which way a convex polygon has to move to stop overlapping another one
+pub fn get_polygon_penetration(polygon: &[Vector2], other: &[Vector2]) -> Option<Vector2> {
+    get_sat_penetration(
+        get_polygon_axes(polygon).chain(get_polygon_axes(other)),
+        |axis| project_polygon(polygon, axis),
+        |axis| project_polygon(other, axis),
+    )
+}
+
+// Separating axis test, two convex shapes overlap if their shadows on every axis they could be
+// kept apart along overlap. The shortest way out is along the axis they overlap on the least.
+fn get_sat_penetration(
+    axes: impl Iterator<Item = Vector2>,
+    project: impl Fn(Vector2) -> (f32, f32),
+    project_other: impl Fn(Vector2) -> (f32, f32),
+) -> Option<Vector2> {
+    let mut shortest: Option<Vector2> = None;
+    for axis in axes {
+        if axis.length_sqr() == 0.0 {
+            continue;
+        }
+        let (min, max) = project(axis);
+        let (other_min, other_max) = project_other(axis);
+        // Either backwards along the axis until its max is at the other's min, or forwards
+        let (backwards, forwards) = (max - other_min, other_max - min);
+        if backwards <= 0.0 || forwards <= 0.0 {
+            return None;
+        }
+        let penetration = if backwards < forwards {
+            -axis * backwards
+        } else {
+            axis * forwards
+        };
+        if shortest.is_none_or(|shortest| penetration.length() < shortest.length()) {
+            shortest = Some(penetration);
+        }
+    }
+    shortest
+}
+
+// Normals of every side of a polygon
+fn get_polygon_axes(polygon: &[Vector2]) -> impl Iterator<Item = Vector2> + '_ {
+    polygon.iter().enumerate().map(|(i, start)| {
+        let side = polygon[(i + 1) % polygon.len()] - *start;
+        Vector2::new(-side.y, side.x).normalized()
+    })
+}
+
+// Shadow of a polygon on an axis
+fn project_polygon(polygon: &[Vector2], axis: Vector2) -> (f32, f32) {
+    polygon.iter().map(|point| point.dot(axis)).fold(
+        (f32::INFINITY, f32::NEG_INFINITY),
+        |(min, max), distance| (min.min(distance), max.max(distance)),
+    )
 }
 
 impl TileRotation {
     pub fn from_raw_u32(tile: u32) -> TileRotation {
+        Self::try_from_raw_u32(tile)
+            .unwrap_or_else(|| panic!("Tile rotation data corrupted, flags: {:#02x}", tile >> 28))
+    }
+
+    // None for flip flags that aren't a rotation
+    pub fn try_from_raw_u32(tile: u32) -> Option<TileRotation> {
         let flags = tile >> 28; // Get first byte from tile
         match flags {
-            0x0 => Self::None,
-            0x6 => Self::One,
-            0xA => Self::Two,
-            0xC => Self::Three,
-            _ => panic!("Tile rotation data corrupted, flags: {:#02x}", flags),
+            0x0 => Some(Self::None),
+            0x6 => Some(Self::One),
+            0xA => Some(Self::Two),
+            0xC => Some(Self::Three),
+            _ => None,
+        }
+    }
+
+    // Flip flags Tiled uses for the rotation, in the first byte of a tile
+    pub fn to_raw_flags(&self) -> u32 {
+        match self {
+            TileRotation::None => 0x0,
+            TileRotation::One => 0x6 << 28,
+            TileRotation::Two => 0xA << 28,
+            TileRotation::Three => 0xC << 28,
         }
     }
 
@@ -273,3 +578,113 @@ impl TileRotation {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circle(x: f32, y: f32, radius: f32) -> Circle {
+        Circle { x, y, radius }
+    }
+
+    fn assert_near(penetration: Option<Vector2>, expected: Vector2) {
+        let penetration = penetration.expect("Shapes should overlap");
+        assert!(
+            penetration.distance_to(expected) < 0.001,
+            "{penetration:?} isn't {expected:?}"
+        );
+    }
+
+    #[test]
+    fn overlapping_circles_push_apart() {
+        // Centers 6 apart with radii adding up to 10
+        let penetration =
+            get_circle_circle_penetration(&circle(6.0, 0.0, 5.0), &circle(0.0, 0.0, 5.0));
+        assert_near(penetration, Vector2::new(4.0, 0.0));
+        let penetration =
+            get_circle_circle_penetration(&circle(0.0, 0.0, 5.0), &circle(6.0, 0.0, 5.0));
+        assert_near(penetration, Vector2::new(-4.0, 0.0));
+    }
+
+    #[test]
+    fn touching_circles_dont_overlap() {
+        let penetration =
+            get_circle_circle_penetration(&circle(0.0, 10.0, 5.0), &circle(0.0, 0.0, 5.0));
+        assert!(penetration.is_none());
+    }
+
+    #[test]
+    fn circles_on_top_of_each_other_push_out_fully() {
+        let penetration =
+            get_circle_circle_penetration(&circle(3.0, 3.0, 4.0), &circle(3.0, 3.0, 2.0));
+        // No way is closer than any other, so they go right
+        assert_near(penetration, Vector2::new(6.0, 0.0));
+    }
+
+    #[test]
+    fn circle_outside_rect_pushed_away_from_closest_point() {
+        let rect = Rectangle::new(0.0, 0.0, 10.0, 10.0);
+        // Beside the right side
+        let penetration = get_circle_rect_penetration(&circle(13.0, 5.0, 5.0), &rect);
+        assert_near(penetration, Vector2::new(2.0, 0.0));
+        // Off the bottom right corner, 3 away from it diagonally
+        let offset = 3.0 / 2.0_f32.sqrt();
+        let penetration =
+            get_circle_rect_penetration(&circle(10.0 + offset, 10.0 + offset, 5.0), &rect);
+        assert_near(penetration, Vector2::new(2.0, 2.0) / 2.0_f32.sqrt());
+    }
+
+    #[test]
+    fn circle_inside_rect_pushed_out_closest_side() {
+        let rect = Rectangle::new(0.0, 0.0, 10.0, 20.0);
+        // 2 from the top, further from every other side
+        let penetration = get_circle_rect_penetration(&circle(5.0, 2.0, 3.0), &rect);
+        assert_near(penetration, Vector2::new(0.0, -5.0));
+    }
+
+    #[test]
+    fn overlapping_squares_push_out_least_overlapping_axis() {
+        let square = get_rect_points(&Rectangle::new(0.0, 0.0, 10.0, 10.0));
+        let other = get_rect_points(&Rectangle::new(7.0, 4.0, 10.0, 10.0));
+        assert_near(
+            get_polygon_penetration(&other, &square),
+            Vector2::new(3.0, 0.0),
+        );
+        assert_near(
+            get_polygon_penetration(&square, &other),
+            Vector2::new(-3.0, 0.0),
+        );
+        let apart = get_rect_points(&Rectangle::new(11.0, 0.0, 10.0, 10.0));
+        assert!(get_polygon_penetration(&square, &apart).is_none());
+    }
+
+    #[test]
+    fn square_pushed_out_of_slanted_triangle_side() {
+        // Right angled triangle with its long side facing down right
+        let triangle = [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(20.0, 0.0),
+            Vector2::new(0.0, 20.0),
+        ];
+        // Top left corner of the square pokes 1 past the long side on both axes
+        let square = get_rect_points(&Rectangle::new(9.0, 9.0, 4.0, 4.0));
+        assert_near(
+            get_polygon_penetration(&square, &triangle),
+            Vector2::new(1.0, 1.0),
+        );
+        assert_near(
+            get_polygon_penetration(&triangle, &square),
+            Vector2::new(-1.0, -1.0),
+        );
+    }
+
+    #[test]
+    fn swapping_shapes_flips_penetration() {
+        let rect = Rectangle::new(0.0, 0.0, 10.0, 10.0);
+        let circle = circle(13.0, 4.0, 5.0);
+        let circle_out = get_shape_penetration(Shape::Circle(&circle), Shape::Rect(&rect));
+        let rect_out = get_shape_penetration(Shape::Rect(&rect), Shape::Circle(&circle));
+        assert_near(circle_out, Vector2::new(2.0, 0.0));
+        assert_near(rect_out, Vector2::new(-2.0, 0.0));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::get_xml_attribute;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+};
+
+// Frames of an animated tile, each the id of the tile to show and for how many seconds
+pub struct TileAnimation {
+    frames: Vec<(u32, f32)>,
+    length: f32,
+}
+
+impl TileAnimation {
+    // Tile shown at a point in time, looping over the frames
+    pub fn get_frame(&self, time: f32) -> u32 {
+        let mut time = time % self.length;
+        for (tile, duration) in self.frames.iter() {
+            if time < *duration {
+                return *tile;
+            }
+            time -= duration;
+        }
+        self.frames[self.frames.len() - 1].0
+    }
+}
+
+// Every animated tile in a tileset, as set up in Tiled's tile animation editor
+#[derive(Default)]
+pub struct TileAnimations {
+    animations: HashMap<u32, TileAnimation>,
+}
+
+impl TileAnimations {
+    pub fn load(path: &str) -> Result<TileAnimations, String> {
+        let file = std::fs::File::open(path).map_err(|err| format!("{path}: {err}"))?;
+        Self::read(BufReader::new(file))
+    }
+
+    pub fn read(reader: impl BufRead) -> Result<TileAnimations, String> {
+        let mut animations = HashMap::new();
+        let mut current_tile: Option<(u32, Vec<(u32, f32)>)> = None;
+        for line in reader.lines() {
+            let line = line.map_err(|err| err.to_string())?;
+            let element = line.trim();
+            if element.starts_with("<tile ") {
+                let id = get_xml_attribute(element, "id")
+                    .and_then(|id| id.parse::<u32>().ok())
+                    .ok_or(format!("Tile without an id: {element}"))?;
+                current_tile = Some((id, vec![]));
+            } else if element.starts_with("</tile>") {
+                if let Some((id, frames)) = current_tile.take() {
+                    let length = frames.iter().map(|(_, duration)| duration).sum::<f32>();
+                    // Frames that take no time would make the animation loop forever
+                    if length > 0.0 {
+                        animations.insert(id, TileAnimation { frames, length });
+                    }
+                }
+            } else if let Some((id, frames)) = current_tile.as_mut() {
+                if !element.starts_with("<frame ") {
+                    continue;
+                }
+                let tile = get_xml_attribute(element, "tileid")
+                    .and_then(|tile| tile.parse::<u32>().ok())
+                    .ok_or(format!("Frame of tile {id} is missing its tileid"))?;
+                // Tiled saves durations in milliseconds
+                let duration = get_xml_attribute(element, "duration")
+                    .and_then(|duration| duration.parse::<u32>().ok())
+                    .ok_or(format!("Frame of tile {id} is missing its duration"))?;
+                frames.push((tile, duration as f32 / 1000.0));
+            }
+        }
+        Ok(TileAnimations { animations })
+    }
+
+    pub fn is_animated(&self, tile: u32) -> bool {
+        self.animations.contains_key(&tile)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.animations.is_empty()
+    }
+
+    // Tile to draw for a tile at a point in time, the tile itself if it isn't animated
+    pub fn get_frame(&self, tile: u32, time: f32) -> u32 {
+        self.animations
+            .get(&tile)
+            .map(|animation| animation.get_frame(time))
+            .unwrap_or(tile)
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    tile_animation::TileAnimations, world_map::CHUNK_SIZE, ImprovedCamera, TileRange, TileRotation,
+    WorldMap, TILE_SIZE,
+};
+use raylib::prelude::*;
+use std::collections::HashMap;
+
+// Static layers of the map that get baked
+#[derive(Clone, Copy)]
+pub enum TileLayer {
+    Floor,
+    Walls,
+}
+
+// A tile sheet and the animations set up for its tiles in Tiled
+pub struct Tileset {
+    pub sheet: Texture2D,
+    pub animations: TileAnimations,
+}
+
+impl Tileset {
+    pub fn load(
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        sheet_path: &str,
+        tileset_path: &str,
+    ) -> Result<Tileset, String> {
+        Ok(Tileset {
+            sheet: rl.load_texture(thread, sheet_path)?,
+            animations: TileAnimations::load(tileset_path)?,
+        })
+    }
+}
+
+// A baked chunk of a tile layer
+struct CachedChunk {
+    texture: RenderTexture2D,
+    dirty: bool,
+}
+
+// Pre-bakes a static tile layer into chunked render textures so that it can be drawn with a
+// handful of draw calls instead of one per tile. Chunks are only re-baked when a tile in them
+// changes. Animated tiles are left out of the bake and drawn every frame on top instead.
+pub struct TileLayerCache {
+    layer: TileLayer,
+    chunks: HashMap<(u32, u32), CachedChunk>,
+    map_id: Option<u32>,
+}
+
+impl TileLayerCache {
+    pub fn new(layer: TileLayer) -> TileLayerCache {
+        TileLayerCache {
+            layer,
+            chunks: HashMap::new(),
+            map_id: None,
+        }
+    }
+
+    // Allocates a render texture for every newly loaded chunk of the map and frees the ones
+    // of chunks that got unloaded
+    pub fn sync_chunks(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread, map: &WorldMap) {
+        let chunk_pixels = CHUNK_SIZE * TILE_SIZE as u32;
+        // A different map was loaded, none of the baked chunks are valid anymore
+        if self.map_id != Some(map.id()) {
+            self.chunks.clear();
+            self.map_id = Some(map.id());
+        }
+        self.chunks
+            .retain(|chunk_pos, _| map.is_chunk_loaded(*chunk_pos));
+        for chunk_pos in map.loaded_chunks() {
+            self.chunks.entry(chunk_pos).or_insert_with(|| CachedChunk {
+                texture: rl
+                    .load_render_texture(thread, chunk_pixels, chunk_pixels)
+                    .unwrap(),
+                dirty: true,
+            });
+        }
+    }
+
+    // Flags the chunk holding a tile to be re-baked before it is next drawn
+    pub fn mark_tile_dirty(&mut self, x: u32, y: u32) {
+        if let Some(chunk) = self.chunks.get_mut(&(x / CHUNK_SIZE, y / CHUNK_SIZE)) {
+            chunk.dirty = true;
+        }
+    }
+
+    // Flags every chunk to be re-baked, for when the tile sheet changed
+    pub fn mark_all_dirty(&mut self) {
+        for chunk in self.chunks.values_mut() {
+            chunk.dirty = true;
+        }
+    }
+
+    // Re-bakes every dirty chunk from the map
+    pub fn bake(
+        &mut self,
+        d: &mut RaylibDrawHandle,
+        thread: &RaylibThread,
+        map: &WorldMap,
+        tileset: &Tileset,
+    ) {
+        for (&(chunk_x, chunk_y), chunk) in self.chunks.iter_mut() {
+            if !chunk.dirty {
+                continue;
+            }
+            let range = map.get_chunk_tile_range((chunk_x, chunk_y));
+            let chunk_pos = get_chunk_world_pos(chunk_x, chunk_y);
+            let (tile_sheet, animations) = (&tileset.sheet, &tileset.animations);
+            let mut tg = d.begin_texture_mode(thread, &mut chunk.texture);
+            tg.clear_background(Color::new(0, 0, 0, 0));
+            match self.layer {
+                TileLayer::Floor => {
+                    for tile in map.iter_tiles(&range) {
+                        if animations.is_animated(tile.ground.varient as u32) {
+                            continue;
+                        }
+                        draw_tile(
+                            &mut tg,
+                            tile_sheet,
+                            tile.ground.varient as u32,
+                            &tile.ground.rotation,
+                            tile.world_pos() - chunk_pos,
+                            1.0,
+                        );
+                    }
+                }
+                TileLayer::Walls => {
+                    for (tile_pos, wall) in map.iter_walls(&range) {
+                        if animations.is_animated(wall.varient as u32) {
+                            continue;
+                        }
+                        draw_tile(
+                            &mut tg,
+                            tile_sheet,
+                            wall.varient as u32,
+                            &wall.rotation,
+                            tile_pos - chunk_pos,
+                            1.0,
+                        );
+                    }
+                }
+            }
+            chunk.dirty = false;
+        }
+    }
+
+    // Draws the current frame of every visible animated tile with the camera transform
+    pub fn draw_animated(
+        &self,
+        d: &mut impl RaylibDraw,
+        camera: &Camera2D,
+        map: &WorldMap,
+        visible_tiles: &TileRange,
+        tileset: &Tileset,
+        time: f32,
+    ) {
+        let animations = &tileset.animations;
+        if animations.is_empty() {
+            return;
+        }
+        for tile in map.iter_tiles(visible_tiles) {
+            let (varient, rotation) = match self.layer {
+                TileLayer::Floor => (tile.ground.varient as u32, &tile.ground.rotation),
+                TileLayer::Walls => match tile.wall {
+                    Some(wall) => (wall.varient as u32, &wall.rotation),
+                    None => continue,
+                },
+            };
+            if animations.is_animated(varient) {
+                draw_tile(
+                    d,
+                    &tileset.sheet,
+                    animations.get_frame(varient, time),
+                    rotation,
+                    camera.to_screen(tile.world_pos()),
+                    camera.zoom,
+                );
+            }
+        }
+    }
+
+    // Draws the baked chunks overlapping the visible tiles with the camera transform
+    pub fn draw(&self, d: &mut impl RaylibDraw, camera: &Camera2D, visible_tiles: &TileRange) {
+        let chunk_world_size = CHUNK_SIZE as f32 * TILE_SIZE;
+        for chunk_y in visible_tiles.y.start / CHUNK_SIZE..visible_tiles.y.end.div_ceil(CHUNK_SIZE)
+        {
+            for chunk_x in
+                visible_tiles.x.start / CHUNK_SIZE..visible_tiles.x.end.div_ceil(CHUNK_SIZE)
+            {
+                let Some(chunk) = self.chunks.get(&(chunk_x, chunk_y)) else {
+                    continue;
+                };
+                let chunk_pos = get_chunk_world_pos(chunk_x, chunk_y);
+                d.draw_texture_pro(
+                    &chunk.texture,
+                    // Render textures are stored upside down
+                    Rectangle::new(0.0, 0.0, chunk_world_size, -chunk_world_size),
+                    Rectangle::new(
+                        camera.to_screen_x(chunk_pos.x),
+                        camera.to_screen_y(chunk_pos.y),
+                        chunk_world_size * camera.zoom + 0.001 * TILE_SIZE,
+                        chunk_world_size * camera.zoom + 0.001 * TILE_SIZE,
+                    ),
+                    Vector2::zero(),
+                    0.0,
+                    Color::WHITE,
+                );
+            }
+        }
+    }
+}
+
+// Draws a single tile from a tile sheet at a position, scaled up from its size in the sheet
+pub fn draw_tile(
+    d: &mut impl RaylibDraw,
+    tile_sheet: &Texture2D,
+    varient: u32,
+    rotation: &TileRotation,
+    pos: Vector2,
+    scale: f32,
+) {
+    let sheet_width = tile_sheet.width() as u32 / TILE_SIZE as u32;
+    let tile_x = varient % sheet_width;
+    let tile_y = varient / sheet_width;
+    let pos = pos + rotation.get_rotation_offset() * scale;
+
+    d.draw_texture_pro(
+        tile_sheet,
+        Rectangle::new(
+            tile_x as f32 * TILE_SIZE,
+            tile_y as f32 * TILE_SIZE,
+            TILE_SIZE,
+            TILE_SIZE,
+        ),
+        Rectangle::new(pos.x, pos.y, TILE_SIZE * scale, TILE_SIZE * scale),
+        Vector2::zero(),
+        rotation.get_angle(),
+        Color::WHITE,
+    );
+}
+
+fn get_chunk_world_pos(chunk_x: u32, chunk_y: u32) -> Vector2 {
+    Vector2::new(
+        (chunk_x * CHUNK_SIZE) as f32 * TILE_SIZE,
+        (chunk_y * CHUNK_SIZE) as f32 * TILE_SIZE,
+    )
+}
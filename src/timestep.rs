@@ -0,0 +1,79 @@
+// How many times a second the world is updated, whatever the frame rate is
+pub const TICK_RATE: f32 = 60.0;
+pub const TICK_TIME: f32 = 1.0 / TICK_RATE;
+// Most ticks run in one frame, so after a long frame the game slows down for a moment rather
+// than spending every frame after it catching up
+const MAX_TICKS_PER_FRAME: u32 = 8;
+
+// Runs the world at a fixed tick rate. Frame time is saved up and spent a tick at a time, and
+// what is left over is how far between two ticks the frame is drawn.
+pub struct FixedTimestep {
+    accumulator: f32,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FixedTimestep {
+    pub fn new() -> FixedTimestep {
+        FixedTimestep { accumulator: 0.0 }
+    }
+
+    // Adds the time a frame took and returns how many ticks to run for it
+    pub fn advance(&mut self, frame_time: f32) -> u32 {
+        self.accumulator += frame_time;
+        let ticks = (self.accumulator / TICK_TIME) as u32;
+        self.accumulator -= ticks as f32 * TICK_TIME;
+        if ticks > MAX_TICKS_PER_FRAME {
+            self.accumulator = 0.0;
+            return MAX_TICKS_PER_FRAME;
+        }
+        ticks
+    }
+
+    // How far from the last tick to the next one it is, from 0 to 1, to draw things between where
+    // they were and where they are
+    pub fn get_alpha(&self) -> f32 {
+        (self.accumulator / TICK_TIME).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ticks run over a number of frames of the same length
+    fn run_frames(frames: u32, frame_time: f32) -> u32 {
+        let mut timestep = FixedTimestep::new();
+        (0..frames).map(|_| timestep.advance(frame_time)).sum()
+    }
+
+    #[test]
+    fn second_of_frames_is_a_second_of_ticks() {
+        assert_eq!(run_frames(30, 1.0 / 30.0), 60);
+        assert_eq!(run_frames(60, 1.0 / 60.0), 60);
+        assert_eq!(run_frames(240, 1.0 / 240.0), 60);
+    }
+
+    #[test]
+    fn alpha_stays_between_ticks() {
+        let mut timestep = FixedTimestep::new();
+        for frame_time in [0.001, 0.007, 1.0 / 60.0, 0.02, 1.0 / 144.0, 0.05] {
+            timestep.advance(frame_time);
+            let alpha = timestep.get_alpha();
+            assert!((0.0..=1.0).contains(&alpha));
+        }
+    }
+
+    #[test]
+    fn long_frame_is_capped_and_forgotten() {
+        let mut timestep = FixedTimestep::new();
+        assert_eq!(timestep.advance(1.0), MAX_TICKS_PER_FRAME);
+        assert_eq!(timestep.get_alpha(), 0.0);
+        // Nothing was saved up from the long frame
+        assert_eq!(timestep.advance(TICK_TIME * 0.5), 0);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{map_objects::ObjectKind, WorldMap};
+use raylib::prelude::*;
+use std::collections::HashMap;
+
+// Something that can set off triggers, the player or anything else moving around the world
+pub type EntityId = u32;
+pub const PLAYER_ENTITY: EntityId = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerEventKind {
+    Enter,
+    Exit,
+    // Fired every update the entity is still inside
+    Stay,
+}
+
+#[derive(Clone, Debug)]
+pub struct TriggerEvent {
+    pub kind: TriggerEventKind,
+    // Id of the trigger object in the map
+    pub trigger: u32,
+    // Name of the trigger object, so events can be matched to the map without knowing ids
+    pub name: String,
+    pub entity: EntityId,
+}
+
+// Keeps track of which entities are inside which trigger and extraction zones of the map
+#[derive(Default)]
+pub struct TriggerSystem {
+    map_id: Option<u32>,
+    // Trigger object ids and the entities inside them, with the name of the trigger
+    overlapping: HashMap<(u32, EntityId), String>,
+    // Events since they were last drained
+    events: Vec<TriggerEvent>,
+}
+
+impl TriggerSystem {
+    pub fn new() -> TriggerSystem {
+        Self::default()
+    }
+
+    // Checks every entity against the triggers of the map, adding to the events that haven't
+    // been drained yet. Entities that aren't passed in anymore exit the triggers they were in.
+    pub fn update(&mut self, map: &WorldMap, entities: &[(EntityId, Vector2)]) {
+        // Nothing is inside the triggers of a map that was just loaded, the ids of the old
+        // map's objects mean nothing in it
+        if self.map_id != Some(map.id()) {
+            self.map_id = Some(map.id());
+            self.exit_all();
+        }
+
+        let mut overlapping = HashMap::new();
+        for trigger in map.objects.iter().filter(|object| {
+            matches!(
+                object.get_kind(),
+                Ok(ObjectKind::Trigger | ObjectKind::ExtractionZone)
+            )
+        }) {
+            for (entity, pos) in entities.iter() {
+                if !trigger.contains(*pos) {
+                    continue;
+                }
+                let kind = if self.overlapping.contains_key(&(trigger.id, *entity)) {
+                    TriggerEventKind::Stay
+                } else {
+                    TriggerEventKind::Enter
+                };
+                overlapping.insert((trigger.id, *entity), trigger.name.clone());
+                self.events.push(TriggerEvent {
+                    kind,
+                    trigger: trigger.id,
+                    name: trigger.name.clone(),
+                    entity: *entity,
+                });
+            }
+        }
+        for ((trigger, entity), name) in self.overlapping.drain() {
+            if !overlapping.contains_key(&(trigger, entity)) {
+                self.events.push(TriggerEvent {
+                    kind: TriggerEventKind::Exit,
+                    trigger,
+                    name,
+                    entity,
+                });
+            }
+        }
+        self.overlapping = overlapping;
+    }
+
+    // Every entity exits the triggers it is in, for when the map is unloaded
+    pub fn exit_all(&mut self) {
+        for ((trigger, entity), name) in self.overlapping.drain() {
+            self.events.push(TriggerEvent {
+                kind: TriggerEventKind::Exit,
+                trigger,
+                name,
+                entity,
+            });
+        }
+    }
+
+    pub fn events(&self) -> &[TriggerEvent] {
+        &self.events
+    }
+
+    // Takes the events of every update since the last call. Read once a frame, while a frame can
+    // run more than one update.
+    pub fn drain_events(&mut self) -> Vec<TriggerEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Names of the triggers an entity is inside
+    pub fn get_triggers_containing(&self, entity: EntityId) -> Vec<&str> {
+        let mut names = self
+            .overlapping
+            .iter()
+            .filter(|((_, inside), _)| *inside == entity)
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<&str>>();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{simulation::Simulation, world_map::write_test_tmx};
+
+    // Map with a trigger zone covering the middle tile
+    fn simulation_with_trigger(name: &str) -> (Simulation, String) {
+        let objects = r#" <objectgroup id="3" name="Triggers">
+  <object id="1" name="middle" type="trigger" x="32" y="32" width="32" height="32"/>
+ </objectgroup>
+"#;
+        let path = write_test_tmx(
+            name,
+            &["1,1,1", "1,1,1", "1,1,1"],
+            &["0,0,0", "0,0,0", "0,0,0"],
+            objects,
+        );
+        (Simulation::new(WorldMap::load_from_file(&path)), path)
+    }
+
+    fn kinds(events: &[TriggerEvent]) -> Vec<TriggerEventKind> {
+        events.iter().map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn events_are_kept_until_drained() {
+        let (simulation, path) = simulation_with_trigger("trigger_events.tmx");
+        let map = &simulation.world.map;
+        let mut triggers = TriggerSystem::new();
+        // Two updates in one frame, in and straight back out
+        triggers.update(map, &[(PLAYER_ENTITY, Vector2::new(48.0, 48.0))]);
+        triggers.update(map, &[(PLAYER_ENTITY, Vector2::new(16.0, 16.0))]);
+        let events = triggers.drain_events();
+        assert_eq!(
+            kinds(&events),
+            vec![TriggerEventKind::Enter, TriggerEventKind::Exit]
+        );
+        assert!(events.iter().all(|event| event.name == "middle"));
+        assert!(triggers.drain_events().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn changing_map_exits_triggers() {
+        let (mut simulation, path) = simulation_with_trigger("trigger_reload.tmx");
+        simulation.player.pos = Vector2::new(48.0, 48.0);
+        simulation.world.update_triggers(&simulation.player);
+        assert_eq!(
+            simulation
+                .world
+                .triggers
+                .get_triggers_containing(PLAYER_ENTITY),
+            vec!["middle"]
+        );
+        simulation.world.triggers.drain_events();
+
+        simulation
+            .world
+            .reload_map(&mut simulation.light_engine)
+            .unwrap();
+        assert_eq!(
+            kinds(&simulation.world.triggers.drain_events()),
+            vec![TriggerEventKind::Exit]
+        );
+        assert!(simulation
+            .world
+            .triggers
+            .get_triggers_containing(PLAYER_ENTITY)
+            .is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -12,6 +12,14 @@ impl UIRenderer {
 pub struct DebugInfo {
     pub info: Vec<String>,
     pub debug: bool,
+    // Shown even when the debug info is shrunk
+    pub errors: Vec<String>,
+}
+
+impl Default for DebugInfo {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DebugInfo {
@@ -19,6 +27,7 @@ impl DebugInfo {
         DebugInfo {
             info: vec![],
             debug: false,
+            errors: vec![],
         }
     }
     pub fn update(&mut self, rl: &mut RaylibHandle) {
@@ -34,6 +43,16 @@ impl DebugInfo {
     }
     pub fn draw(&self, d: &mut RaylibDrawHandle) {
         let font_size = 40;
+        let lines = if self.debug { self.info.len() } else { 1 };
+        for (i, error) in self.errors.iter().enumerate() {
+            d.draw_text(
+                error,
+                font_size / 5,
+                (lines + i) as i32 * font_size + font_size / 10,
+                font_size / 2,
+                Color::RED,
+            );
+        }
         if self.debug {
             for (i, info) in self.info.iter().enumerate() {
                 d.draw_text(
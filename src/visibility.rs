@@ -0,0 +1,165 @@
+use crate::{WorldMap, TILE_SIZE};
+use raylib::prelude::*;
+use std::f32::consts::PI;
+
+// How far the player can see, in world units
+pub const VIEW_DISTANCE: f32 = 400.0;
+// Rays are also cast just past both sides of every wall corner, so they hit what is behind it
+const CORNER_OFFSET: f32 = 0.0001;
+// Rays cast evenly around the origin so the edge of the view is round where nothing blocks it
+const EDGE_RAYS: u32 = 64;
+
+// What the player can see from where they are, as a polygon around them that stops at walls,
+// and the tiles they have seen before
+pub struct Visibility {
+    pub origin: Vector2,
+    // Outline of everything that can be seen, sorted by the angle around the origin
+    pub polygon: Vec<Vector2>,
+    angles: Vec<f32>,
+    map_id: Option<u32>,
+    map_width: u32,
+    explored: Vec<bool>,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Visibility {
+    pub fn new() -> Visibility {
+        Visibility {
+            origin: Vector2::zero(),
+            polygon: vec![],
+            angles: vec![],
+            map_id: None,
+            map_width: 0,
+            explored: vec![],
+        }
+    }
+
+    // Works out what can be seen from a point by casting rays at the corners of every wall
+    // collider in view, then marks the tiles in it as explored
+    pub fn update(&mut self, map: &WorldMap, origin: Vector2) {
+        // Nothing has been seen yet on a map that was just loaded
+        if self.map_id != Some(map.id()) {
+            self.map_id = Some(map.id());
+            self.map_width = map.width;
+            self.explored = vec![false; (map.width * map.height) as usize];
+        }
+        self.origin = origin;
+
+        let view_rect = Rectangle::new(
+            origin.x - VIEW_DISTANCE,
+            origin.y - VIEW_DISTANCE,
+            VIEW_DISTANCE * 2.0,
+            VIEW_DISTANCE * 2.0,
+        );
+        let view_tiles = map.get_tile_range(&view_rect, 0);
+        let mut edges = vec![];
+        for (tile_pos, wall) in map.iter_walls(&view_tiles) {
+            for rect in wall.get_collider().with_pos(tile_pos).rects {
+                let corners = [
+                    Vector2::new(rect.x, rect.y),
+                    Vector2::new(rect.x + rect.width, rect.y),
+                    Vector2::new(rect.x + rect.width, rect.y + rect.height),
+                    Vector2::new(rect.x, rect.y + rect.height),
+                ];
+                for i in 0..4 {
+                    edges.push((corners[i], corners[(i + 1) % 4]));
+                }
+            }
+        }
+
+        let mut angles = (0..EDGE_RAYS)
+            .map(|i| i as f32 / EDGE_RAYS as f32 * PI * 2.0 - PI)
+            .collect::<Vec<f32>>();
+        for (corner, _) in edges.iter() {
+            let angle = (corner.y - origin.y).atan2(corner.x - origin.x);
+            angles.extend([angle - CORNER_OFFSET, angle, angle + CORNER_OFFSET]);
+        }
+        angles.sort_by(f32::total_cmp);
+        angles.dedup();
+
+        self.polygon = angles
+            .iter()
+            .map(|angle| {
+                let dir = Vector2::new(angle.cos(), angle.sin());
+                let distance = edges
+                    .iter()
+                    .filter_map(|(start, end)| get_ray_hit_distance(origin, dir, *start, *end))
+                    .fold(VIEW_DISTANCE, f32::min);
+                origin + dir * distance
+            })
+            .collect();
+        self.angles = angles;
+
+        // Tiles count as seen when their middle or any of their quarters can be seen, so walls
+        // only seen from one side still get remembered
+        for (x, y) in view_tiles.iter() {
+            let tile_pos = Vector2::new(x as f32, y as f32) * TILE_SIZE;
+            let seen = [
+                (0.5, 0.5),
+                (0.25, 0.25),
+                (0.75, 0.25),
+                (0.25, 0.75),
+                (0.75, 0.75),
+            ]
+            .iter()
+            .any(|(offset_x, offset_y)| {
+                self.is_visible(tile_pos + Vector2::new(*offset_x, *offset_y) * TILE_SIZE)
+            });
+            if seen {
+                self.explored[(y * self.map_width + x) as usize] = true;
+            }
+        }
+    }
+
+    // Whether a point is inside the visibility polygon
+    pub fn is_visible(&self, pos: Vector2) -> bool {
+        if self.polygon.is_empty() || pos.distance_to(self.origin) > VIEW_DISTANCE {
+            return false;
+        }
+        // The polygon is a fan around the origin, so only the edge at the point's angle matters
+        let angle = (pos.y - self.origin.y).atan2(pos.x - self.origin.x);
+        let next = self
+            .angles
+            .partition_point(|edge_angle| *edge_angle <= angle);
+        let start = self.polygon[(next + self.polygon.len() - 1) % self.polygon.len()];
+        let end = self.polygon[next % self.polygon.len()];
+        // Same side of the edge as the origin
+        let side = |point: Vector2| {
+            (end.x - start.x) * (point.y - start.y) - (end.y - start.y) * (point.x - start.x)
+        };
+        side(pos) * side(self.origin) >= 0.0
+    }
+
+    // Whether a tile has been seen since the map was loaded
+    pub fn is_explored(&self, x: u32, y: u32) -> bool {
+        x < self.map_width
+            && self
+                .explored
+                .get((y * self.map_width + x) as usize)
+                .copied()
+                .unwrap_or(false)
+    }
+}
+
+// Distance along a ray to where it crosses a line segment, None if it doesn't
+fn get_ray_hit_distance(
+    origin: Vector2,
+    dir: Vector2,
+    start: Vector2,
+    end: Vector2,
+) -> Option<f32> {
+    let segment = end - start;
+    let denominator = dir.x * segment.y - dir.y * segment.x;
+    if denominator.abs() < f32::EPSILON {
+        return None;
+    }
+    let to_start = start - origin;
+    let distance = (to_start.x * segment.y - to_start.y * segment.x) / denominator;
+    let along_segment = (to_start.x * dir.y - to_start.y * dir.x) / denominator;
+    (distance >= 0.0 && (0.0..=1.0).contains(&along_segment)).then_some(distance)
+}
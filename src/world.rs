@@ -1,25 +1,225 @@
-use crate::{bullet::Bullet, day_cycle::DayCycle, ImprovedCamera, LightEngine, Player, WorldMap};
+use crate::{
+    bullet::Bullet,
+    day_cycle::DayCycle,
+    ground_material::{GroundMaterial, GroundMaterials, ImpactEffect},
+    map_objects::{parse_tiled_color, MapObject},
+    navigation::NavGrid,
+    triggers::{TriggerSystem, PLAYER_ENTITY},
+    visibility::Visibility,
+    Light, LightEngine, LightHandle, Player, WorldMap, FLOOR_TILESET_PATH, TILE_SIZE,
+};
 use rand::Rng;
 use raylib::prelude::*;
+use std::path::Path;
+
+// Class given to objects in Tiled that move the player to another map
+pub const TRANSITION_CLASS: &str = "transition";
+// Class given to objects in Tiled that are radial lights
+pub const LIGHT_CLASS: &str = "light";
+// Classes of spawn points and zones placed in Tiled
+pub const PLAYER_SPAWN_CLASS: &str = "player_spawn";
+pub const ENEMY_SPAWNER_CLASS: &str = "enemy_spawner";
+pub const TRIGGER_CLASS: &str = "trigger";
+pub const EXTRACTION_CLASS: &str = "extraction";
+// Seconds footstep noises and bullet impacts stay around for
+const NOISE_LIFETIME: f32 = 0.5;
+pub const IMPACT_LIFETIME: f32 = 1.5;
+
+// A sound made in the world, like a footstep
+pub struct Noise {
+    pub pos: Vector2,
+    // How far away it can be heard, in world units
+    pub radius: f32,
+    // Name of the set of sounds to play
+    pub sound: String,
+    pub time_left: f32,
+}
+
+// Mark left where a bullet landed on the ground
+pub struct Impact {
+    pub pos: Vector2,
+    pub effect: ImpactEffect,
+    pub time_left: f32,
+}
 
 pub struct World {
     pub map: WorldMap,
+    pub map_path: String,
     pub day_cycle: DayCycle,
     pub bullets: Vec<Bullet>,
+    pub ground_materials: GroundMaterials,
+    pub noises: Vec<Noise>,
+    pub impacts: Vec<Impact>,
+    // Where the player can walk, kept in sync with the walls of the map
+    pub navigation: NavGrid,
+    pub triggers: TriggerSystem,
+    // What the player can see and has seen of the map
+    pub visibility: Visibility,
+    // Lights placed in the map, despawned when the map is unloaded
+    map_lights: Vec<LightHandle>,
+    // Set while the player is standing in a transition zone, so arriving in one doesn't
+    // send them straight back
+    in_transition: bool,
 }
 
 impl World {
-    pub fn new(light_engine: &mut LightEngine) -> World {
-        Self {
-            map: WorldMap::load_from_file("assets/maps/map0.tmx", 30, 20),
+    pub fn new(light_engine: &mut LightEngine, map_path: &str) -> World {
+        Self::from_map(light_engine, WorldMap::stream_from_file(map_path))
+    }
+
+    // World around a map that is already loaded, or made in code
+    pub fn from_map(light_engine: &mut LightEngine, map: WorldMap) -> World {
+        let mut world = Self {
+            map_path: map.source_path.clone().unwrap_or_default(),
+            map,
             day_cycle: DayCycle::new(light_engine),
             bullets: vec![],
+            ground_materials: GroundMaterials::load(FLOOR_TILESET_PATH)
+                .expect("Unable to load ground materials"),
+            noises: vec![],
+            impacts: vec![],
+            navigation: NavGrid::new(Player::COLLIDER_SIZE / 2.0),
+            triggers: TriggerSystem::new(),
+            visibility: Visibility::new(),
+            map_lights: vec![],
+            in_transition: false,
+        };
+        world.spawn_map_lights(light_engine);
+        world
+    }
+
+    // Loads the current map again from disk, keeping the loaded one if the file can't be parsed
+    pub fn reload_map(&mut self, light_engine: &mut LightEngine) -> Result<(), String> {
+        let map = WorldMap::try_load_from_file(&self.map_path)?;
+        self.replace_map(light_engine, map);
+        Ok(())
+    }
+
+    fn replace_map(&mut self, light_engine: &mut LightEngine, map: WorldMap) {
+        for light in self.map_lights.drain(..) {
+            light_engine.despawn_light(light);
         }
+        self.map = map;
+        self.triggers.exit_all();
+        self.bullets.clear();
+        self.noises.clear();
+        self.impacts.clear();
+        self.spawn_map_lights(light_engine);
     }
 
-    pub fn spawn_bullet(&mut self, rl: &RaylibHandle, camera: &Camera2D, player: &Player) {
-        let player_screen_pos = camera.to_screen(player.pos);
-        let mouse_pos = rl.get_mouse_position();
+    // Spawns a radial light for every light object in the map, using its radius and color
+    // properties when it has them
+    fn spawn_map_lights(&mut self, light_engine: &mut LightEngine) {
+        for object in self.map.objects.iter() {
+            if object.class != LIGHT_CLASS {
+                continue;
+            }
+            let radius = object
+                .get_property("radius")
+                .and_then(|radius| radius.parse::<f32>().ok())
+                .unwrap_or(Light::default_radial().radius());
+            let color = object
+                .get_property("color")
+                .and_then(parse_tiled_color)
+                .unwrap_or(Color::WHITE);
+            let light = Light::Radial {
+                pos: object.get_center(),
+                color: color.into(),
+                radius,
+            };
+            if let Ok(light) = light_engine.spawn_light(light) {
+                self.map_lights.push(light);
+            }
+        }
+    }
+
+    // Moves the player to another map when they walk into a transition zone. Transitions need a
+    // target_map property with the name of the map file next to the current one, and a target
+    // property with the name of the object to place the player at. Returns true if the map
+    // changed.
+    pub fn handle_map_transitions(
+        &mut self,
+        light_engine: &mut LightEngine,
+        player: &mut Player,
+    ) -> bool {
+        let Some(transition) = self
+            .map
+            .objects
+            .iter()
+            .find(|object| object.class == TRANSITION_CLASS && object.contains(player.pos))
+        else {
+            self.in_transition = false;
+            return false;
+        };
+        // Only transition when entering a zone
+        if self.in_transition {
+            return false;
+        }
+
+        let name = transition.name.clone();
+        let target = self.open_transition_target(transition);
+        // Set either way, so a transition that leads nowhere isn't tried again every tick
+        self.in_transition = true;
+        let (map, map_path, target_pos) = match target {
+            Ok(target) => target,
+            Err(err) => {
+                eprintln!("Skipping transition {name}: {err}");
+                return false;
+            }
+        };
+        // Unloads the current map, everything not part of it, like the day cycle and player
+        // lights, is kept
+        self.replace_map(light_engine, map);
+        self.map_path = map_path;
+
+        player.pos = target_pos;
+        // Teleported, so not drawn sliding over from where they were
+        player.prev_pos = player.pos;
+        player.vel = Vector2::zero();
+        self.in_transition = self
+            .map
+            .objects
+            .iter()
+            .any(|object| object.class == TRANSITION_CLASS && object.contains(player.pos));
+        true
+    }
+
+    // Opens the map a transition leads to, without unloading the current one, and finds where
+    // in it the player ends up. Returns the map, its path and the position.
+    fn open_transition_target(
+        &self,
+        transition: &MapObject,
+    ) -> Result<(WorldMap, String, Vector2), String> {
+        let target_map = transition
+            .get_property("target_map")
+            .ok_or("Transition is missing a target_map")?;
+        let target = transition
+            .get_property("target")
+            .ok_or("Transition is missing a target")?;
+        let target_path = Path::new(&self.map_path)
+            .with_file_name(format!("{target_map}.tmx"))
+            .to_string_lossy()
+            .to_string();
+        let map = WorldMap::try_stream_from_file(&target_path)
+            .map_err(|err| format!("{target_path}: {err}"))?;
+        let target_pos = map
+            .find_object(target)
+            .ok_or(format!("Map {target_path} has no object named {target}"))?
+            .get_center();
+        Ok((map, target_path, target_pos))
+    }
+
+    // Fires the enter, exit and stay events of the triggers the player is in
+    pub fn update_triggers(&mut self, player: &Player) {
+        self.triggers
+            .update(&self.map, &[(PLAYER_ENTITY, player.pos)]);
+    }
+
+    pub fn update_visibility(&mut self, player: &Player) {
+        self.visibility.update(&self.map, player.pos);
+    }
+
+    pub fn spawn_bullet(&mut self, player: &Player) {
         let mut rng = rand::thread_rng();
         let accuracy = PI as f32
             / if player.is_sprinting {
@@ -27,11 +227,11 @@ impl World {
             } else {
                 player.gun.get_accuarcy()
             };
-        let angle_to_mouse = (mouse_pos.y - player_screen_pos.y)
-            .atan2(mouse_pos.x - player_screen_pos.x)
+        let aim_angle = (player.aim_pos.y - player.pos.y).atan2(player.aim_pos.x - player.pos.x)
             + rng.gen_range(-accuracy..accuracy); // Add shake to shooting
-        let bullet_vel = Vector2::new(angle_to_mouse.cos(), angle_to_mouse.sin());
-        let bullet_speed = 1000.0;
+        let bullet_vel = Vector2::new(aim_angle.cos(), aim_angle.sin());
+        let caliber = player.gun.get_caliber();
+        let bullet_speed = caliber.muzzle_speed;
         let bullet_speed_accuracy = 10.0;
         let bullet = Bullet::new(
             player.pos + player.vel + bullet_vel * player.gun.get_render_barrel_length(),
@@ -40,6 +240,7 @@ impl World {
                     bullet_speed - bullet_speed / bullet_speed_accuracy
                         ..bullet_speed + bullet_speed / bullet_speed_accuracy,
                 )),
+            caliber,
         );
         if self
             .map
@@ -50,12 +251,130 @@ impl World {
         }
     }
 
-    pub fn update_bullets(&mut self, rl: &RaylibHandle) {
+    pub fn update_bullets(&mut self, dt: f32) {
         // Update bullets
         for bullet in self.bullets.iter_mut() {
-            bullet.update(rl, &self.map);
+            bullet.update(&self.map, dt);
+        }
+        // Bullets that stopped fall onto the ground they are over
+        for bullet in self.bullets.iter() {
+            if bullet.vel == Vector2::zero() {
+                self.impacts.push(Impact {
+                    pos: bullet.pos,
+                    effect: self.get_ground_material(bullet.pos).impact,
+                    time_left: IMPACT_LIFETIME,
+                });
+            }
         }
         // Filter bullets that are stopped or are in a wall
         self.bullets.retain(|bullet| bullet.vel != Vector2::zero());
     }
+
+    // Counts down noises and impacts, removing the ones that are over
+    pub fn update_effects(&mut self, dt: f32) {
+        for noise in self.noises.iter_mut() {
+            noise.time_left -= dt;
+        }
+        for impact in self.impacts.iter_mut() {
+            impact.time_left -= dt;
+        }
+        self.noises.retain(|noise| noise.time_left > 0.0);
+        self.impacts.retain(|impact| impact.time_left > 0.0);
+    }
+
+    // Makes a footstep noise from the ground at a position
+    pub fn make_footstep(&mut self, pos: Vector2, loudness: f32) {
+        let material = self.get_ground_material(pos);
+        self.noises.push(Noise {
+            pos,
+            radius: material.noise * loudness,
+            sound: material.footsteps.clone(),
+            time_left: NOISE_LIFETIME,
+        });
+    }
+
+    // Material of the ground at a position, the default one off the map or in unloaded chunks
+    pub fn get_ground_material(&self, pos: Vector2) -> &GroundMaterial {
+        let ground = if pos.x >= 0.0 && pos.y >= 0.0 {
+            self.map
+                .get_ground((pos.x / TILE_SIZE) as u32, (pos.y / TILE_SIZE) as u32)
+        } else {
+            None
+        };
+        match ground {
+            Some(ground) => self.ground_materials.get(ground.varient),
+            None => self.ground_materials.get_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{simulation::Simulation, world_map::write_test_tmx, WorldMap};
+    use raylib::prelude::*;
+
+    #[test]
+    fn broken_transitions_are_skipped() {
+        let pid = std::process::id();
+        // Transitions to a map that doesn't exist, to an object that doesn't exist, without a
+        // target and one that works, side by side
+        let objects = format!(
+            r#" <objectgroup id="3" name="Transitions">
+  <object id="1" name="no_map" type="transition" x="0" y="32" width="32" height="32">
+   <properties>
+    <property name="target" value="arrival"/>
+    <property name="target_map" value="{pid}_missing"/>
+   </properties>
+  </object>
+  <object id="2" name="no_object" type="transition" x="32" y="32" width="32" height="32">
+   <properties>
+    <property name="target" value="missing"/>
+    <property name="target_map" value="{pid}_transitions"/>
+   </properties>
+  </object>
+  <object id="3" name="no_target" type="transition" x="64" y="32" width="32" height="32">
+   <properties>
+    <property name="target_map" value="{pid}_transitions"/>
+   </properties>
+  </object>
+  <object id="4" name="working" type="transition" x="96" y="32" width="32" height="32">
+   <properties>
+    <property name="target" value="arrival"/>
+    <property name="target_map" value="{pid}_transitions"/>
+   </properties>
+  </object>
+  <object id="5" name="arrival" x="144" y="16">
+   <point/>
+  </object>
+ </objectgroup>
+"#
+        );
+        let floor = ["1,1,1,1,1", "1,1,1,1,1", "1,1,1,1,1"];
+        let walls = ["0,0,0,0,0", "0,0,0,0,0", "0,0,0,0,0"];
+        let path = write_test_tmx("transitions.tmx", &floor, &walls, &objects);
+        let mut simulation = Simulation::new(WorldMap::load_from_file(&path));
+
+        let enter = |simulation: &mut Simulation, pos: Vector2| {
+            // Stepping out first, transitions only happen when entering a zone
+            simulation.player.pos = Vector2::new(16.0, 80.0);
+            simulation
+                .world
+                .handle_map_transitions(&mut simulation.light_engine, &mut simulation.player);
+            simulation.player.pos = pos;
+            simulation
+                .world
+                .handle_map_transitions(&mut simulation.light_engine, &mut simulation.player)
+        };
+        let map_id = simulation.world.map.id();
+        for pos in [(16.0, 48.0), (48.0, 48.0), (80.0, 48.0)] {
+            let pos = Vector2::new(pos.0, pos.1);
+            assert!(!enter(&mut simulation, pos));
+            assert_eq!(simulation.world.map.id(), map_id);
+            assert_eq!(simulation.player.pos, pos);
+        }
+        assert!(enter(&mut simulation, Vector2::new(112.0, 48.0)));
+        assert_ne!(simulation.world.map.id(), map_id);
+        assert_eq!(simulation.player.pos, Vector2::new(144.0, 16.0));
+        std::fs::remove_file(path).unwrap();
+    }
 }
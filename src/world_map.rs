@@ -1,161 +1,1217 @@
-use crate::{Ground, GroundVarient, Player, TileRotation, Wall, WallVarient};
+use crate::{
+    auto_tile::{get_wall_shapes_keeping_sides, WallShape, WallStyle},
+    chunk_streamer::ChunkStreamer,
+    map_objects::{read_map_objects, write_map_objects, MapObject, ObjectKind, ObjectLayer},
+    tile::get_rect_points,
+    Circle, Collider, Ground, GroundVarient, Line, Player, TileRotation, Wall, WallVarient,
+    FLOOR_FIRST_GID, OBJECT_FIRST_GID, TILE_SIZE, WALL_FIRST_GID,
+};
 use raylib::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, Write},
+    ops::Range,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
-pub struct WorldMap {
+// Gap left between moving boxes and the walls they stop against
+const COLLISION_SKIN: f32 = 0.01;
+// Times a move can hit a wall and slide along it in one go
+const MAX_SLIDES: u32 = 4;
+
+// Used to give every loaded map a unique id
+static NEXT_MAP_ID: AtomicU32 = AtomicU32::new(0);
+
+// A rectangular block of tiles, in tile coordinates
+#[derive(Clone, Debug)]
+pub struct TileRange {
+    pub x: Range<u32>,
+    pub y: Range<u32>,
+}
+
+impl TileRange {
+    // Iterates over every tile position in the range, row by row
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u32)> {
+        let x_range = self.x.clone();
+        self.y
+            .clone()
+            .flat_map(move |y| x_range.clone().map(move |x| (x, y)))
+    }
+}
+
+// A single tile on the map with its position
+pub struct MapTile<'a> {
+    pub x: u32,
+    pub y: u32,
+    pub ground: &'a Ground,
+    pub wall: Option<&'a Wall>,
+}
+
+impl MapTile<'_> {
+    // Position of the tiles top left corner in the world
+    pub fn world_pos(&self) -> Vector2 {
+        Vector2::new(self.x as f32 * TILE_SIZE, self.y as f32 * TILE_SIZE)
+    }
+}
+
+// Where a ray hit a wall
+#[derive(Clone, Debug)]
+pub struct RayHit {
+    pub point: Vector2,
+    // Unit vector pointing out of the surface that was hit, towards where the ray came from
+    pub normal: Vector2,
+    // How far along the ray the hit is
+    pub distance: f32,
+    pub tile: (u32, u32),
+    pub material: WallStyle,
+}
+
+// Raw tile ids of the floor and wall layers
+type RawLayers = (Vec<Vec<u32>>, Vec<Vec<u32>>);
+
+// Width and height of a map chunk in tiles
+pub const CHUNK_SIZE: u32 = 16;
+// Chunks within this many chunks of the player get loaded
+const CHUNK_LOAD_RADIUS: u32 = 2;
+// Chunks further than this many chunks from the player get unloaded
+const CHUNK_UNLOAD_RADIUS: u32 = 3;
+// Tiles around a changed wall looked at when re-picking the walls next to it
+const WALL_RETILE_RADIUS: u32 = 8;
+
+// A square block of the map that is loaded and unloaded as a whole. Tiles are stored by their
+// position within the chunk, chunks on the right and bottom edge of the map may be smaller.
+pub struct MapChunk {
     pub ground: Vec<Vec<Ground>>,
     pub walls: Vec<Vec<Option<Wall>>>,
+    // Walls with tile ids the game doesn't know, kept so they aren't lost when saving
+    pub unknown_walls: HashMap<(u32, u32), u32>,
+}
+
+// The lines of a .tmx file around the tile data, kept from the file a map was loaded from so
+// that saving it gives back the same file
+#[derive(Clone)]
+pub struct TmxLayout {
+    // From the xml declaration to the start of the floor layer data
+    pub header: Vec<String>,
+    // From the end of the floor layer data to the start of the wall layer data
+    pub between_layers: Vec<String>,
+}
+
+impl TmxLayout {
+    // Layout for maps that weren't loaded from a file
+    pub fn new(width: u32, height: u32) -> TmxLayout {
+        TmxLayout {
+            header: vec![
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>".to_string(),
+                format!(
+                    "<map version=\"1.10\" tiledversion=\"1.10.2\" orientation=\"orthogonal\" \
+                    renderorder=\"right-down\" width=\"{width}\" height=\"{height}\" \
+                    tilewidth=\"{TILE_SIZE}\" tileheight=\"{TILE_SIZE}\" infinite=\"0\" \
+                    nextlayerid=\"3\" nextobjectid=\"1\">"
+                ),
+                format!(
+                    " <tileset firstgid=\"{FLOOR_FIRST_GID}\" source=\"../tiled/floor_tile_sheet.tsx\"/>"
+                ),
+                format!(
+                    " <tileset firstgid=\"{WALL_FIRST_GID}\" source=\"../tiled/wall_tile_sheet.tsx\"/>"
+                ),
+                format!(
+                    " <tileset firstgid=\"{OBJECT_FIRST_GID}\" source=\"../tiled/object_tile_sheet.tsx\"/>"
+                ),
+                format!(" <layer id=\"1\" name=\"Floor\" width=\"{width}\" height=\"{height}\">"),
+                "  <data encoding=\"csv\">".to_string(),
+            ],
+            between_layers: vec![
+                "</data>".to_string(),
+                " </layer>".to_string(),
+                format!(" <layer id=\"2\" name=\"Walls\" width=\"{width}\" height=\"{height}\">"),
+                "  <data encoding=\"csv\">".to_string(),
+            ],
+        }
+    }
+}
+
+pub struct WorldMap {
+    id: u32,
+    chunks: HashMap<(u32, u32), MapChunk>,
     pub width: u32,
     pub height: u32,
+    pub object_layers: Vec<ObjectLayer>,
+    pub objects: Vec<MapObject>,
+    // Colliders of the objects that have one, worked out once when the map is loaded
+    prop_colliders: Vec<Collider>,
+    pub tmx_layout: TmxLayout,
+    // File the map was loaded from
+    pub source_path: Option<String>,
+    changed_tiles: Vec<(u32, u32)>,
+    // Chunks with runtime changes are kept loaded so the changes aren't lost
+    modified_chunks: HashSet<(u32, u32)>,
+    streamer: Option<ChunkStreamer>,
 }
 
 impl WorldMap {
-    // Load a world map from .tmx file from Tiled
-    pub fn load_from_file(path: &str, map_width: u32, map_height: u32) -> WorldMap {
-        use std::io::BufRead;
+    // Load a whole world map from .tmx file from Tiled
+    pub fn load_from_file(path: &str) -> WorldMap {
+        Self::try_load_from_file(path).unwrap_or_else(|err| panic!("{err}"))
+    }
 
-        let mut ground = vec![];
-        let mut walls = vec![];
-        let map = std::fs::File::open(path).unwrap();
+    // Load a whole world map from .tmx file from Tiled, returning what is wrong with the file
+    // instead of panicking
+    pub fn try_load_from_file(path: &str) -> Result<WorldMap, String> {
+        let map = std::fs::File::open(path).map_err(|err| format!("Unable to open map: {err}"))?;
         let mut reader = std::io::BufReader::new(map);
-        // Skip first 7 lines of map data
-        for _ in 0..7 {
-            reader.read_line(&mut String::new()).unwrap();
-        }
+        let (map_width, map_height, header) = try_read_map_header(&mut reader)?;
+
         // Parsing background layer
-        for _ in 0..map_height {
-            let mut buffer = String::new();
-            reader.read_line(&mut buffer).unwrap();
-            let buffer = &buffer.replace("\r\n", "");
-            let floor_map_line = buffer
-                .split(',')
-                .filter(|s| s != &"")
-                .map(|s| {
-                    if let Ok(ground) = s.parse::<u32>() {
-                        if let Some(varient) = GroundVarient::from_raw_u32(ground) {
-                            Ground {
-                                varient, // Removing 64 bit and first byte
-                                rotation: TileRotation::from_raw_u32(ground),
-                            }
-                        } else {
-                            panic!("Unable to parse map, ground: {ground}");
-                        }
-                    } else {
-                        panic!("Unable to parse map");
-                    }
-                })
-                .collect::<Vec<Ground>>();
-            ground.push(floor_map_line);
+        let ground = read_layer(&mut reader, map_width, map_height)?
+            .into_iter()
+            .enumerate()
+            .map(|(y, line)| {
+                line.into_iter()
+                    .enumerate()
+                    .map(|(x, ground)| {
+                        try_parse_ground(ground).ok_or(format!(
+                            "Unable to parse map, ground {ground} at ({x}, {y})"
+                        ))
+                    })
+                    .collect::<Result<Vec<Ground>, String>>()
+            })
+            .collect::<Result<Vec<Vec<Ground>>, String>>()?;
+        // Keeping the 4 lines between the layers
+        let between_layers = read_lines(&mut reader, 4);
+        // Parsing wall layer
+        let raw_walls = read_layer(&mut reader, map_width, map_height)?;
+        let walls = raw_walls
+            .iter()
+            .enumerate()
+            .map(|(y, line)| {
+                line.iter()
+                    .enumerate()
+                    .map(|(x, wall)| {
+                        try_parse_wall(*wall)
+                            .map_err(|err| format!("Unable to parse map, {err} at ({x}, {y})"))
+                    })
+                    .collect::<Result<Vec<Option<Wall>>, String>>()
+            })
+            .collect::<Result<Vec<Vec<Option<Wall>>>, String>>()?;
+
+        let (object_layers, objects) = read_map_objects(reader);
+        let mut map = WorldMap::from_tiles(ground, walls, objects);
+        for (y, line) in raw_walls.iter().enumerate() {
+            for (x, wall) in line.iter().enumerate() {
+                if *wall != 0 && WallVarient::from_raw_u32(*wall).is_none() {
+                    map.chunks
+                        .get_mut(&(x as u32 / CHUNK_SIZE, y as u32 / CHUNK_SIZE))
+                        .unwrap()
+                        .unknown_walls
+                        .insert((x as u32 % CHUNK_SIZE, y as u32 % CHUNK_SIZE), *wall);
+                }
+            }
         }
-        // Skipping another 4 lines
-        for _ in 0..4 {
-            reader.read_line(&mut String::new()).unwrap();
+        map.object_layers = object_layers;
+        map.tmx_layout = TmxLayout {
+            header,
+            between_layers,
+        };
+        map.source_path = Some(path.to_string());
+        Ok(map)
+    }
+
+    // Creates a map from rows of tiles, every row has to be the same length. Object layers are
+    // made for the layers the objects are in.
+    pub fn from_tiles(
+        ground: Vec<Vec<Ground>>,
+        walls: Vec<Vec<Option<Wall>>>,
+        objects: Vec<MapObject>,
+    ) -> WorldMap {
+        let map_width = ground.first().map_or(0, |line| line.len()) as u32;
+        let map_height = ground.len() as u32;
+
+        // Split the map up into chunks
+        let mut chunks = HashMap::new();
+        for (y, (ground_line, wall_line)) in ground.into_iter().zip(walls).enumerate() {
+            let local_y = y % CHUNK_SIZE as usize;
+            for (x, (ground, wall)) in ground_line.into_iter().zip(wall_line).enumerate() {
+                let chunk = chunks
+                    .entry((x as u32 / CHUNK_SIZE, y as u32 / CHUNK_SIZE))
+                    .or_insert_with(|| MapChunk {
+                        ground: vec![],
+                        walls: vec![],
+                        unknown_walls: HashMap::new(),
+                    });
+                if chunk.ground.len() <= local_y {
+                    chunk.ground.push(vec![]);
+                    chunk.walls.push(vec![]);
+                }
+                chunk.ground[local_y].push(ground);
+                chunk.walls[local_y].push(wall);
+            }
         }
-        // Parsing wall layer
-        for _ in 0..map_height {
-            let mut buffer = String::new();
-            reader.read_line(&mut buffer).unwrap();
-            let buffer = &buffer.replace("\r\n", "");
-            let wall_map_line = buffer
-                .split(',')
-                .filter(|s| s != &"")
-                .map(|s| {
-                    if let Ok(wall) = s.parse::<u32>() {
-                        if wall != 0 {
-                            WallVarient::from_raw_u32(wall).map(|varient| Wall {
-                                varient,
-                                rotation: TileRotation::from_raw_u32(wall),
-                            })
-                        } else {
-                            None
-                        }
-                    } else {
-                        panic!("Unable to parse map");
-                    }
-                })
-                .collect::<Vec<Option<Wall>>>();
-            walls.push(wall_map_line);
+
+        let mut object_layers: Vec<ObjectLayer> = vec![];
+        for object in objects.iter() {
+            if !object_layers.iter().any(|layer| layer.name == object.layer) {
+                object_layers.push(ObjectLayer {
+                    // The floor and wall layers come first
+                    id: object_layers.len() as u32 + 3,
+                    name: object.layer.clone(),
+                });
+            }
         }
-        assert!(ground.len() == map_height as usize);
-        assert!(walls.len() == map_height as usize);
+
         WorldMap {
-            ground,
-            walls,
+            id: NEXT_MAP_ID.fetch_add(1, Ordering::Relaxed),
+            chunks,
             width: map_width,
             height: map_height,
+            object_layers,
+            prop_colliders: get_prop_colliders(&objects),
+            objects,
+            tmx_layout: TmxLayout::new(map_width, map_height),
+            source_path: None,
+            changed_tiles: vec![],
+            modified_chunks: HashSet::new(),
+            streamer: None,
         }
     }
 
-    pub fn collides_with_wall(&self, collider: &crate::Collider) -> Option<Rectangle> {
-        // Iterate over every wall, and every collider rect in each wall collider
-        for (y, wall_line) in self.walls.iter().enumerate() {
-            for (x, wall) in wall_line.iter().enumerate() {
-                if let Some(wall) = wall {
-                    let wall_collider = wall
-                        .get_collider()
-                        .with_pos(Vector2::new(x as f32 * 32.0, y as f32 * 32.0));
-                    if let Some(collision) = wall_collider.collides(collider) {
-                        return Some(collision);
-                    }
+    // Opens a .tmx file from Tiled without loading any of it yet. Chunks are loaded on a
+    // background thread as the player gets close to them, see update_streaming
+    pub fn stream_from_file(path: &str) -> WorldMap {
+        Self::try_stream_from_file(path).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    // Opens a .tmx file to stream, returning what is wrong with its header instead of panicking.
+    // Problems in the tiles only show up as their chunks are loaded.
+    pub fn try_stream_from_file(path: &str) -> Result<WorldMap, String> {
+        let map = std::fs::File::open(path).map_err(|err| format!("Unable to open map: {err}"))?;
+        let mut reader = std::io::BufReader::new(map);
+        let (map_width, map_height, header) = try_read_map_header(&mut reader)?;
+        skip_lines(&mut reader, map_height);
+        let between_layers = read_lines(&mut reader, 4);
+        skip_lines(&mut reader, map_height);
+        // Objects are small enough to always be kept loaded
+        let (object_layers, objects) = read_map_objects(reader);
+        Ok(WorldMap {
+            id: NEXT_MAP_ID.fetch_add(1, Ordering::Relaxed),
+            chunks: HashMap::new(),
+            width: map_width,
+            height: map_height,
+            object_layers,
+            prop_colliders: get_prop_colliders(&objects),
+            objects,
+            tmx_layout: TmxLayout {
+                header,
+                between_layers,
+            },
+            source_path: Some(path.to_string()),
+            changed_tiles: vec![],
+            modified_chunks: HashSet::new(),
+            streamer: Some(ChunkStreamer::new(path, map_width, map_height)),
+        })
+    }
+
+    // Loads chunks around a world position and unloads the ones far away from it. The chunk
+    // under the position is always loaded by the time this returns so that collisions
+    // around it are never skipped. Chunks with tiles that can't be parsed stay unloaded and
+    // are returned as an error.
+    pub fn update_streaming(&mut self, world_pos: Vector2) -> Result<(), String> {
+        let Some(streamer) = self.streamer.as_mut() else {
+            return Ok(());
+        };
+        let chunks_width = self.width.div_ceil(CHUNK_SIZE);
+        let chunks_height = self.height.div_ceil(CHUNK_SIZE);
+        let to_chunk = |pos: f32, chunks: u32| {
+            ((pos / (TILE_SIZE * CHUNK_SIZE as f32)).max(0.0) as u32).min(chunks - 1)
+        };
+        let focus = (
+            to_chunk(world_pos.x, chunks_width),
+            to_chunk(world_pos.y, chunks_height),
+        );
+
+        // Request chunks close by
+        for chunk_y in focus.1.saturating_sub(CHUNK_LOAD_RADIUS)
+            ..(focus.1 + CHUNK_LOAD_RADIUS + 1).min(chunks_height)
+        {
+            for chunk_x in focus.0.saturating_sub(CHUNK_LOAD_RADIUS)
+                ..(focus.0 + CHUNK_LOAD_RADIUS + 1).min(chunks_width)
+            {
+                if !self.chunks.contains_key(&(chunk_x, chunk_y)) {
+                    streamer.request((chunk_x, chunk_y));
                 }
             }
         }
-        None
+
+        // Add chunks that have finished loading
+        let mut error = None;
+        for (chunk_pos, chunk) in streamer.take_loaded() {
+            match chunk {
+                Ok(chunk) => {
+                    self.chunks.insert(chunk_pos, chunk);
+                }
+                Err(err) => error = Some(err),
+            }
+        }
+        while error.is_none() && !self.chunks.contains_key(&focus) {
+            match streamer.wait_for_chunk() {
+                (chunk_pos, Ok(chunk)) => {
+                    self.chunks.insert(chunk_pos, chunk);
+                }
+                (_, Err(err)) => error = Some(err),
+            }
+        }
+
+        // Unload far away chunks
+        self.chunks.retain(|chunk_pos, _| {
+            chunk_pos.0.abs_diff(focus.0) <= CHUNK_UNLOAD_RADIUS
+                && chunk_pos.1.abs_diff(focus.1) <= CHUNK_UNLOAD_RADIUS
+                || self.modified_chunks.contains(chunk_pos)
+        });
+        match error {
+            Some(err) => Err(format!(
+                "Unable to parse map {}, {err}",
+                self.source_path.as_deref().unwrap_or_default()
+            )),
+            None => Ok(()),
+        }
     }
 
-    // Prevents player from clipping through colliders
-    pub fn handle_player_collisions(&self, player: &mut Player) {
-        let player_collider = player.get_world_collider();
-
-        // Iterate over every wall, and every collider rect in each wall collider
-        for (y, wall_line) in self.walls.iter().enumerate() {
-            for (x, wall) in wall_line.iter().enumerate() {
-                if let Some(wall) = wall {
-                    for collider_rect in wall
-                        .get_collider()
-                        .with_pos(Vector2::new(x as f32 * 32.0, y as f32 * 32.0))
-                        .rects
+    // Writes the map as a .tmx file that can be opened in Tiled. Maps loaded from a file keep
+    // the layout of that file, so saving an unchanged map gives back the same file.
+    pub fn save_to_tmx(&self, path: &str) -> std::io::Result<()> {
+        // Chunks that aren't loaded can't have changed, so their tiles are copied from the file
+        // the map was streamed from
+        let chunk_count = self.width.div_ceil(CHUNK_SIZE) * self.height.div_ceil(CHUNK_SIZE);
+        let source_layers = if self.chunks.len() < chunk_count as usize {
+            let source_path = self.source_path.as_ref().ok_or(std::io::Error::other(
+                "Can't save a map that isn't fully loaded",
+            ))?;
+            Some(
+                read_raw_layers(source_path, self.width, self.height)
+                    .map_err(std::io::Error::other)?,
+            )
+        } else {
+            None
+        };
+        let is_loaded = |x: u32, y: u32| self.is_chunk_loaded((x / CHUNK_SIZE, y / CHUNK_SIZE));
+        let ground_lines = self.get_csv_lines(|x, y| match &source_layers {
+            Some((ground, _)) if !is_loaded(x, y) => ground[y as usize][x as usize],
+            _ => self.get_ground(x, y).unwrap().to_raw_u32(),
+        });
+        let wall_lines = self.get_csv_lines(|x, y| match &source_layers {
+            Some((_, walls)) if !is_loaded(x, y) => walls[y as usize][x as usize],
+            _ => self.get_raw_wall(x, y),
+        });
+
+        // Objects can be in layers that aren't saved yet
+        let mut object_layers = self.object_layers.clone();
+        for object in self.objects.iter() {
+            if !object_layers.iter().any(|layer| layer.name == object.layer) {
+                object_layers.push(ObjectLayer {
+                    id: object_layers
                         .iter()
-                    {
-                        // Checks if player will collide with wall in y axis
-                        if let Some(_collision_rect) = collider_rect.get_collision_rec(
-                            &player_collider
-                                .with_pos(Vector2::new(0.0, player.vel.y))
-                                .rects[0],
-                        ) {
-                            // Move player to edge of wall and set vel y to 0
-                            player.vel.y = 0.0;
-                            if player.pos.y < collider_rect.y + collider_rect.height / 2.0 {
-                                player.pos.y =
-                                    collider_rect.y - player_collider.rects[0].height / 2.0;
-                            } else {
-                                player.pos.y = collider_rect.y
-                                    + collider_rect.height
-                                    + player_collider.rects[0].height / 2.0;
-                            }
-                        }
-
-                        // Checks if player will collide with wall in x axis
-                        if let Some(_collision_rect) = collider_rect.get_collision_rec(
-                            &player_collider
-                                .with_pos(Vector2::new(player.vel.x, 0.0))
-                                .rects[0],
-                        ) {
-                            // Move player to edge of wall and set vel x to 0
-                            player.vel.x = 0.0;
-                            if player.pos.x < collider_rect.x + collider_rect.width / 2.0 {
-                                player.pos.x =
-                                    collider_rect.x - player_collider.rects[0].width / 2.0;
-                            } else {
-                                player.pos.x = collider_rect.x
-                                    + collider_rect.width
-                                    + player_collider.rects[0].width / 2.0;
-                            }
-                        }
-                    }
+                        .map(|layer| layer.id)
+                        .max()
+                        .unwrap_or(2)
+                        + 1,
+                    name: object.layer.clone(),
+                });
+            }
+        }
+        // Tiled needs the ids it gives new layers and objects to be unused
+        let mut header = self.tmx_layout.header.clone();
+        let next_layer_id = object_layers
+            .iter()
+            .map(|layer| layer.id)
+            .max()
+            .unwrap_or(2)
+            + 1;
+        let next_object_id = self
+            .objects
+            .iter()
+            .map(|object| object.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        for (attribute, next_id) in [
+            ("nextlayerid", next_layer_id),
+            ("nextobjectid", next_object_id),
+        ] {
+            let current_id = get_xml_attribute(&header[1], attribute)
+                .and_then(|id| id.parse::<u32>().ok())
+                .unwrap_or(0);
+            header[1] =
+                set_xml_attribute(&header[1], attribute, &current_id.max(next_id).to_string());
+        }
+
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        for line in header
+            .iter()
+            .chain(ground_lines.iter())
+            .chain(self.tmx_layout.between_layers.iter())
+            .chain(wall_lines.iter())
+        {
+            writeln!(writer, "{line}")?;
+        }
+        writeln!(writer, "</data>")?;
+        writeln!(writer, " </layer>")?;
+        write_map_objects(&mut writer, &object_layers, &self.objects)?;
+        writeln!(writer, "</map>")?;
+        writer.flush()
+    }
+
+    // Saves the map over the file it was loaded from
+    pub fn save_to_source(&mut self) -> std::io::Result<()> {
+        let path = self
+            .source_path
+            .clone()
+            .expect("Map wasn't loaded from a file");
+        self.save_to_tmx(&path)?;
+        // The file changed under the streaming thread, so it has to be read again
+        if self.streamer.is_some() {
+            self.streamer = Some(ChunkStreamer::new(&path, self.width, self.height));
+        }
+        Ok(())
+    }
+
+    // Lines of csv layer data, with a tile id for every tile on the map
+    fn get_csv_lines(&self, get_tile: impl Fn(u32, u32) -> u32) -> Vec<String> {
+        (0..self.height)
+            .map(|y| {
+                let line = (0..self.width)
+                    .map(|x| get_tile(x, y).to_string())
+                    .collect::<Vec<String>>()
+                    .join(",");
+                // Every line but the last one ends with a comma
+                if y + 1 < self.height {
+                    line + ","
+                } else {
+                    line
+                }
+            })
+            .collect()
+    }
+
+    // Tile id of the wall on a tile as saved in the map file, 0 if there is no wall
+    pub fn get_raw_wall(&self, x: u32, y: u32) -> u32 {
+        if let Some(wall) = self.get_wall(x, y) {
+            return wall.to_raw_u32();
+        }
+        self.chunks
+            .get(&(x / CHUNK_SIZE, y / CHUNK_SIZE))
+            .and_then(|chunk| {
+                chunk
+                    .unknown_walls
+                    .get(&(x % CHUNK_SIZE, y % CHUNK_SIZE))
+                    .copied()
+            })
+            .unwrap_or(0)
+    }
+
+    // Unique id of this map, changes whenever a map is loaded
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    // Returns the first object with a name
+    pub fn find_object(&self, name: &str) -> Option<&MapObject> {
+        self.objects.iter().find(|object| object.name == name)
+    }
+
+    // Where the player starts on the map, the first player spawn point
+    pub fn get_player_spawn(&self) -> Option<Vector2> {
+        self.objects
+            .iter()
+            .filter(|object| object.get_kind() == Ok(ObjectKind::PlayerSpawn))
+            .min_by_key(|object| object.id)
+            .map(|object| object.get_center())
+    }
+
+    // Tiles in a chunk, chunks at the edges of the map can be cut off
+    pub fn get_chunk_tile_range(&self, (chunk_x, chunk_y): (u32, u32)) -> TileRange {
+        TileRange {
+            x: chunk_x * CHUNK_SIZE..((chunk_x + 1) * CHUNK_SIZE).min(self.width),
+            y: chunk_y * CHUNK_SIZE..((chunk_y + 1) * CHUNK_SIZE).min(self.height),
+        }
+    }
+
+    // Colliders of every object that has one
+    pub fn get_prop_colliders(&self) -> &[Collider] {
+        &self.prop_colliders
+    }
+
+    // Iterates over the position of every chunk currently loaded
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn is_chunk_loaded(&self, chunk_pos: (u32, u32)) -> bool {
+        self.chunks.contains_key(&chunk_pos)
+    }
+
+    // Returns the ground of a tile, None if it is off the map or not loaded
+    pub fn get_ground(&self, x: u32, y: u32) -> Option<&Ground> {
+        let chunk = self.chunks.get(&(x / CHUNK_SIZE, y / CHUNK_SIZE))?;
+        chunk
+            .ground
+            .get((y % CHUNK_SIZE) as usize)?
+            .get((x % CHUNK_SIZE) as usize)
+    }
+
+    // Returns the wall of a tile, None if there is no wall or it is not loaded
+    pub fn get_wall(&self, x: u32, y: u32) -> Option<&Wall> {
+        let chunk = self.chunks.get(&(x / CHUNK_SIZE, y / CHUNK_SIZE))?;
+        chunk
+            .walls
+            .get((y % CHUNK_SIZE) as usize)?
+            .get((x % CHUNK_SIZE) as usize)?
+            .as_ref()
+    }
+
+    // Replaces the ground of a tile. Does nothing if the tile isn't loaded
+    pub fn set_ground(&mut self, x: u32, y: u32, ground: Ground) {
+        let chunk_pos = (x / CHUNK_SIZE, y / CHUNK_SIZE);
+        if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+            chunk.ground[(y % CHUNK_SIZE) as usize][(x % CHUNK_SIZE) as usize] = ground;
+            self.changed_tiles.push((x, y));
+            self.modified_chunks.insert(chunk_pos);
+        }
+    }
+
+    // Replaces or removes the wall of a tile. Does nothing if the tile isn't loaded
+    pub fn set_wall(&mut self, x: u32, y: u32, wall: Option<Wall>) {
+        let chunk_pos = (x / CHUNK_SIZE, y / CHUNK_SIZE);
+        if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+            chunk.walls[(y % CHUNK_SIZE) as usize][(x % CHUNK_SIZE) as usize] = wall;
+            chunk
+                .unknown_walls
+                .remove(&(x % CHUNK_SIZE, y % CHUNK_SIZE));
+            self.changed_tiles.push((x, y));
+            self.modified_chunks.insert(chunk_pos);
+        }
+    }
+
+    // Adds a wall and re-picks it and the walls next to it so they join up
+    pub fn build_wall(&mut self, x: u32, y: u32, style: WallStyle) {
+        // Pillars don't have a side, so the new wall doesn't change the side of the walls it
+        // joins up with
+        self.set_wall(x, y, Some(WallShape::Pillar.to_wall(style)));
+        self.retile_walls_around(x, y, style);
+    }
+
+    // Removes a wall and re-picks the walls next to it so they join up again
+    pub fn destroy_wall(&mut self, x: u32, y: u32) {
+        if let Some(wall) = self.get_wall(x, y) {
+            let style = WallStyle::from_varient(wall.varient);
+            self.set_wall(x, y, None);
+            self.retile_walls_around(x, y, style);
+        }
+    }
+
+    // Re-picks the walls next to a tile from the walls around it, keeping their style and the
+    // side they are on. Pillars have no style of their own and get the one given.
+    pub fn retile_walls_around(&mut self, x: u32, y: u32, style: WallStyle) {
+        let range = TileRange {
+            x: x.saturating_sub(WALL_RETILE_RADIUS)..(x + WALL_RETILE_RADIUS + 1).min(self.width),
+            y: y.saturating_sub(WALL_RETILE_RADIUS)..(y + WALL_RETILE_RADIUS + 1).min(self.height),
+        };
+        let existing = range
+            .y
+            .clone()
+            .map(|y| {
+                range
+                    .x
+                    .clone()
+                    .map(|x| self.get_wall(x, y).map(WallShape::from_wall))
+                    .collect()
+            })
+            .collect::<Vec<Vec<Option<WallShape>>>>();
+        let mask = existing
+            .iter()
+            .map(|line| line.iter().map(|shape| shape.is_some()).collect())
+            .collect::<Vec<Vec<bool>>>();
+        let shapes = get_wall_shapes_keeping_sides(&mask, &existing);
+
+        let next_to = TileRange {
+            x: x.saturating_sub(1)..(x + 2).min(self.width),
+            y: y.saturating_sub(1)..(y + 2).min(self.height),
+        };
+        for (tile_x, tile_y) in next_to.iter() {
+            let (Some(wall), Some(shape)) = (
+                self.get_wall(tile_x, tile_y),
+                shapes[(tile_y - range.y.start) as usize][(tile_x - range.x.start) as usize],
+            ) else {
+                continue;
+            };
+            let wall_style = match wall.varient {
+                WallVarient::WhitePillar => style,
+                varient => WallStyle::from_varient(varient),
+            };
+            let new_wall = shape.to_wall(wall_style);
+            if new_wall.to_raw_u32() != wall.to_raw_u32() {
+                self.set_wall(tile_x, tile_y, Some(new_wall));
+            }
+        }
+    }
+
+    // Returns every tile changed since the last call, used to keep cached tile layers updated
+    pub fn take_changed_tiles(&mut self) -> Vec<(u32, u32)> {
+        std::mem::take(&mut self.changed_tiles)
+    }
+
+    // Returns every tile on the map
+    pub fn get_full_tile_range(&self) -> TileRange {
+        TileRange {
+            x: 0..self.width,
+            y: 0..self.height,
+        }
+    }
+
+    // Iterates over every loaded tile in the range, row by row. All per tile loops over the map
+    // go through here so that maps of any width and height are handled the same way
+    pub fn iter_tiles(&self, range: &TileRange) -> impl Iterator<Item = MapTile<'_>> {
+        range.iter().filter_map(move |(x, y)| {
+            Some(MapTile {
+                x,
+                y,
+                ground: self.get_ground(x, y)?,
+                wall: self.get_wall(x, y),
+            })
+        })
+    }
+
+    // Iterates over every wall in the range along with the world position of its tile
+    pub fn iter_walls(&self, range: &TileRange) -> impl Iterator<Item = (Vector2, &Wall)> {
+        self.iter_tiles(range)
+            .filter_map(|tile| tile.wall.map(|wall| (tile.world_pos(), wall)))
+    }
+
+    // Returns the tiles overlapping a world space rect, padded by a margin of tiles and
+    // clamped to the map bounds
+    pub fn get_tile_range(&self, rect: &Rectangle, margin: u32) -> TileRange {
+        let to_tile = |pos: f32| (pos / TILE_SIZE).floor().max(0.0) as u32;
+        TileRange {
+            x: to_tile(rect.x).saturating_sub(margin).min(self.width)
+                ..(to_tile(rect.x + rect.width) + 1 + margin).min(self.width),
+            y: to_tile(rect.y).saturating_sub(margin).min(self.height)
+                ..(to_tile(rect.y + rect.height) + 1 + margin).min(self.height),
+        }
+    }
+
+    // How far and which way a collider has to move to get out of the walls around it, the
+    // deepest overlap if it is in more than one
+    pub fn collides_with_wall(&self, collider: &Collider) -> Option<Vector2> {
+        // Iterate over every wall near the collider, and every collider rect in each wall collider
+        let nearby_tiles = self.get_tile_range(&collider.get_bounds(), 0);
+        self.iter_walls(&nearby_tiles)
+            .filter_map(|(tile_pos, wall)| {
+                collider.get_penetration(&wall.get_collider().with_pos(tile_pos))
+            })
+            .max_by(|a, b| a.length().total_cmp(&b.length()))
+    }
+
+    // Finds the first wall collider a line from one point to another hits. Tiles are walked in
+    // the order the line goes through them, so the search stops at the first tile with a hit.
+    pub fn raycast(&self, from: Vector2, to: Vector2) -> Option<RayHit> {
+        let ray = Line {
+            start: from,
+            end: to,
+        };
+        let length = from.distance_to(to);
+        // Nothing to hit, even when the point is on the edge of a wall
+        if length == 0.0 {
+            return None;
+        }
+        let dir = (to - from).normalized();
+
+        // Distance along the ray to the next tile edge on each axis and between tile edges
+        let mut tile = (
+            (from.x / TILE_SIZE).floor() as i64,
+            (from.y / TILE_SIZE).floor() as i64,
+        );
+        let step = (dir.x.signum() as i64, dir.y.signum() as i64);
+        let get_first_edge = |pos: f32, tile: i64, dir: f32| {
+            let edge = if dir > 0.0 { tile + 1 } else { tile } as f32 * TILE_SIZE;
+            if dir == 0.0 {
+                f32::INFINITY
+            } else {
+                (edge - pos) / dir
+            }
+        };
+        let mut next_edge = (
+            get_first_edge(from.x, tile.0, dir.x),
+            get_first_edge(from.y, tile.1, dir.y),
+        );
+        let edge_step = (TILE_SIZE / dir.x.abs(), TILE_SIZE / dir.y.abs());
+
+        loop {
+            if let Some(hit) = self.raycast_tile(&ray, tile) {
+                return Some(hit);
+            }
+            if next_edge.0.min(next_edge.1) > length {
+                return None;
+            }
+            if next_edge.0 < next_edge.1 {
+                tile.0 += step.0;
+                next_edge.0 += edge_step.0;
+            } else {
+                tile.1 += step.1;
+                next_edge.1 += edge_step.1;
+            }
+        }
+    }
+
+    // Closest hit of a ray with the collider of the wall on a tile
+    fn raycast_tile(&self, ray: &Line, (x, y): (i64, i64)) -> Option<RayHit> {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
+        }
+        let (x, y) = (x as u32, y as u32);
+        let wall = self.get_wall(x, y)?;
+        let collider = wall
+            .get_collider()
+            .with_pos(Vector2::new(x as f32, y as f32) * TILE_SIZE);
+        let mut closest: Option<RayHit> = None;
+        for rect in collider.rects.iter() {
+            // Rays starting inside a wall hit it straight away, coming back the way they went
+            if rect.check_collision_point_rec(ray.start) {
+                return Some(RayHit {
+                    point: ray.start,
+                    normal: (ray.start - ray.end).normalized(),
+                    distance: 0.0,
+                    tile: (x, y),
+                    material: WallStyle::from_varient(wall.varient),
+                });
+            }
+            for line in Line::from_rect(rect) {
+                let Some(point) = line.intersection(ray) else {
+                    continue;
+                };
+                let distance = ray.start.distance_to(point);
+                let normal = line.get_normal(ray.start);
+                // At corners the side facing the ray the most is the one hit, rather than one
+                // the ray only slides along
+                let dir = ray.end - ray.start;
+                if closest.as_ref().is_some_and(|hit| {
+                    hit.distance < distance
+                        || (hit.distance == distance && hit.normal.dot(dir) <= normal.dot(dir))
+                }) {
+                    continue;
                 }
+                closest = Some(RayHit {
+                    point,
+                    normal,
+                    distance,
+                    tile: (x, y),
+                    material: WallStyle::from_varient(wall.varient),
+                });
             }
         }
+        closest
     }
+
+    // Whether nothing blocks a straight line between two points
+    pub fn has_line_of_sight(&self, from: Vector2, to: Vector2) -> bool {
+        self.raycast(from, to).is_none()
+    }
+
+    // Moves the player by their velocity, sliding along the walls they run into. Velocity
+    // becomes the move that was actually made, so apply_velocity puts them where they ended up.
+    pub fn handle_player_collisions(&self, player: &mut Player) {
+        // Walls can end up on top of the player, from the editor or a map reload
+        player.pos += self.get_push_out(&player.get_world_collider());
+        let collider = player.get_world_collider();
+        let mut vel = self.move_circle_and_slide(collider.circles[0], player.vel);
+
+        // Props are pushed out of rather than swept against, they are too big to walk through
+        // in one frame. Being pushed out is swept though, so a prop can't shove the player into
+        // a wall.
+        for prop_collider in self.prop_colliders.iter() {
+            let moved_collider = collider.with_pos(vel);
+            if let Some(penetration) = moved_collider.get_penetration(prop_collider) {
+                vel += self.move_circle_and_slide(moved_collider.circles[0], penetration);
+            }
+        }
+        player.vel = vel;
+    }
+
+    // How far a box can go towards a movement without going into a wall. Whenever it hits one
+    // the rest of the movement slides along the wall, so it is swept the whole way and can't
+    // pass through thin walls however far it moves.
+    pub fn move_and_slide(&self, rect: Rectangle, movement: Vector2) -> Vector2 {
+        self.slide(movement, |moved, remaining| {
+            let moved_rect =
+                Rectangle::new(rect.x + moved.x, rect.y + moved.y, rect.width, rect.height);
+            self.sweep_box(moved_rect, remaining)
+        })
+    }
+
+    // Same as move_and_slide for a circle, which goes smoothly around corners instead of
+    // catching on them
+    pub fn move_circle_and_slide(&self, circle: Circle, movement: Vector2) -> Vector2 {
+        self.slide(movement, |moved, remaining| {
+            let moved_circle = Circle {
+                x: circle.x + moved.x,
+                y: circle.y + moved.y,
+                radius: circle.radius,
+            };
+            self.sweep_circle(moved_circle, remaining)
+        })
+    }
+
+    // Moves as far as it can with a sweep, which is given how far it has moved so far and the
+    // movement left, then slides the rest of the way along whatever it hit
+    fn slide(
+        &self,
+        movement: Vector2,
+        sweep: impl Fn(Vector2, Vector2) -> Option<(f32, Vector2)>,
+    ) -> Vector2 {
+        let mut moved = Vector2::zero();
+        let mut remaining = movement;
+        for _ in 0..MAX_SLIDES {
+            if remaining.length_sqr() == 0.0 {
+                break;
+            }
+            let Some((time, normal)) = sweep(moved, remaining) else {
+                moved += remaining;
+                break;
+            };
+            // Stopped a little off the wall, so sliding along it later doesn't touch it and
+            // catch on the seams between wall rects
+            moved += remaining * time + normal * COLLISION_SKIN;
+            remaining *= 1.0 - time;
+            remaining -= normal * remaining.dot(normal);
+        }
+        moved
+    }
+
+    // When a box moving through the walls first hits one, as the part of the movement done
+    // before the hit and the normal of the side it hit
+    pub fn sweep_box(&self, rect: Rectangle, movement: Vector2) -> Option<(f32, Vector2)> {
+        let half_size = Vector2::new(rect.width / 2.0, rect.height / 2.0);
+        let center = Vector2::new(rect.x, rect.y) + half_size;
+        let swept_bounds = Rectangle::new(
+            rect.x + movement.x.min(0.0),
+            rect.y + movement.y.min(0.0),
+            rect.width + movement.x.abs(),
+            rect.height + movement.y.abs(),
+        );
+        let mut first_hit: Option<(f32, Vector2)> = None;
+        for (tile_pos, wall) in self.iter_walls(&self.get_tile_range(&swept_bounds, 0)) {
+            for wall_rect in wall.get_collider().with_pos(tile_pos).rects {
+                // Growing the wall by half the box turns the box into a point moving along a line
+                let min = Vector2::new(wall_rect.x, wall_rect.y) - half_size;
+                let max = Vector2::new(
+                    wall_rect.x + wall_rect.width,
+                    wall_rect.y + wall_rect.height,
+                ) + half_size;
+                keep_first_hit(
+                    &mut first_hit,
+                    sweep_point(center, movement, min, max),
+                    movement,
+                );
+            }
+        }
+        first_hit
+    }
+
+    // When a circle moving through the walls first hits one, as the part of the movement done
+    // before the hit and the normal of the wall where it hit
+    pub fn sweep_circle(&self, circle: Circle, movement: Vector2) -> Option<(f32, Vector2)> {
+        let center = Vector2::new(circle.x, circle.y);
+        let radius = circle.radius;
+        let swept_bounds = Rectangle::new(
+            center.x - radius + movement.x.min(0.0),
+            center.y - radius + movement.y.min(0.0),
+            radius * 2.0 + movement.x.abs(),
+            radius * 2.0 + movement.y.abs(),
+        );
+        let mut first_hit: Option<(f32, Vector2)> = None;
+        for (tile_pos, wall) in self.iter_walls(&self.get_tile_range(&swept_bounds, 0)) {
+            for wall_rect in wall.get_collider().with_pos(tile_pos).rects {
+                // Growing the wall by the circle gives a rect with round corners, made of a rect
+                // grown sideways, one grown up and down and a circle at each corner
+                let (min, max) = (
+                    Vector2::new(wall_rect.x, wall_rect.y),
+                    Vector2::new(
+                        wall_rect.x + wall_rect.width,
+                        wall_rect.y + wall_rect.height,
+                    ),
+                );
+                for (grow_x, grow_y) in [(radius, 0.0), (0.0, radius)] {
+                    let grow = Vector2::new(grow_x, grow_y);
+                    let hit = sweep_point(center, movement, min - grow, max + grow);
+                    keep_first_hit(&mut first_hit, hit, movement);
+                }
+                for corner in get_rect_points(&wall_rect) {
+                    let hit = sweep_point_circle(center, movement, corner, radius);
+                    keep_first_hit(&mut first_hit, hit, movement);
+                }
+            }
+        }
+        first_hit
+    }
+
+    // Shortest move that gets a collider out of every wall it overlaps
+    fn get_push_out(&self, collider: &Collider) -> Vector2 {
+        let mut push_out = Vector2::zero();
+        for (tile_pos, wall) in self.iter_walls(&self.get_tile_range(&collider.get_bounds(), 0)) {
+            let wall_collider = wall.get_collider().with_pos(tile_pos);
+            if let Some(penetration) = collider.with_pos(push_out).get_penetration(&wall_collider) {
+                push_out += penetration + penetration.normalized() * COLLISION_SKIN;
+            }
+        }
+        push_out
+    }
+}
+
+// Objects with a collider property that can't be parsed are left out, the map validator reports
+// them
+fn get_prop_colliders(objects: &[MapObject]) -> Vec<Collider> {
+    objects
+        .iter()
+        .filter_map(|object| object.get_collider().ok().flatten())
+        .collect()
+}
+
+// Keeps whichever of two sweep hits comes first. When two walls are hit at once, the one moved
+// into more is the one that stops it.
+fn keep_first_hit(
+    first_hit: &mut Option<(f32, Vector2)>,
+    hit: Option<(f32, Vector2)>,
+    movement: Vector2,
+) {
+    let Some(hit) = hit else {
+        return;
+    };
+    if first_hit.is_none_or(|(time, normal)| {
+        hit.0 < time || (hit.0 == time && hit.1.dot(movement) < normal.dot(movement))
+    }) {
+        *first_hit = Some(hit);
+    }
+}
+
+// When a point moving along a line goes into a circle, as the part of the line before it does
+// and the normal of the circle there. Points already inside aren't stopped, like sweep_point.
+fn sweep_point_circle(
+    start: Vector2,
+    movement: Vector2,
+    center: Vector2,
+    radius: f32,
+) -> Option<(f32, Vector2)> {
+    let offset = start - center;
+    let (a, b, c) = (
+        movement.length_sqr(),
+        offset.dot(movement),
+        offset.length_sqr() - radius * radius,
+    );
+    // Inside already, or moving away from it
+    if c <= 0.0 || b >= 0.0 || a == 0.0 {
+        return None;
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let time = (-b - discriminant.sqrt()) / a;
+    (time <= 1.0).then(|| (time, (offset + movement * time).normalized()))
+}
+
+// When a point moving along a line goes into a box, as the part of the line before it does and
+// the normal of the side it goes in through. Points already inside aren't stopped, so anything
+// that ends up in a wall can still get out of it.
+fn sweep_point(
+    start: Vector2,
+    movement: Vector2,
+    min: Vector2,
+    max: Vector2,
+) -> Option<(f32, Vector2)> {
+    let mut entry = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+    let mut normal = Vector2::zero();
+    for (start, movement, min, max, axis) in [
+        (start.x, movement.x, min.x, max.x, Vector2::new(1.0, 0.0)),
+        (start.y, movement.y, min.y, max.y, Vector2::new(0.0, 1.0)),
+    ] {
+        if movement == 0.0 {
+            // Moving alongside the box without ever being in line with it
+            if start <= min || start >= max {
+                return None;
+            }
+            continue;
+        }
+        let (near, far, side) = if movement > 0.0 {
+            (min, max, -axis)
+        } else {
+            (max, min, axis)
+        };
+        let (axis_entry, axis_exit) = ((near - start) / movement, (far - start) / movement);
+        if axis_entry > entry {
+            entry = axis_entry;
+            normal = side;
+        }
+        exit = exit.min(axis_exit);
+    }
+    (entry < exit && (0.0..=1.0).contains(&entry)).then_some((entry, normal))
+}
+
+// Reads the map size from the start of a .tmx file, leaving the reader at the first line of
+// the floor layer. Also returns the lines it read.
+pub fn read_map_header(reader: &mut impl BufRead) -> (u32, u32, Vec<String>) {
+    try_read_map_header(reader).unwrap_or_else(|err| panic!("{err}"))
+}
+
+pub fn try_read_map_header(reader: &mut impl BufRead) -> Result<(u32, u32, Vec<String>), String> {
+    // The xml declaration, the map element and 5 more lines of map data
+    let lines = read_lines(reader, 7);
+    // Read map size from the map element
+    let map_width = get_xml_attribute(&lines[1], "width")
+        .and_then(|width| width.parse::<u32>().ok())
+        .ok_or("Unable to parse map, missing width")?;
+    let map_height = get_xml_attribute(&lines[1], "height")
+        .and_then(|height| height.parse::<u32>().ok())
+        .ok_or("Unable to parse map, missing height")?;
+    Ok((map_width, map_height, lines))
+}
+
+// A value in a tile layer's csv data that isn't a tile id
+pub struct LayerError {
+    pub cell: (u32, u32),
+    pub message: String,
+}
+
+// Parses the csv rows of a tile layer's data. Values that aren't tile ids are read as 0 and
+// returned as errors, so every bad cell is found instead of just the first.
+pub fn parse_layer_rows(lines: &[impl AsRef<str>]) -> (Vec<Vec<u32>>, Vec<LayerError>) {
+    let mut errors = vec![];
+    let rows = lines
+        .iter()
+        .enumerate()
+        .map(|(y, line)| {
+            line.as_ref()
+                .trim()
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .enumerate()
+                .map(|(x, value)| {
+                    value.parse::<u32>().unwrap_or_else(|_| {
+                        errors.push(LayerError {
+                            cell: (x as u32, y as u32),
+                            message: format!("{value} isn't a tile id"),
+                        });
+                        0
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    (rows, errors)
+}
+
+// Reads the rows of a tile layer's data, every row has to be as wide as the map
+fn read_layer(reader: &mut impl BufRead, width: u32, height: u32) -> Result<Vec<Vec<u32>>, String> {
+    let (rows, errors) = parse_layer_rows(&read_lines(reader, height));
+    if let Some(LayerError {
+        cell: (x, y),
+        message,
+    }) = errors.first()
+    {
+        return Err(format!("Unable to parse map, cell ({x}, {y}): {message}"));
+    }
+    if let Some((y, row)) = rows
+        .iter()
+        .enumerate()
+        .find(|(_, row)| row.len() != width as usize)
+    {
+        return Err(format!(
+            "Unable to parse map, row {y} has {} tiles, the map is {width} tiles wide",
+            row.len()
+        ));
+    }
+    Ok(rows)
+}
+
+// Reads the tile ids of the floor and wall layers of a .tmx file as they are, without parsing
+// the tiles. The file has to still be the size it was when the map was opened.
+fn read_raw_layers(path: &str, width: u32, height: u32) -> Result<RawLayers, String> {
+    let file = std::fs::File::open(path).map_err(|err| format!("Unable to open map: {err}"))?;
+    let mut reader = std::io::BufReader::new(file);
+    let (file_width, file_height, _) = try_read_map_header(&mut reader)?;
+    if (file_width, file_height) != (width, height) {
+        return Err(format!(
+            "{path} is {file_width}x{file_height} tiles now, the map is {width}x{height}"
+        ));
+    }
+    let ground = read_layer(&mut reader, width, height)?;
+    skip_lines(&mut reader, 4);
+    let walls = read_layer(&mut reader, width, height)?;
+    Ok((ground, walls))
+}
+
+// Reads lines without their line endings
+pub fn read_lines(reader: &mut impl BufRead, lines: u32) -> Vec<String> {
+    (0..lines)
+        .map(|_| {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            line.trim_end_matches(['\r', '\n']).to_string()
+        })
+        .collect()
+}
+
+pub fn skip_lines(reader: &mut impl BufRead, lines: u32) {
+    for _ in 0..lines {
+        reader.read_line(&mut String::new()).unwrap();
+    }
+}
+
+pub fn parse_ground(ground: u32) -> Ground {
+    try_parse_ground(ground).unwrap_or_else(|| panic!("Unable to parse map, ground: {ground}"))
+}
+
+// None for tiles that aren't ground or have flip flags that aren't a rotation
+pub fn try_parse_ground(ground: u32) -> Option<Ground> {
+    Some(Ground {
+        varient: GroundVarient::from_raw_u32(ground)?,
+        rotation: TileRotation::try_from_raw_u32(ground)?,
+    })
+}
+
+pub fn parse_wall(wall: u32) -> Option<Wall> {
+    try_parse_wall(wall).unwrap_or_else(|err| panic!("Unable to parse map, {err}"))
+}
+
+// Tiles that aren't walls are None, they are kept around as unknown walls. Only flip flags that
+// aren't a rotation are an error.
+pub fn try_parse_wall(wall: u32) -> Result<Option<Wall>, String> {
+    let Some(varient) = WallVarient::from_raw_u32(wall) else {
+        return Ok(None);
+    };
+    let rotation = TileRotation::try_from_raw_u32(wall).ok_or(format!(
+        "wall {wall} is flipped, only rotations are supported"
+    ))?;
+    Ok(Some(Wall { varient, rotation }))
+}
+
+// Returns the value of an attribute in a line of xml
+pub fn get_xml_attribute<'a>(line: &'a str, attribute: &str) -> Option<&'a str> {
+    let pattern = format!(" {attribute}=\"");
+    let start = line.find(&pattern)? + pattern.len();
+    let end = line[start..].find('"')? + start;
+    Some(&line[start..end])
+}
+
+// Replaces the value of an attribute in a line of xml, the line is unchanged if it doesn't have
+// the attribute
+pub fn set_xml_attribute(line: &str, attribute: &str, value: &str) -> String {
+    let pattern = format!(" {attribute}=\"");
+    let Some(start) = line.find(&pattern).map(|start| start + pattern.len()) else {
+        return line.to_string();
+    };
+    let Some(end) = line[start..].find('"').map(|end| end + start) else {
+        return line.to_string();
+    };
+    format!("{}{value}{}", &line[..start], &line[end..])
 }
 
 // Adding additional methods to raylib camera2d
@@ -165,6 +1221,7 @@ pub trait ImprovedCamera {
     fn to_screen_y(&self, world_pos_y: f32) -> f32;
     fn to_screen_rect(&self, rect: &Rectangle) -> Rectangle;
     fn to_world(&self, screen_pos: Vector2) -> Vector2;
+    fn get_visible_world_rect(&self, screen_size: Vector2) -> Rectangle;
     fn handle_player_controls(&mut self, rl: &mut RaylibHandle);
     fn track(&mut self, pos: Vector2, screen_size: Vector2);
     fn pan_to(&mut self, rl: &RaylibHandle, pos: Vector2, screen_size: Vector2);
@@ -198,6 +1255,16 @@ impl ImprovedCamera for Camera2D {
         (screen_pos / self.zoom) - self.offset
     }
 
+    fn get_visible_world_rect(&self, screen_size: Vector2) -> Rectangle {
+        let top_left = self.to_world(Vector2::zero());
+        Rectangle {
+            x: top_left.x,
+            y: top_left.y,
+            width: screen_size.x / self.zoom,
+            height: screen_size.y / self.zoom,
+        }
+    }
+
     fn handle_player_controls(&mut self, rl: &mut RaylibHandle) {
         let screen_size = Vector2::new(rl.get_screen_width() as f32, rl.get_screen_height() as f32);
         let mouse_wheel_move = rl.get_mouse_wheel_move();
@@ -222,9 +1289,401 @@ impl ImprovedCamera for Camera2D {
     }
 
     fn pan_to(&mut self, rl: &RaylibHandle, target_pos: Vector2, screen_size: Vector2) {
+        // Part of the way to the target the camera moves every 60th of a second, compounded so
+        // it pans the same at any frame rate
         let camera_pan_time = 8.0;
+        let pan = 1.0 - (1.0 - 1.0 / camera_pan_time as f32).powf(rl.get_frame_time() * 60.0);
         let old_pos = self.get_world_pos(self.offset, screen_size);
-        let pos = old_pos + (target_pos - old_pos) / (camera_pan_time / rl.get_frame_time() / 60.0);
+        let pos = old_pos + (target_pos - old_pos) * pan;
         self.offset = self.get_screen_offset(pos, screen_size);
     }
 }
+
+// Dirt map of a size with walls on some of the tiles, for tests
+#[cfg(test)]
+pub(crate) fn test_map(
+    width: usize,
+    height: usize,
+    walls: Vec<((usize, usize), WallVarient, TileRotation)>,
+) -> WorldMap {
+    let ground = (0..height)
+        .map(|_| {
+            (0..width)
+                .map(|_| Ground {
+                    varient: GroundVarient::Dirt,
+                    rotation: TileRotation::None,
+                })
+                .collect()
+        })
+        .collect();
+    let mut wall_tiles: Vec<Vec<Option<Wall>>> = (0..height)
+        .map(|_| (0..width).map(|_| None).collect())
+        .collect();
+    for ((x, y), varient, rotation) in walls {
+        wall_tiles[y][x] = Some(Wall { varient, rotation });
+    }
+    WorldMap::from_tiles(ground, wall_tiles, vec![])
+}
+
+// Writes a .tmx file laid out the way Tiled saves it to the temp dir and returns its path.
+// Objects are the xml of the object groups.
+#[cfg(test)]
+pub(crate) fn write_test_tmx(name: &str, floor: &[&str], walls: &[&str], objects: &str) -> String {
+    let width = floor.first().map_or(0, |row| row.split(',').count());
+    let height = floor.len();
+    let tmx = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" tiledversion="1.10.2" orientation="orthogonal" renderorder="right-down" width="{width}" height="{height}" tilewidth="32" tileheight="32" infinite="0" nextlayerid="4" nextobjectid="1">
+ <tileset firstgid="1" source="../tiled/floor_tile_sheet.tsx"/>
+ <tileset firstgid="65" source="../tiled/wall_tile_sheet.tsx"/>
+ <tileset firstgid="129" source="../tiled/object_tile_sheet.tsx"/>
+ <layer id="1" name="Floor" width="{width}" height="{height}">
+  <data encoding="csv">
+{}
+</data>
+ </layer>
+ <layer id="2" name="Walls" width="{width}" height="{height}">
+  <data encoding="csv">
+{}
+</data>
+ </layer>
+{objects}</map>
+"#,
+        floor.join(",\n"),
+        walls.join(",\n"),
+    );
+    let path = std::env::temp_dir().join(format!("{}_{name}", std::process::id()));
+    std::fs::write(&path, tmx).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOX_SIZE: f32 = Player::COLLIDER_SIZE;
+
+    fn box_at(center: Vector2) -> Rectangle {
+        Rectangle::new(
+            center.x - BOX_SIZE / 2.0,
+            center.y - BOX_SIZE / 2.0,
+            BOX_SIZE,
+            BOX_SIZE,
+        )
+    }
+
+    #[test]
+    fn fast_box_stops_at_thin_wall() {
+        // Wall strip from x 64 to 74, a lot thinner than the move
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::Staight, TileRotation::None)],
+        );
+        let rect = box_at(Vector2::new(40.0, 80.0));
+        let moved = map.move_and_slide(rect, Vector2::new(500.0, 0.0));
+        assert!(rect.x + rect.width + moved.x <= 64.0);
+        assert!(rect.x + rect.width + moved.x > 63.9);
+    }
+
+    #[test]
+    fn box_slides_along_wall() {
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::Staight, TileRotation::None)],
+        );
+        let rect = box_at(Vector2::new(40.0, 80.0));
+        let moved = map.move_and_slide(rect, Vector2::new(100.0, 10.0));
+        assert!(rect.x + rect.width + moved.x <= 64.0);
+        assert_eq!(moved.y, 10.0);
+    }
+
+    #[test]
+    fn box_slides_past_seam_between_walls() {
+        // Two wall strips on top of each other, touching at y 96
+        let map = test_map(
+            5,
+            5,
+            vec![
+                ((2, 2), WallVarient::Staight, TileRotation::None),
+                ((2, 3), WallVarient::Staight, TileRotation::None),
+            ],
+        );
+        let rect = box_at(Vector2::new(64.0 - COLLISION_SKIN - BOX_SIZE / 2.0, 80.0));
+        let moved = map.move_and_slide(rect, Vector2::new(5.0, 30.0));
+        assert_eq!(moved.y, 30.0);
+        assert!(rect.x + rect.width + moved.x <= 64.0);
+    }
+
+    #[test]
+    fn box_stops_in_inside_corner() {
+        // Elbow with strips on the left, x 64 to 74, and bottom, y 86 to 96, of the tile
+        let map = test_map(5, 5, vec![((2, 2), WallVarient::Elbow, TileRotation::None)]);
+        let rect = box_at(Vector2::new(85.0, 75.0));
+        let moved = map.move_and_slide(rect, Vector2::new(-50.0, 50.0));
+        let (x, y) = (rect.x + moved.x, rect.y + moved.y);
+        assert!((74.0..74.1).contains(&x));
+        assert!((85.9..=86.0).contains(&(y + BOX_SIZE)));
+    }
+
+    #[test]
+    fn box_slides_around_outside_corner() {
+        // Pillar from 73 to 87 on both axes, the box starts in line with its top but has moved
+        // up past it by the time it gets there
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::WhitePillar, TileRotation::None)],
+        );
+        let rect = box_at(Vector2::new(40.0, 73.0 - BOX_SIZE / 2.0 + 0.5));
+        let moved = map.move_and_slide(rect, Vector2::new(100.0, -10.0));
+        assert_eq!(moved.x, 100.0);
+        assert!(rect.y + rect.height + moved.y <= 73.0);
+    }
+
+    #[test]
+    fn fast_circle_stops_at_thin_wall() {
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::Staight, TileRotation::None)],
+        );
+        let circle = Circle {
+            x: 40.0,
+            y: 80.0,
+            radius: BOX_SIZE / 2.0,
+        };
+        let moved = map.move_circle_and_slide(circle, Vector2::new(500.0, 0.0));
+        assert!(circle.x + circle.radius + moved.x <= 64.0);
+        assert!(circle.x + circle.radius + moved.x > 63.9);
+    }
+
+    #[test]
+    fn circle_slides_around_corner() {
+        // Heading at the top left corner of the pillar, whose top is at y 73
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::WhitePillar, TileRotation::None)],
+        );
+        let circle = Circle {
+            x: 40.0,
+            y: 68.0,
+            radius: BOX_SIZE / 2.0,
+        };
+        let moved = map.move_circle_and_slide(circle, Vector2::new(100.0, 0.0));
+        // Pushed up and over the corner rather than stopped by it
+        assert!(moved.x > 60.0);
+        assert!(moved.y < 0.0);
+        assert!(circle.y + circle.radius + moved.y <= 73.0);
+    }
+
+    fn assert_hit(hit: Option<RayHit>, point: (f32, f32), normal: (f32, f32)) {
+        let hit = hit.expect("ray should hit a wall");
+        assert!(hit.point.distance_to(Vector2::new(point.0, point.1)) < 0.01);
+        assert!(hit.normal.distance_to(Vector2::new(normal.0, normal.1)) < 0.01);
+    }
+
+    #[test]
+    fn ray_hits_nearest_of_several_walls() {
+        // Wall strips from x 32 to 42 and 96 to 106
+        let map = test_map(
+            5,
+            5,
+            vec![
+                ((1, 2), WallVarient::Staight, TileRotation::None),
+                ((3, 2), WallVarient::Staight, TileRotation::None),
+            ],
+        );
+        let hit = map.raycast(Vector2::new(10.0, 80.0), Vector2::new(150.0, 80.0));
+        assert_eq!(hit.as_ref().map(|hit| hit.tile), Some((1, 2)));
+        assert!((hit.as_ref().unwrap().distance - 22.0).abs() < 0.01);
+        assert_hit(hit, (32.0, 80.0), (-1.0, 0.0));
+
+        let hit = map.raycast(Vector2::new(150.0, 80.0), Vector2::new(10.0, 80.0));
+        assert_eq!(hit.as_ref().map(|hit| hit.tile), Some((3, 2)));
+        assert_hit(hit, (106.0, 80.0), (1.0, 0.0));
+    }
+
+    #[test]
+    fn ray_normal_faces_back_on_each_side_of_wall() {
+        // Wall strip from x 64 to 74 and y 64 to 96
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::Staight, TileRotation::None)],
+        );
+        let ray = |from: (f32, f32), to: (f32, f32)| {
+            map.raycast(Vector2::new(from.0, from.1), Vector2::new(to.0, to.1))
+        };
+        assert_hit(ray((20.0, 80.0), (120.0, 80.0)), (64.0, 80.0), (-1.0, 0.0));
+        assert_hit(ray((120.0, 80.0), (20.0, 80.0)), (74.0, 80.0), (1.0, 0.0));
+        assert_hit(ray((69.0, 20.0), (69.0, 150.0)), (69.0, 64.0), (0.0, -1.0));
+        assert_hit(ray((69.0, 150.0), (69.0, 20.0)), (69.0, 96.0), (0.0, 1.0));
+    }
+
+    #[test]
+    fn ray_hitting_corner_takes_side_it_faces_most() {
+        // Pillar from 73 to 87 on both axes, the ray is shallower than 45 degrees so it faces the
+        // left side more than the top
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::WhitePillar, TileRotation::None)],
+        );
+        let hit = map.raycast(Vector2::new(53.0, 63.0), Vector2::new(93.0, 83.0));
+        assert_hit(hit, (73.0, 73.0), (-1.0, 0.0));
+    }
+
+    #[test]
+    fn ray_starting_inside_wall_hits_straight_away() {
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::WhitePillar, TileRotation::None)],
+        );
+        let hit = map.raycast(Vector2::new(80.0, 80.0), Vector2::new(150.0, 80.0));
+        assert_eq!(hit.as_ref().map(|hit| hit.distance), Some(0.0));
+        assert_hit(hit, (80.0, 80.0), (-1.0, 0.0));
+    }
+
+    #[test]
+    fn ray_along_wall_face_hits_its_end() {
+        // Runs down the left face of the strip, so the first thing it touches is the top corner
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::Staight, TileRotation::None)],
+        );
+        let hit = map.raycast(Vector2::new(64.0, 20.0), Vector2::new(64.0, 150.0));
+        assert_hit(hit, (64.0, 64.0), (0.0, -1.0));
+    }
+
+    #[test]
+    fn zero_length_ray_hits_nothing() {
+        let map = test_map(
+            5,
+            5,
+            vec![((2, 2), WallVarient::Staight, TileRotation::None)],
+        );
+        assert!(map
+            .raycast(Vector2::new(60.0, 80.0), Vector2::new(60.0, 80.0))
+            .is_none());
+        assert!(map
+            .raycast(Vector2::new(64.0, 80.0), Vector2::new(64.0, 80.0))
+            .is_none());
+    }
+
+    #[test]
+    fn bad_tiles_fail_to_load_instead_of_panicking() {
+        let try_load = |floor: [&str; 3], walls: [&str; 3]| {
+            let path = write_test_tmx("bad_tiles.tmx", &floor, &walls, "");
+            let map = WorldMap::try_load_from_file(&path);
+            std::fs::remove_file(path).unwrap();
+            map
+        };
+        let floor = ["1,1,1", "1,1,1", "1,1,1"];
+        let walls = ["0,0,0", "0,0,0", "0,0,0"];
+        assert!(try_load(floor, walls).is_ok());
+        // Empty floor tile
+        assert!(try_load(["1,1,1", "1,0,1", "1,1,1"], walls).is_err());
+        // Floor and wall tiles flipped horizontally, which isn't a rotation
+        assert!(try_load(["1,1,1", "1,2147483649,1", "1,1,1"], walls).is_err());
+        let err = try_load(floor, ["0,0,0", "0,2147483713,0", "0,0,0"])
+            .err()
+            .unwrap();
+        assert!(err.ends_with("at (1, 1)"));
+    }
+
+    #[test]
+    fn bad_streamed_chunk_is_an_error() {
+        let path = write_test_tmx(
+            "bad_chunk.tmx",
+            &["1,1,1", "1,x,1", "1,1,1"],
+            &["0,0,0", "0,0,0", "0,0,0"],
+            "",
+        );
+        let mut map = WorldMap::try_stream_from_file(&path).unwrap();
+        let err = map
+            .update_streaming(Vector2::new(16.0, 16.0))
+            .err()
+            .unwrap();
+        assert!(err.contains("x isn't a tile id"));
+        assert!(!map.is_chunk_loaded((0, 0)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // Saves a map to the temp dir and reads the file back
+    fn save_and_read(map: &WorldMap, name: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("{}_{name}", std::process::id()));
+        map.save_to_tmx(path.to_str().unwrap()).unwrap();
+        let saved = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        saved
+    }
+
+    #[test]
+    fn map0_round_trips_losslessly() {
+        let original = std::fs::read("assets/maps/map0.tmx").unwrap();
+        let map = WorldMap::load_from_file("assets/maps/map0.tmx");
+        assert!(save_and_read(&map, "loaded_map0.tmx") == original);
+        // Nothing is loaded yet, so every chunk comes from the source file
+        let map = WorldMap::stream_from_file("assets/maps/map0.tmx");
+        assert!(save_and_read(&map, "streamed_map0.tmx") == original);
+    }
+
+    #[test]
+    fn saving_streamed_map_with_broken_source_fails() {
+        let path = write_test_tmx(
+            "broken_source.tmx",
+            &["1,1,1", "1,1,1", "1,1,1"],
+            &["0,0,0", "0,0,0", "0,0,0"],
+            "",
+        );
+        let map = WorldMap::stream_from_file(&path);
+        // Changed outside the game after the map was opened
+        let broken = std::fs::read_to_string(&path)
+            .unwrap()
+            .replace("1,1,1,", "1,x,1,");
+        std::fs::write(&path, broken).unwrap();
+        assert!(map.save_to_tmx(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // Every tile of a map that isn't square is there, walls included out to the far edges
+    fn assert_fully_loaded(path: &str, width: u32, height: u32) {
+        let map = WorldMap::load_from_file(path);
+        assert_eq!((map.width, map.height), (width, height));
+        let tiles = map
+            .iter_tiles(&map.get_full_tile_range())
+            .collect::<Vec<MapTile>>();
+        assert_eq!(tiles.len(), (width * height) as usize);
+        assert!((0..height).any(|y| map.get_wall(width - 1, y).is_some()));
+        assert!((0..width).any(|x| map.get_wall(x, height - 1).is_some()));
+    }
+
+    #[test]
+    fn wide_map_loads_every_tile() {
+        assert_fully_loaded("assets/maps/test_wide.tmx", 40, 12);
+    }
+
+    #[test]
+    fn tall_map_loads_every_tile() {
+        assert_fully_loaded("assets/maps/test_tall.tmx", 12, 40);
+    }
+
+    #[test]
+    fn layer_rows_report_every_bad_cell() {
+        let (rows, errors) = parse_layer_rows(&["1,2,x,", "4,,5,6", "y,8,9"]);
+        assert_eq!(rows, vec![vec![1, 2, 0], vec![4, 5, 6], vec![0, 8, 9]]);
+        let errors = errors
+            .iter()
+            .map(|error| (error.cell, error.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![((2, 0), "x isn't a tile id"), ((0, 2), "y isn't a tile id")]
+        );
+    }
+}
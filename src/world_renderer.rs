@@ -1,26 +1,51 @@
 use crate::{
-    bullet::Bullet, day_cycle, player::*, world::*, DebugInfo, ImprovedCamera, Line, WorldMap,
+    day_cycle,
+    map_objects::ObjectKind,
+    player::*,
+    tile_animation::TileAnimations,
+    tile_cache::{draw_tile, TileLayer, TileLayerCache, Tileset},
+    world::*,
+    DebugInfo, ImprovedCamera, Line, TileRange, TileRotation, WorldMap, FLOOR_TILESET_PATH,
+    OBJECT_FIRST_GID, OBJECT_TILESET_PATH, WALL_TILESET_PATH,
 };
 use raylib::prelude::*;
 
 pub const TILE_SIZE: f32 = 32.0;
+pub const LIGHTING_SHADER_PATH: &str = "shaders/lighting.fs";
+pub const FLOOR_TILE_SHEET_PATH: &str = "assets/background/floor_tile_sheet.png";
+pub const WALL_TILE_SHEET_PATH: &str = "assets/background/wall_tile_sheet.png";
+pub const OBJECT_TILE_SHEET_PATH: &str = "assets/background/objects_tile_sheet.png";
+// Extra tiles drawn around the screen so shadows cast from walls just off screen still show
+const CULLING_MARGIN: u32 = 1;
 
 pub struct Renderer {
     pub shader: Shader,
     target: RenderTexture2D,
     shadow_target: RenderTexture2D,
-    floor_tile_sheet: Texture2D,
-    wall_tile_sheet: Texture2D,
+    // What the player can see, read by the lighting shader to draw the fog of war
+    fog_mask: RenderTexture2D,
+    fog_mask_location: i32,
+    // Hides what the player can't see, turned off to see the whole map in the editor
+    pub fog_enabled: bool,
+    // How far between the last world tick and the next one the frame is, moving things are
+    // drawn that far between where they were and where they are
+    pub interpolation: f32,
+    floor_tileset: Tileset,
+    wall_tileset: Tileset,
+    object_tileset: Tileset,
+    floor_cache: TileLayerCache,
+    wall_cache: TileLayerCache,
+    // Seconds since the game started, drives every tile animation so they stay in sync
+    animation_time: f32,
 }
 
 impl Renderer {
     pub fn new(rl: &mut RaylibHandle, thread: &RaylibThread) -> Renderer {
+        let shader =
+            rl.load_shader_from_memory(thread, None, Some(include_str!("../shaders/lighting.fs")));
         Renderer {
-            shader: rl.load_shader_from_memory(
-                thread,
-                None,
-                Some(include_str!("../shaders/lighting.fs")),
-            ),
+            fog_mask_location: shader.get_shader_location("fogMask"),
+            shader,
             target: rl
                 .load_render_texture(
                     thread,
@@ -35,12 +60,92 @@ impl Renderer {
                     rl.get_screen_height() as u32,
                 )
                 .unwrap(),
-            floor_tile_sheet: rl
-                .load_texture(thread, "assets/background/floor_tile_sheet.png")
+            fog_mask: rl
+                .load_render_texture(
+                    thread,
+                    rl.get_screen_width() as u32,
+                    rl.get_screen_height() as u32,
+                )
+                .unwrap(),
+            fog_enabled: true,
+            interpolation: 1.0,
+            floor_tileset: Tileset::load(rl, thread, FLOOR_TILE_SHEET_PATH, FLOOR_TILESET_PATH)
+                .unwrap(),
+            wall_tileset: Tileset::load(rl, thread, WALL_TILE_SHEET_PATH, WALL_TILESET_PATH)
                 .unwrap(),
-            wall_tile_sheet: rl
-                .load_texture(thread, "assets/background/wall_tile_sheet.png")
+            object_tileset: Tileset::load(rl, thread, OBJECT_TILE_SHEET_PATH, OBJECT_TILESET_PATH)
                 .unwrap(),
+            floor_cache: TileLayerCache::new(TileLayer::Floor),
+            wall_cache: TileLayerCache::new(TileLayer::Walls),
+            animation_time: 0.0,
+        }
+    }
+
+    // Compiles the lighting shader again from disk. The current shader is kept if it fails.
+    pub fn reload_shader(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+    ) -> Result<(), String> {
+        let code = std::fs::read_to_string(LIGHTING_SHADER_PATH).map_err(|err| err.to_string())?;
+        let shader = rl.load_shader_from_memory(thread, None, Some(&code));
+        // Raylib falls back to its default shader when compiling fails, which has none of the
+        // light uniforms
+        if shader.get_shader_location("lightsAmount") == -1 {
+            return Err("Shader failed to compile, see the log for details".to_string());
+        }
+        self.fog_mask_location = shader.get_shader_location("fogMask");
+        self.shader = shader;
+        Ok(())
+    }
+
+    // Loads a tile sheet again from disk if it is one the renderer uses, and re-bakes the tile
+    // layers drawn with it
+    pub fn reload_texture(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        path: &str,
+    ) -> Result<(), String> {
+        if path == FLOOR_TILE_SHEET_PATH {
+            self.floor_tileset.sheet = rl.load_texture(thread, path)?;
+            self.floor_cache.mark_all_dirty();
+        } else if path == WALL_TILE_SHEET_PATH {
+            self.wall_tileset.sheet = rl.load_texture(thread, path)?;
+            self.wall_cache.mark_all_dirty();
+        } else if path == OBJECT_TILE_SHEET_PATH {
+            self.object_tileset.sheet = rl.load_texture(thread, path)?;
+        }
+        Ok(())
+    }
+
+    // Reads the tile animations of a tileset again if it is one the renderer uses
+    pub fn reload_tileset(&mut self, path: &str) -> Result<(), String> {
+        if path == FLOOR_TILESET_PATH {
+            self.floor_tileset.animations = TileAnimations::load(path)?;
+            self.floor_cache.mark_all_dirty();
+        } else if path == WALL_TILESET_PATH {
+            self.wall_tileset.animations = TileAnimations::load(path)?;
+            self.wall_cache.mark_all_dirty();
+        } else if path == OBJECT_TILESET_PATH {
+            self.object_tileset.animations = TileAnimations::load(path)?;
+        }
+        Ok(())
+    }
+
+    // Keeps the baked floor and wall layers in sync with the map
+    pub fn update_tile_cache(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        map: &WorldMap,
+        changed_tiles: &[(u32, u32)],
+    ) {
+        self.floor_cache.sync_chunks(rl, thread, map);
+        self.wall_cache.sync_chunks(rl, thread, map);
+        for (x, y) in changed_tiles.iter() {
+            self.floor_cache.mark_tile_dirty(*x, *y);
+            self.wall_cache.mark_tile_dirty(*x, *y);
         }
     }
 
@@ -58,6 +163,9 @@ impl Renderer {
             self.shadow_target = rl
                 .load_render_texture(thread, screen_size.x as u32, screen_size.y as u32)
                 .unwrap();
+            self.fog_mask = rl
+                .load_render_texture(thread, screen_size.x as u32, screen_size.y as u32)
+                .unwrap();
         }
     }
 
@@ -77,20 +185,45 @@ impl Renderer {
         world: &World,
         debug_info: &DebugInfo,
     ) {
+        let screen_size = Vector2::new(d.get_screen_width() as f32, d.get_screen_height() as f32);
+        // Only tiles inside the camera's view get drawn
+        let visible_tiles = world
+            .map
+            .get_tile_range(&camera.get_visible_world_rect(screen_size), CULLING_MARGIN);
+
+        // Re-bake any static tile chunks that changed
+        self.floor_cache
+            .bake(d, thread, &world.map, &self.floor_tileset);
+        self.wall_cache
+            .bake(d, thread, &world.map, &self.wall_tileset);
+        self.animation_time += d.get_frame_time();
+
         // Draw world onto the renderers target
         self.clear_target(d, thread);
-        self.draw_floor(d, thread, &world.map, camera);
-        self.draw_wall_shadows(d, thread, world, camera);
-        self.draw_walls(d, thread, &world.map, camera);
+        self.draw_floor(d, thread, camera, &world.map, &visible_tiles);
+        self.draw_impacts(world, d, thread, camera);
+        self.draw_objects(d, thread, camera, world, screen_size);
+        self.draw_wall_shadows(d, thread, world, camera, &visible_tiles);
+        self.draw_walls(d, thread, camera, &world.map, &visible_tiles);
         self.draw_player(d, thread, camera, world, player);
-        self.draw_bullets(&world.bullets, d, thread, camera);
+        self.draw_bullets(world, d, thread, camera);
 
         if debug_info.debug {
-            self.draw_debug_colliders(thread, d, player, world, camera);
+            self.draw_debug_colliders(thread, d, player, world, camera, &visible_tiles);
         }
+        self.draw_fog_mask(d, thread, world, camera, &visible_tiles);
 
         // Render target with shader
         let mut sh = d.begin_shader_mode(&self.shader);
+        // Textures can only be given to a shader while it is in use, which the safe bindings
+        // don't allow since the shader is borrowed by the draw handle
+        unsafe {
+            raylib::ffi::SetShaderValueTexture(
+                *self.shader.as_ref(),
+                self.fog_mask_location,
+                *self.fog_mask.as_ref(),
+            );
+        }
         sh.draw_texture(&self.target, 0, 0, Color::WHITE);
     }
 
@@ -104,7 +237,7 @@ impl Renderer {
         player: &Player,
     ) {
         let mut tg = d.begin_texture_mode(thread, &mut self.target);
-        let player_screen_pos = camera.to_screen(player.pos);
+        let player_screen_pos = camera.to_screen(player.get_render_pos(self.interpolation));
 
         // Draw player's shadow
         tg.draw_circle(
@@ -136,62 +269,111 @@ impl Renderer {
         &mut self,
         d: &mut RaylibDrawHandle,
         thread: &RaylibThread,
+        camera: &Camera2D,
         map: &WorldMap,
+        visible_tiles: &TileRange,
+    ) {
+        let mut tg = d.begin_texture_mode(thread, &mut self.target);
+        self.floor_cache.draw(&mut tg, camera, visible_tiles);
+        self.floor_cache.draw_animated(
+            &mut tg,
+            camera,
+            map,
+            visible_tiles,
+            &self.floor_tileset,
+            self.animation_time,
+        );
+    }
+
+    // Draws the objects placed as tiles in Tiled, like props
+    fn draw_objects(
+        &mut self,
+        d: &mut RaylibDrawHandle,
+        thread: &RaylibThread,
         camera: &Camera2D,
+        world: &World,
+        screen_size: Vector2,
     ) {
+        let visible_rect = camera.get_visible_world_rect(screen_size);
         let mut tg = d.begin_texture_mode(thread, &mut self.target);
-        for y in 0..map.height {
-            for x in 0..map.width {
-                let texture = &self.floor_tile_sheet;
-                let tile = &map.ground[y as usize][x as usize];
-                let texture_width = self.floor_tile_sheet.width() as u32 / TILE_SIZE as u32;
-                let tile_x = (tile.varient as u32) % texture_width;
-                let tile_y = (tile.varient as u32) / texture_width;
-                let rot_offset = tile.rotation.get_rotation_offset();
-
-                tg.draw_texture_pro(
-                    texture,
-                    Rectangle::new(
-                        tile_x as f32 * TILE_SIZE,
-                        tile_y as f32 * TILE_SIZE,
-                        TILE_SIZE,
-                        TILE_SIZE,
-                    ),
-                    Rectangle::new(
-                        camera.to_screen_x(x as f32 * TILE_SIZE + rot_offset.x),
-                        camera.to_screen_y(y as f32 * TILE_SIZE + rot_offset.y),
-                        TILE_SIZE * camera.zoom + 0.001 * TILE_SIZE,
-                        TILE_SIZE * camera.zoom + 0.001 * TILE_SIZE,
-                    ),
-                    Vector2::zero(),
-                    tile.rotation.get_angle(),
-                    Color::WHITE,
-                )
+        for object in world.map.objects.iter() {
+            let Some(gid) = object.gid else {
+                continue;
+            };
+            if !object.rect.check_collision_recs(&visible_rect) {
+                continue;
             }
+            // Flip flags aren't supported for objects, and objects from other tilesets are skipped
+            let Some(tile) = (gid & 0x0FFFFFFF).checked_sub(OBJECT_FIRST_GID) else {
+                continue;
+            };
+            draw_tile(
+                &mut tg,
+                &self.object_tileset.sheet,
+                self.object_tileset
+                    .animations
+                    .get_frame(tile, self.animation_time),
+                &TileRotation::None,
+                camera.to_screen(Vector2::new(object.rect.x, object.rect.y)),
+                object.rect.width / TILE_SIZE * camera.zoom,
+            );
+        }
+    }
+
+    // Draws a puff where bullets landed, colored by the ground they hit, fading out over time
+    fn draw_impacts(
+        &mut self,
+        world: &World,
+        d: &mut RaylibDrawHandle,
+        thread: &RaylibThread,
+        camera: &Camera2D,
+    ) {
+        let mut tg = d.begin_texture_mode(thread, &mut self.target);
+        for impact in world
+            .impacts
+            .iter()
+            .filter(|impact| !self.fog_enabled || world.visibility.is_visible(impact.pos))
+        {
+            let fade = (impact.time_left / IMPACT_LIFETIME).clamp(0.0, 1.0);
+            tg.draw_circle_v(
+                camera.to_screen(impact.pos),
+                (3.0 - fade * 1.5) * camera.zoom,
+                impact.effect.get_color().fade(fade * 0.8),
+            );
         }
     }
 
     pub fn draw_bullets(
         &mut self,
-        bullets: &[Bullet],
+        world: &World,
         d: &mut RaylibDrawHandle,
         thread: &RaylibThread,
         camera: &Camera2D,
     ) {
         let mut tg = d.begin_texture_mode(thread, &mut self.target);
-        for bullet in bullets.iter() {
+        // Bullets out of sight are hidden by the fog of war
+        for bullet in world
+            .bullets
+            .iter()
+            .filter(|bullet| !self.fog_enabled || world.visibility.is_visible(bullet.pos))
+        {
+            let pos = bullet.get_render_pos(self.interpolation);
+            let trail = [
+                bullet.pos_history[1].lerp(bullet.pos_history[0], self.interpolation),
+                bullet.pos_history[2].lerp(bullet.pos_history[1], self.interpolation),
+            ];
             // Long transparent trail
             tg.draw_line_ex(
-                camera.to_screen(bullet.pos_history[0]),
-                camera.to_screen(bullet.pos_history[1]),
+                camera.to_screen(trail[0]),
+                camera.to_screen(trail[1]),
                 2.0,
                 Color::new(255, 255, 255, 30),
             );
 
             // Bullet
             tg.draw_line_ex(
-                camera.to_screen(bullet.pos),
-                camera.to_screen(bullet.pos_history[0]),
+                camera.to_screen(pos),
+                camera.to_screen(trail[0]),
                 2.0,
                 Color::WHITE,
             );
@@ -203,39 +385,64 @@ impl Renderer {
         &mut self,
         d: &mut RaylibDrawHandle,
         thread: &RaylibThread,
-        map: &WorldMap,
         camera: &Camera2D,
+        map: &WorldMap,
+        visible_tiles: &TileRange,
     ) {
         let mut tg = d.begin_texture_mode(thread, &mut self.target);
-        for y in 0..map.height {
-            for x in 0..map.height {
-                let texture = &self.wall_tile_sheet;
-                if let Some(tile) = &map.walls[y as usize][x as usize] {
-                    let tile_x = (tile.varient as i32) % (texture.width / TILE_SIZE as i32);
-                    let tile_y = (tile.varient as i32) / (texture.width / TILE_SIZE as i32);
-                    let rot_offset = tile.rotation.get_rotation_offset();
-
-                    tg.draw_texture_pro(
-                        texture,
-                        Rectangle::new(
-                            tile_x as f32 * TILE_SIZE,
-                            tile_y as f32 * TILE_SIZE,
-                            TILE_SIZE,
-                            TILE_SIZE,
-                        ),
-                        Rectangle::new(
-                            camera.to_screen_x(x as f32 * TILE_SIZE + rot_offset.x),
-                            camera.to_screen_y(y as f32 * TILE_SIZE + rot_offset.y),
-                            TILE_SIZE * camera.zoom + 0.001 * TILE_SIZE,
-                            TILE_SIZE * camera.zoom + 0.001 * TILE_SIZE,
-                        ),
-                        Vector2::zero(),
-                        tile.rotation.get_angle(),
-                        Color::WHITE,
-                    );
-                }
+        self.wall_cache.draw(&mut tg, camera, visible_tiles);
+        self.wall_cache.draw_animated(
+            &mut tg,
+            camera,
+            map,
+            visible_tiles,
+            &self.wall_tileset,
+            self.animation_time,
+        );
+    }
+
+    // Draws what the player can see in red and the explored tiles in green, for the lighting
+    // shader to darken everything else
+    fn draw_fog_mask(
+        &mut self,
+        d: &mut RaylibDrawHandle,
+        thread: &RaylibThread,
+        world: &World,
+        camera: &Camera2D,
+        visible_tiles: &TileRange,
+    ) {
+        let mut fg = d.begin_texture_mode(thread, &mut self.fog_mask);
+        if !self.fog_enabled {
+            fg.clear_background(Color::RED);
+            return;
+        }
+        fg.clear_background(Color::BLACK);
+        for (x, y) in visible_tiles.iter() {
+            if world.visibility.is_explored(x, y) {
+                fg.draw_rectangle_rec(
+                    camera.to_screen_rect(&Rectangle::new(
+                        x as f32 * TILE_SIZE,
+                        y as f32 * TILE_SIZE,
+                        TILE_SIZE,
+                        TILE_SIZE,
+                    )),
+                    Color::GREEN,
+                );
             }
         }
+
+        let origin = camera.to_screen(world.visibility.origin);
+        let polygon = &world.visibility.polygon;
+        for (i, point) in polygon.iter().enumerate() {
+            let next = polygon[(i + 1) % polygon.len()];
+            // Raylib only draws triangles with their points going counter-clockwise on screen
+            fg.draw_triangle(
+                origin,
+                camera.to_screen(next),
+                camera.to_screen(*point),
+                Color::YELLOW,
+            );
+        }
     }
 
     fn draw_debug_colliders(
@@ -245,60 +452,123 @@ impl Renderer {
         player: &Player,
         world: &World,
         camera: &Camera2D,
+        visible_tiles: &TileRange,
     ) {
+        let mouse_pos = camera.to_world(d.get_mouse_position());
         let mut tg = d.begin_texture_mode(thread, &mut self.target);
         // Drawing debug colliders for walls
-        for y in 0..world.map.height {
-            for x in 0..world.map.height {
-                if let Some(wall) = &world.map.walls[y as usize][x as usize] {
-                    for rect in &wall
-                        .get_collider()
-                        .with_pos(Vector2::new(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE))
-                        .rects
-                    {
-                        tg.draw_rectangle(
-                            camera.to_screen_x(rect.x) as i32,
-                            camera.to_screen_y(rect.y) as i32,
-                            (rect.width * camera.zoom) as i32,
-                            (rect.height * camera.zoom) as i32,
-                            Color::BLUE,
-                        );
-
-                        for line in Line::from_rect(rect) {
-                            tg.draw_line_ex(
-                                camera.to_screen(line.start),
-                                camera.to_screen(line.end),
-                                3.0,
-                                Color::GREEN,
-                            );
-                            tg.draw_line_ex(
-                                camera.to_screen(line.start),
-                                camera.to_screen(line.end),
-                                3.0,
-                                Color::GREEN,
-                            );
-                        }
-                    }
+        for (tile_pos, wall) in world.map.iter_walls(visible_tiles) {
+            for rect in &wall.get_collider().with_pos(tile_pos).rects {
+                tg.draw_rectangle(
+                    camera.to_screen_x(rect.x) as i32,
+                    camera.to_screen_y(rect.y) as i32,
+                    (rect.width * camera.zoom) as i32,
+                    (rect.height * camera.zoom) as i32,
+                    Color::BLUE,
+                );
+
+                for line in Line::from_rect(rect) {
+                    tg.draw_line_ex(
+                        camera.to_screen(line.start),
+                        camera.to_screen(line.end),
+                        3.0,
+                        Color::GREEN,
+                    );
+                    tg.draw_line_ex(
+                        camera.to_screen(line.start),
+                        camera.to_screen(line.end),
+                        3.0,
+                        Color::GREEN,
+                    );
                 }
             }
         }
 
+        // Drawing how far noises can be heard
+        for noise in world.noises.iter() {
+            tg.draw_circle_lines(
+                camera.to_screen_x(noise.pos.x) as i32,
+                camera.to_screen_y(noise.pos.y) as i32,
+                noise.radius * camera.zoom,
+                Color::YELLOW,
+            );
+        }
+
+        // Drawing the outlines of zones
+        for object in world.map.objects.iter().filter(|object| object.has_area()) {
+            let color = match object.get_kind() {
+                Ok(ObjectKind::Trigger) => Color::MAGENTA,
+                Ok(ObjectKind::ExtractionZone) => Color::LIME,
+                Ok(ObjectKind::Transition) => Color::GOLD,
+                _ => continue,
+            };
+            let origin = Vector2::new(object.rect.x, object.rect.y);
+            if object.polygon.is_empty() {
+                tg.draw_rectangle_lines_ex(camera.to_screen_rect(&object.rect), 2.0, color);
+            }
+            for (i, start) in object.polygon.iter().enumerate() {
+                let end = object.polygon[(i + 1) % object.polygon.len()];
+                tg.draw_line_ex(
+                    camera.to_screen(origin + *start),
+                    camera.to_screen(origin + end),
+                    2.0,
+                    color,
+                );
+            }
+        }
+
+        // Drawing the path from the player to the mouse
+        if let Some(path) = world.navigation.find_path(player.pos, mouse_pos) {
+            for points in path.windows(2) {
+                tg.draw_line_ex(
+                    camera.to_screen(points[0]),
+                    camera.to_screen(points[1]),
+                    2.0,
+                    Color::SKYBLUE,
+                );
+            }
+        }
+
         // Drawing player collider
-        tg.draw_rectangle_rec(
-            camera.to_screen_rect(&player.get_world_collider().rects[0]),
-            Color::RED,
-        );
+        let player_collider = player.get_world_collider();
+        for circle in player_collider.circles.iter() {
+            tg.draw_circle_v(
+                camera.to_screen(Vector2::new(circle.x, circle.y)),
+                circle.radius * camera.zoom,
+                Color::RED,
+            );
+        }
 
-        for (y, wall_line) in world.map.walls.iter().enumerate() {
-            for (x, wall) in wall_line.iter().enumerate() {
-                if let Some(wall) = wall {
-                    if let Some(collider) = wall
-                        .get_collider()
-                        .with_pos(Vector2::new(x as f32 * 32.0, y as f32 * 32.0))
-                        .collides(&player.get_world_collider())
-                    {
-                        tg.draw_rectangle_rec(camera.to_screen_rect(&collider), Color::WHITE);
-                    }
+        for (tile_pos, wall) in world.map.iter_walls(visible_tiles) {
+            let collider = wall.get_collider().with_pos(tile_pos);
+            if collider.collides(&player_collider) {
+                for rect in collider.rects.iter() {
+                    tg.draw_rectangle_rec(camera.to_screen_rect(rect), Color::WHITE);
+                }
+            }
+        }
+
+        // Prop footprints
+        for collider in world.map.get_prop_colliders() {
+            for rect in collider.rects.iter() {
+                tg.draw_rectangle_lines_ex(camera.to_screen_rect(rect), 2.0, Color::ORANGE);
+            }
+            for circle in collider.circles.iter() {
+                tg.draw_circle_lines(
+                    camera.to_screen_x(circle.x) as i32,
+                    camera.to_screen_y(circle.y) as i32,
+                    circle.radius * camera.zoom,
+                    Color::ORANGE,
+                );
+            }
+            for polygon in collider.polygons.iter() {
+                for (i, start) in polygon.iter().enumerate() {
+                    tg.draw_line_ex(
+                        camera.to_screen(*start),
+                        camera.to_screen(polygon[(i + 1) % polygon.len()]),
+                        2.0,
+                        Color::ORANGE,
+                    );
                 }
             }
         }
@@ -322,6 +592,7 @@ impl Renderer {
         thread: &RaylibThread,
         world: &World,
         camera: &Camera2D,
+        visible_tiles: &TileRange,
     ) {
         let screen_size = Vector2::new(d.get_screen_width() as f32, d.get_screen_height() as f32);
 
@@ -329,51 +600,58 @@ impl Renderer {
         let shadow_color = world.day_cycle.get_shadow_color();
         let (shadow_x_length, shadow_y_length) = (12.0, 8.0);
 
+        // Shadows shrink towards the light source while it rises and grow on the other
+        // side while it sets. At night the moon takes the sun's place.
+        let (is_rising, shadow_width) = if let Some(moon_progress) =
+            world.day_cycle.get_moon_progress()
+        {
+            if moon_progress < 0.5 {
+                (true, 1.0 - moon_progress * 2.0)
+            } else {
+                (false, (moon_progress - 0.5) * 2.0)
+            }
+        }
+        // Before noon
+        else if !(day_cycle::NOON..=1.0 - day_cycle::SUNRISE_LENGTH).contains(&normilized_time) {
+            let shadow_width = if normilized_time > 1.0 - day_cycle::SUNRISE_LENGTH {
+                1.0
+            } else {
+                1.0 - normilized_time.max(day_cycle::SUNRISE) / 0.25
+            };
+            (true, shadow_width)
+        }
+        // After noon
+        else {
+            (
+                false,
+                (normilized_time.min(day_cycle::SUNSET) - day_cycle::NOON) * 4.0,
+            )
+        };
+
         let mut shd = d.begin_texture_mode(thread, &mut self.shadow_target);
         shd.clear_background(Color::new(0, 0, 0, 0));
 
         // Drawing debug colliders for walls
-        for y in 0..world.map.height {
-            for x in 0..world.map.height {
-                if let Some(wall) = &world.map.walls[y as usize][x as usize] {
-                    for rect in &wall
-                        .get_collider()
-                        .with_pos(Vector2::new(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE))
-                        .rects
-                    {
-                        // Before noon
-                        if !(day_cycle::NOON..=1.0 - day_cycle::SUNRISE_LENGTH)
-                            .contains(&normilized_time)
-                        {
-                            let shadow_width = if normilized_time > 1.0 - day_cycle::SUNRISE_LENGTH
-                            {
-                                1.0
-                            } else {
-                                1.0 - normilized_time.max(day_cycle::SUNRISE) / 0.25
-                            };
-                            let screen_rect = Rectangle::new(
-                                screen_size.x - camera.to_screen_x(rect.x + rect.width),
-                                camera.to_screen_y(rect.y - shadow_width * shadow_y_length),
-                                (rect.width + shadow_width * shadow_x_length) * camera.zoom,
-                                (rect.height + shadow_width * shadow_y_length) * camera.zoom,
-                            );
-                            shd.draw_rectangle_rec(screen_rect, Color::new(255, 255, 255, 255));
-                        }
-                        // After noon
-                        else if normilized_time > day_cycle::NOON {
-                            let shadow_width =
-                                (normilized_time.min(day_cycle::SUNSET) - day_cycle::NOON) * 4.0;
-                            let screen_rect_width =
-                                (rect.width + shadow_width * shadow_x_length) * camera.zoom;
-                            let screen_rect = Rectangle::new(
-                                (screen_size.x - camera.to_screen_x(rect.x)) - screen_rect_width,
-                                camera.to_screen_y(rect.y),
-                                screen_rect_width,
-                                (rect.height + shadow_width * shadow_y_length) * camera.zoom,
-                            );
-                            shd.draw_rectangle_rec(screen_rect, Color::new(255, 255, 255, 255));
-                        }
-                    }
+        for (tile_pos, wall) in world.map.iter_walls(visible_tiles) {
+            for rect in &wall.get_collider().with_pos(tile_pos).rects {
+                if is_rising {
+                    let screen_rect = Rectangle::new(
+                        screen_size.x - camera.to_screen_x(rect.x + rect.width),
+                        camera.to_screen_y(rect.y - shadow_width * shadow_y_length),
+                        (rect.width + shadow_width * shadow_x_length) * camera.zoom,
+                        (rect.height + shadow_width * shadow_y_length) * camera.zoom,
+                    );
+                    shd.draw_rectangle_rec(screen_rect, Color::new(255, 255, 255, 255));
+                } else {
+                    let screen_rect_width =
+                        (rect.width + shadow_width * shadow_x_length) * camera.zoom;
+                    let screen_rect = Rectangle::new(
+                        (screen_size.x - camera.to_screen_x(rect.x)) - screen_rect_width,
+                        camera.to_screen_y(rect.y),
+                        screen_rect_width,
+                        (rect.height + shadow_width * shadow_y_length) * camera.zoom,
+                    );
+                    shd.draw_rectangle_rec(screen_rect, Color::new(255, 255, 255, 255));
                 }
             }
         }